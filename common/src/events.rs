@@ -0,0 +1,69 @@
+//! Decoding of program events from transaction logs.
+//!
+//! Events emitted with `arch_program::emit!` are logged by the runtime as
+//! `Program data: <field> <field>`, each field base64 encoded.
+
+use arch_program::log::{decode_event, Event};
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+const LOG_DATA_PREFIX: &str = "Program data: ";
+
+/// The fields of a `sol_log_data` log line, or `None` if `line` is not one.
+pub fn parse_log_data(line: &str) -> Option<Vec<Vec<u8>>> {
+    line.strip_prefix(LOG_DATA_PREFIX)?
+        .split_whitespace()
+        .map(|field| STANDARD.decode(field).ok())
+        .collect()
+}
+
+/// The events of type `E` in `logs`, in order. Lines holding other events or
+/// plain messages are skipped.
+pub fn decode_events<E: Event>(logs: &[String]) -> Vec<E> {
+    logs.iter()
+        .filter_map(|line| parse_log_data(line))
+        .filter_map(|fields| decode_event(&fields))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use borsh::{BorshDeserialize, BorshSerialize};
+
+    #[derive(Debug, PartialEq, BorshSerialize, BorshDeserialize)]
+    struct Deposited {
+        amount: u64,
+    }
+
+    impl Event for Deposited {
+        const DISCRIMINATOR: [u8; 8] = *b"deposit_";
+    }
+
+    fn log_line(fields: &[&[u8]]) -> String {
+        let fields: Vec<String> = fields.iter().map(|field| STANDARD.encode(field)).collect();
+        format!("{}{}", LOG_DATA_PREFIX, fields.join(" "))
+    }
+
+    #[test]
+    fn test_decode_events() {
+        let deposit = borsh::to_vec(&Deposited { amount: 7 }).unwrap();
+        let logs = vec![
+            "Program log: Deposit successful".to_string(),
+            log_line(&[&Deposited::DISCRIMINATOR, &deposit]),
+            log_line(&[b"withdraw", &deposit]),
+            "Program data: not-base64!".to_string(),
+            log_line(&[&Deposited::DISCRIMINATOR, &deposit[..3]]),
+            log_line(&[
+                &Deposited::DISCRIMINATOR,
+                &borsh::to_vec(&Deposited { amount: 9 }).unwrap(),
+            ]),
+        ];
+
+        assert_eq!(
+            decode_events::<Deposited>(&logs),
+            vec![Deposited { amount: 7 }, Deposited { amount: 9 }]
+        );
+        assert_eq!(parse_log_data("Program data: "), Some(vec![]));
+        assert_eq!(parse_log_data("Program log: hello"), None);
+    }
+}
@@ -1,7 +1,7 @@
 use crate::constants;
 use anyhow::{anyhow, Result};
 use bip322::sign_message_bip322;
-use bitcoin::Txid;
+use bitcoin::hashes::Hash;
 use bitcoin::{
     absolute::LockTime,
     key::{Keypair, TapTweak, TweakedKeypair},
@@ -11,6 +11,7 @@ use bitcoin::{
     Network, OutPoint, ScriptBuf, Sequence, TapSighashType, Transaction, TxIn, Witness,
 };
 use bitcoin::{Address, Amount};
+use bitcoin::{BlockHash, Txid};
 use bitcoincore_rpc::{Auth, Client, RawTx, RpcApi};
 use indicatif::{ProgressBar, ProgressStyle};
 use serde::Deserialize;
@@ -28,7 +29,9 @@ use crate::constants::{
     TRANSACTION_NOT_FOUND_CODE,
 };
 use crate::models::CallerInfo;
-use crate::runtime_transaction::RuntimeTransaction;
+use crate::runtime_transaction::{
+    RuntimeTransaction, RUNTIME_TX_VERSION_COMPACT, RUNTIME_TX_VERSION_LEGACY,
+};
 use crate::signature::Signature;
 use arch_program::message::Message;
 use arch_program::pubkey::Pubkey;
@@ -129,7 +132,7 @@ pub fn with_secret_key_file(file_path: &str) -> Result<(UntweakedKeypair, Pubkey
         }
     };
     let keypair = UntweakedKeypair::from_secret_key(&secp, &secret_key);
-    let pubkey = Pubkey::from_slice(&XOnlyPublicKey::from_keypair(&keypair).0.serialize());
+    let pubkey = Pubkey::from(XOnlyPublicKey::from_keypair(&keypair).0.serialize());
     Ok((keypair, pubkey))
 }
 
@@ -150,8 +153,10 @@ pub fn extend_bytes_max_len() -> usize {
             version: 0,
             signatures: vec![Signature([0_u8; 64].to_vec())],
             message,
+            recent_blockhash: None,
         }
         .serialize()
+        .expect("extend bytes transaction should encode")
         .len()
 }
 
@@ -166,37 +171,16 @@ pub fn sign_and_send_instruction(
     let pubkeys = signers
         .iter()
         .map(|signer| {
-            let pubkey = Pubkey::from_slice(&XOnlyPublicKey::from_keypair(signer).0.serialize());
+            let pubkey = Pubkey::from(XOnlyPublicKey::from_keypair(signer).0.serialize());
             pubkey
         })
         .collect::<Vec<Pubkey>>();
 
     // Step 2: Create a message with the instruction and signers
-    let message = Message {
-        signers: pubkeys.clone(), // Clone for logging purposes
-        instructions: vec![instruction.clone()],
-    };
-
-    // Step 3: Hash the message and decode
-    let digest_slice = message.hash();
-
-    // Step 5: Sign the message with each signer's key
-    let signatures = signers
-        .iter()
-        .map(|signer| {
-            let signature = sign_message_bip322(signer, &digest_slice, BITCOIN_NETWORK).to_vec();
-            Signature(signature)
-        })
-        .collect::<Vec<Signature>>();
+    let message = Message::new(pubkeys.clone(), vec![instruction.clone()]);
 
-    //println!("Message signed by {} signers",signatures.len());
-
-    // Step 6: Create transaction parameters
-    let params = RuntimeTransaction {
-        version: 0,
-        signatures: signatures.clone(), // Clone for logging purposes
-        message: message.clone(),       // Clone for logging purposes
-    };
+    // Step 3: Sign the message with each signer's key
+    let params = sign_message(message, &signers)?;
 
     // Correcting the error by using the `unwrap_or` method instead of `unwrap_or_else`
     let url = rpc_url;
@@ -212,40 +196,116 @@ pub fn sign_and_send_instruction(
     //println!("Arch transaction ID: {:?}", result);
 
     // Step 8: Hash the instruction
-    let hashed_instruction = instruction.hash();
+    let hashed_instruction = instruction.legacy_hash()?;
 
     Ok((result, hashed_instruction))
 }
 
 use arch_program::instruction::Instruction;
 
+/// Signs `message` with `signers` as a version 0 transaction, falling back
+/// to version 1 (on the message's account-table form) when the message has
+/// too many accounts or instructions for the legacy layout. Each signature is
+/// made by the keypair matching the message signer at the same index.
+pub fn sign_message(message: Message, signers: &[Keypair]) -> Result<RuntimeTransaction> {
+    let (version, message) = match message.serialize() {
+        Ok(_) => (RUNTIME_TX_VERSION_LEGACY, message),
+        Err(_) => (RUNTIME_TX_VERSION_COMPACT, message.compile()?.decompile()),
+    };
+    sign_transaction(
+        RuntimeTransaction {
+            version,
+            signatures: vec![],
+            message,
+            recent_blockhash: None,
+        },
+        signers,
+    )
+}
+
+/// Signs `message` with `signers` as a version 1 transaction tied to
+/// `recent_blockhash`, so that it cannot be replayed once that block is no
+/// longer recent.
+pub fn sign_message_with_blockhash(
+    message: Message,
+    recent_blockhash: [u8; 32],
+    signers: &[Keypair],
+) -> Result<RuntimeTransaction> {
+    sign_transaction(
+        RuntimeTransaction {
+            version: RUNTIME_TX_VERSION_COMPACT,
+            signatures: vec![],
+            message: message.compile()?.decompile(),
+            recent_blockhash: Some(recent_blockhash),
+        },
+        signers,
+    )
+}
+
+fn sign_transaction(
+    mut transaction: RuntimeTransaction,
+    signers: &[Keypair],
+) -> Result<RuntimeTransaction> {
+    let digest_slice = transaction.signing_digest()?;
+    transaction.signatures = transaction
+        .message
+        .signers
+        .iter()
+        .map(|pubkey| {
+            let signer = signers
+                .iter()
+                .find(|signer| {
+                    XOnlyPublicKey::from_keypair(signer).0.serialize() == pubkey.serialize()
+                })
+                .ok_or_else(|| anyhow!("no keypair for signer {:x}", pubkey))?;
+            Ok(Signature(
+                sign_message_bip322(signer, &digest_slice, BITCOIN_NETWORK).to_vec(),
+            ))
+        })
+        .collect::<Result<Vec<Signature>>>()?;
+
+    Ok(transaction)
+}
+
 pub fn sign_and_send_transaction(
     instructions: Vec<Instruction>,
     signers: Vec<UntweakedKeypair>,
 ) -> Result<String> {
     let pubkeys = signers
         .iter()
-        .map(|signer| Pubkey::from_slice(&XOnlyPublicKey::from_keypair(signer).0.serialize()))
+        .map(|signer| Pubkey::from(XOnlyPublicKey::from_keypair(signer).0.serialize()))
         .collect::<Vec<Pubkey>>();
 
-    let message = Message {
-        signers: pubkeys,
-        instructions,
-    };
-    let digest_slice = message.hash();
-    let signatures = signers
-        .iter()
-        .map(|signer| {
-            Signature(sign_message_bip322(signer, &digest_slice, BITCOIN_NETWORK).to_vec())
-        })
-        .collect::<Vec<Signature>>();
+    sign_and_send_message(NODE1_ADDRESS, Message::new(pubkeys, instructions), &signers)
+}
 
-    let params = RuntimeTransaction {
-        version: 0,
-        signatures,
-        message,
-    };
-    let result = process_result(post_data(NODE1_ADDRESS, "send_transaction", params))
+/// Signs `message` with every signer and sends it to the node at `url`,
+/// returning the transaction id.
+pub fn sign_and_send_message(url: &str, message: Message, signers: &[Keypair]) -> Result<String> {
+    send_transaction(url, sign_message(message, signers)?)
+}
+
+/// Like [`sign_and_send_message`], but ties the transaction to the best
+/// block of the node at `url`, so that it cannot be replayed once that block
+/// is no longer recent.
+///
+/// The transaction is always version 1, which nodes are not yet confirmed to
+/// accept; until they are, [`sign_and_send_message`] remains the way to send
+/// messages. Once they do, this is the path to use.
+pub fn sign_and_send_message_with_blockhash(
+    url: &str,
+    message: Message,
+    signers: &[Keypair],
+) -> Result<String> {
+    let recent_blockhash = get_best_block_hash(url)?;
+    send_transaction(
+        url,
+        sign_message_with_blockhash(message, recent_blockhash, signers)?,
+    )
+}
+
+fn send_transaction(url: &str, params: RuntimeTransaction) -> Result<String> {
+    let result = process_result(post_data(url, "send_transaction", params))
         .expect("send_transaction should not fail")
         .as_str()
         .expect("cannot convert result to string")
@@ -255,8 +315,7 @@ pub fn sign_and_send_transaction(
 }
 
 pub fn deploy_program_txs(program_keypair: UntweakedKeypair, elf_path: &str) {
-    let program_pubkey =
-        Pubkey::from_slice(&XOnlyPublicKey::from_keypair(&program_keypair).0.serialize());
+    let program_pubkey = Pubkey::from(XOnlyPublicKey::from_keypair(&program_keypair).0.serialize());
 
     let elf = fs::read(elf_path).expect("elf path should be available");
 
@@ -273,23 +332,15 @@ pub fn deploy_program_txs(program_keypair: UntweakedKeypair, elf_path: &str) {
             bytes.extend(len.to_le_bytes());
             bytes.extend(chunk);
 
-            let message = Message {
-                signers: vec![program_pubkey],
-                instructions: vec![SystemInstruction::new_extend_bytes_instruction(
+            let message = Message::new(
+                vec![program_pubkey],
+                vec![SystemInstruction::new_extend_bytes_instruction(
                     bytes,
                     program_pubkey,
                 )],
-            };
-
-            let digest_slice = message.hash();
+            );
 
-            RuntimeTransaction {
-                version: 0,
-                signatures: vec![Signature(
-                    sign_message_bip322(&program_keypair, &digest_slice, BITCOIN_NETWORK).to_vec(),
-                )],
-                message,
-            }
+            sign_message(message, &[program_keypair]).expect("deployment message should encode")
         })
         .collect::<Vec<RuntimeTransaction>>();
 
@@ -412,6 +463,15 @@ pub fn get_program(url: &str, program_id: String) -> String {
         .to_string()
 }
 
+/// Returns the hash of the node's best block, in internal byte order
+pub fn get_best_block_hash(url: &str) -> Result<[u8; 32]> {
+    let best_block_hash = process_result(post(url, GET_BEST_BLOCK_HASH))?;
+    let best_block_hash = best_block_hash
+        .as_str()
+        .ok_or_else(|| anyhow!("cannot convert result to string"))?;
+    Ok(BlockHash::from_str(best_block_hash)?.to_byte_array())
+}
+
 /// Returns the best block
 fn _get_best_block() -> String {
     let best_block_hash = process_result(post(NODE1_ADDRESS, GET_BEST_BLOCK_HASH))
@@ -676,6 +736,7 @@ pub fn send_utxo_2(url: &str, pubkey: Pubkey) -> (Txid, u32) {
     (txid, vout)
 }
 
+/// Asks the node at `url` for the address anchoring `pubkey`.
 pub fn get_account_address(url: &str, pubkey: Pubkey) -> String {
     process_result(post_data(url, GET_ACCOUNT_ADDRESS, pubkey.serialize()))
         .expect("get_account_address should not fail")
@@ -1,6 +1,7 @@
 pub mod helper;
 pub mod models;
 pub mod constants;
+pub mod events;
 pub mod processed_transaction;
 pub mod runtime_transaction;
 pub mod signature;
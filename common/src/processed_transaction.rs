@@ -1,8 +1,13 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use borsh::{BorshDeserialize, BorshSerialize};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
+use arch_program::codec::{DecodeError, Reader};
+use arch_program::log::Event;
+use arch_program::program_error::{CustomError, ErrorCode, ErrorRegistry};
+
+use crate::events;
 use crate::runtime_transaction::RuntimeTransaction;
 
 #[derive(Clone, Debug, Deserialize, Serialize, BorshDeserialize, BorshSerialize, PartialEq)]
@@ -27,6 +32,30 @@ impl Status {
         }
         None
     }
+
+    /// The code of the custom program error a failed transaction reported,
+    /// parsed from a message like `"custom program error: 0x1f7"`.
+    pub fn custom_error_code(&self) -> Option<u32> {
+        let Status::Failed(message) = self else {
+            return None;
+        };
+        let message = message.to_ascii_lowercase();
+        let start = message.find("program error: 0x")? + "program error: 0x".len();
+        let digits = message[start..]
+            .find(|c: char| !c.is_ascii_hexdigit())
+            .map_or(&message[start..], |end| &message[start..start + end]);
+        u32::from_str_radix(digits, 16).ok()
+    }
+
+    /// The typed program error a failed transaction reported, if it is one of `E`'s.
+    pub fn program_error<E: CustomError>(&self) -> Option<E> {
+        E::from_code(self.custom_error_code()?)
+    }
+
+    /// Look up the program error a failed transaction reported in `registry`.
+    pub fn registered_error<'a>(&self, registry: &'a ErrorRegistry) -> Option<&'a ErrorCode> {
+        registry.get(self.custom_error_code()?)
+    }
 }
 #[derive(Clone, Debug, Serialize, Deserialize, BorshSerialize, BorshDeserialize)]
 pub struct ProcessedTransaction {
@@ -34,18 +63,37 @@ pub struct ProcessedTransaction {
     pub status: Status,
     pub bitcoin_txid: Option<String>,
     pub accounts_tags: Vec<String>,
+    /// Program logs, or `None` if the node did not return any. They are not
+    /// part of the binary encoding.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[borsh(skip)]
+    pub logs: Option<Vec<String>>,
 }
 
 impl ProcessedTransaction {
-    pub fn txid(&self) -> String {
-        self.runtime_transaction.txid()
+    /// The id nodes report for the transaction; see
+    /// [`RuntimeTransaction::legacy_txid`].
+    pub fn txid(&self) -> Result<String> {
+        self.runtime_transaction.legacy_txid()
+    }
+
+    /// The events of type `E` the transaction's programs emitted, in order.
+    /// Fails if the node returned no logs, which is not the same as the
+    /// transaction emitting no events.
+    pub fn events<E: Event>(&self) -> Result<Vec<E>> {
+        let logs = self
+            .logs
+            .as_ref()
+            .ok_or_else(|| anyhow!("the node returned no logs for this transaction"))?;
+        Ok(events::decode_events(logs))
     }
 
     pub fn to_vec(&self) -> Result<Vec<u8>> {
         let mut serialized = vec![];
 
-        serialized.extend((self.runtime_transaction.serialize().len() as u64).to_le_bytes());
-        serialized.extend(self.runtime_transaction.serialize());
+        let runtime_transaction = self.runtime_transaction.serialize()?;
+        serialized.extend((runtime_transaction.len() as u64).to_le_bytes());
+        serialized.extend(runtime_transaction);
 
         serialized.extend(match &self.bitcoin_txid {
             Some(txid) => {
@@ -75,43 +123,44 @@ impl ProcessedTransaction {
     }
 
     pub fn from_vec(data: &[u8]) -> Result<Self> {
-        let data_bytes = data[..8].try_into()?;
-        let runtime_transaction_len = u64::from_le_bytes(data_bytes) as usize;
-        let mut size = 8;
-        let runtime_transaction =
-            RuntimeTransaction::from_slice(&data[size..(size + runtime_transaction_len)])?;
-        size += runtime_transaction_len;
-
-        let bitcoin_txid = if data[size] == 1 {
-            size += 1;
-            let res = Some(hex::encode(&data[(size)..(size + 32)]));
-            size += 32;
-            res
-        } else {
-            size += 1;
-            None
+        let mut reader = Reader::new(data);
+
+        let runtime_transaction = RuntimeTransaction::from_slice(
+            reader.read_u64_prefixed("processed_transaction.runtime_transaction")?,
+        )?;
+
+        let bitcoin_txid = match reader.read_u8("processed_transaction.bitcoin_txid.tag")? {
+            1 => Some(hex::encode(
+                reader.read_bytes("processed_transaction.bitcoin_txid", 32)?,
+            )),
+            _ => None,
         };
 
-        let data_bytes = data[size..(size + 8)].try_into()?;
-        let accounts_tags_len = u64::from_le_bytes(data_bytes) as usize;
-        size += 8;
-        let mut accounts_tags = vec![];
+        let accounts_tags_len =
+            reader.read_u64("processed_transaction.accounts_tags.len")? as usize;
+        let mut accounts_tags = Vec::with_capacity(reader.capacity_for(accounts_tags_len, 32));
         for _ in 0..accounts_tags_len {
-            accounts_tags.push(hex::encode(&data[(size)..(size + 32)]));
-            size += 32;
+            accounts_tags.push(hex::encode(
+                reader.read_bytes("processed_transaction.accounts_tags", 32)?,
+            ));
         }
 
-        let status = match data[size] {
+        let status_offset = reader.offset();
+        let status = match reader.read_u8("processed_transaction.status")? {
             0 => Status::Processing,
             1 => Status::Processed,
             2 => {
-                let data_bytes = data[(size + 1)..(size + 9)].try_into()?;
-                let error_len = u64::from_le_bytes(data_bytes) as usize;
-                size += 9;
-                let error = String::from_utf8(data[size..(size + error_len)].to_vec())?;
-                Status::Failed(error)
+                let error = reader.read_u64_prefixed("processed_transaction.status.error")?;
+                Status::Failed(String::from_utf8(error.to_vec())?)
+            }
+            tag => {
+                return Err(DecodeError::InvalidTag {
+                    field: "processed_transaction.status",
+                    offset: status_offset,
+                    tag,
+                }
+                .into())
             }
-            _ => unreachable!("status doesn't exist"),
         };
 
         Ok(ProcessedTransaction {
@@ -119,6 +168,7 @@ impl ProcessedTransaction {
             status,
             bitcoin_txid,
             accounts_tags,
+            logs: None,
         })
     }
 }
@@ -133,7 +183,7 @@ use proptest::strategy::Just;
 proptest! {
     #[test]
     fn fuzz_serialize_deserialize_processed_transaction(
-        version in any::<u32>(),
+        version in Just(crate::runtime_transaction::RUNTIME_TX_VERSION_LEGACY),
         signatures in prop::collection::vec(prop::collection::vec(any::<u8>(), 64), 0..10),
         signers in prop::collection::vec(any::<[u8; 32]>(), 0..10),
         instructions in prop::collection::vec(prop::collection::vec(any::<u8>(), 0..100), 0..10),
@@ -166,6 +216,7 @@ proptest! {
             version,
             signatures,
             message,
+            recent_blockhash: None,
         };
 
         let processed_transaction = ProcessedTransaction {
@@ -173,6 +224,7 @@ proptest! {
             status: Status::Processing,
             bitcoin_txid: Some(bitcoin_txid.to_string()),
             accounts_tags: accounts_tags.iter().map(|s| s.to_string()).collect(),
+            logs: None,
         };
 
         let serialized = processed_transaction.to_vec().unwrap();
@@ -182,3 +234,83 @@ proptest! {
         assert_eq!(serialized, reserialized);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq, arch_program::program_error::CustomError)]
+    #[custom_error(offset = 501)]
+    enum TestTokenError {
+        SenderNotOwnedByProgram,
+        OwnerMismatch,
+        #[custom_error(msg = "Sender balance account holds a different mint")]
+        SenderMintMismatch,
+    }
+
+    #[test]
+    fn test_status_decodes_custom_program_error() {
+        let status = Status::Failed(
+            "Error processing Instruction 0: custom program error: 0x1f7".to_string(),
+        );
+        assert_eq!(status.custom_error_code(), Some(503));
+        assert_eq!(
+            status.program_error::<TestTokenError>(),
+            Some(TestTokenError::SenderMintMismatch)
+        );
+
+        let registry = ErrorRegistry::new().register::<TestTokenError>();
+        let error_code = status.registered_error(&registry).unwrap();
+        assert_eq!(error_code.name, "SenderMintMismatch");
+        assert_eq!(
+            error_code.message,
+            "Sender balance account holds a different mint"
+        );
+
+        let status = Status::Failed("Custom program error: 0x1F5".to_string());
+        assert_eq!(
+            status.program_error::<TestTokenError>(),
+            Some(TestTokenError::SenderNotOwnedByProgram)
+        );
+
+        assert_eq!(
+            Status::Failed("custom program error: 0x1".to_string())
+                .program_error::<TestTokenError>(),
+            None
+        );
+        assert_eq!(
+            Status::Failed("insufficient funds".to_string()).custom_error_code(),
+            None
+        );
+        assert_eq!(Status::Processed.custom_error_code(), None);
+    }
+
+    #[derive(Debug, PartialEq, BorshSerialize, BorshDeserialize)]
+    struct Deposited {
+        amount: u64,
+    }
+
+    impl Event for Deposited {
+        const DISCRIMINATOR: [u8; 8] = *b"deposit_";
+    }
+
+    #[test]
+    fn test_events_need_logs() {
+        let mut processed_transaction = ProcessedTransaction {
+            runtime_transaction: RuntimeTransaction {
+                version: crate::runtime_transaction::RUNTIME_TX_VERSION_LEGACY,
+                signatures: vec![],
+                message: Message::new(vec![], vec![]),
+                recent_blockhash: None,
+            },
+            status: Status::Processed,
+            bitcoin_txid: None,
+            accounts_tags: vec![],
+            logs: None,
+        };
+        assert!(processed_transaction.events::<Deposited>().is_err());
+
+        processed_transaction.logs = Some(vec!["Program log: done".to_string()]);
+        assert_eq!(processed_transaction.events::<Deposited>().unwrap(), vec![]);
+    }
+}
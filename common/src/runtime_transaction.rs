@@ -1,63 +1,313 @@
+use std::fmt;
+
 use anyhow::{anyhow, Result};
+use arch_program::codec::{self, EncodeError, Reader};
+use arch_program::hash::{self, Hash};
 use arch_program::message::Message;
+use arch_program::pubkey::Pubkey;
+use arch_program::sanitized::{SanitizeError, SanitizedMessage};
+use bip322::verify_message_bip322;
+use bitcoin::XOnlyPublicKey;
 use borsh::{BorshDeserialize, BorshSerialize};
 use serde::{Deserialize, Serialize};
-use sha256::digest;
 
+use crate::constants::BITCOIN_NETWORK;
 use crate::signature::Signature;
 
 pub const RUNTIME_TX_SIZE_LIMIT: usize = 10240;
 
-#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize, BorshDeserialize, BorshSerialize)]
+/// Version 0: `u8` signature count, message in its legacy layout.
+pub const RUNTIME_TX_VERSION_LEGACY: u32 = 0;
+
+/// Version 1: compact signature count, message in its compiled account-table
+/// form (see [`SanitizedMessage::serialize`]), followed by the optional
+/// [recent block hash](RuntimeTransaction::recent_blockhash). Only the
+/// indexes are carried, so the message must already be in table form, with
+/// merged privileges and signers in table order: sign
+/// `message.compile()?.decompile()`. Signers sign [`SanitizedMessage::hash`],
+/// which covers the block hash and unlike the legacy digest has no limit on
+/// the number of accounts; see [`RuntimeTransaction::signing_digest`].
+pub const RUNTIME_TX_VERSION_COMPACT: u32 = 1;
+
+/// Length of a BIP322 signature as carried in a runtime transaction
+pub const SIGNATURE_LEN: usize = 64;
+
+/// Reasons a [`RuntimeTransaction`]'s signatures do not check out
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum VerifyError {
+    /// There is not exactly one signature per message signer.
+    SignatureCount { signatures: usize, signers: usize },
+    /// The message could not be encoded to compute its hash.
+    Encode(EncodeError),
+    /// The message could not be compiled for a version 1 transaction.
+    Compile(SanitizeError),
+    /// A version 1 transaction's message is not in account-table form.
+    NotInTableForm,
+    /// A version 0 transaction carries a recent block hash.
+    LegacyBlockhash,
+    /// The transaction version has no signing digest.
+    UnsupportedVersion(u32),
+    /// The signature at `index` is not a valid signature of the message by
+    /// `signer`.
+    InvalidSignature { index: usize, signer: Pubkey },
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VerifyError::SignatureCount {
+                signatures,
+                signers,
+            } => write!(
+                f,
+                "transaction has {} signatures for {} signers",
+                signatures, signers
+            ),
+            VerifyError::Encode(error) => write!(f, "{}", error),
+            VerifyError::Compile(error) => write!(f, "{}", error),
+            VerifyError::NotInTableForm => write!(
+                f,
+                "version {} transactions need a message in account-table form",
+                RUNTIME_TX_VERSION_COMPACT
+            ),
+            VerifyError::LegacyBlockhash => write!(
+                f,
+                "version {} transactions cannot carry a recent block hash",
+                RUNTIME_TX_VERSION_LEGACY
+            ),
+            VerifyError::UnsupportedVersion(version) => {
+                write!(f, "unsupported runtime transaction version {}", version)
+            }
+            VerifyError::InvalidSignature { index, signer } => write!(
+                f,
+                "signature {} is not a valid signature by signer {:x}",
+                index, signer
+            ),
+        }
+    }
+}
+
+impl std::error::Error for VerifyError {}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub struct RuntimeTransaction {
     pub version: u32,
     pub signatures: Vec<Signature>,
     pub message: Message,
+    /// The block hash a version 1 transaction is tied to, so it cannot be
+    /// replayed once that block is no longer recent. Version 0 transactions
+    /// have none, and their encodings are unchanged.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recent_blockhash: Option<[u8; 32]>,
+}
+
+/// The borsh encoding only carries the block hash for versions other than 0,
+/// so version 0 transactions encode as they always have.
+impl BorshSerialize for RuntimeTransaction {
+    fn serialize<W: std::io::Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        BorshSerialize::serialize(&self.version, writer)?;
+        BorshSerialize::serialize(&self.signatures, writer)?;
+        BorshSerialize::serialize(&self.message, writer)?;
+        if self.version != RUNTIME_TX_VERSION_LEGACY {
+            BorshSerialize::serialize(&self.recent_blockhash, writer)?;
+        }
+        Ok(())
+    }
+}
+
+impl BorshDeserialize for RuntimeTransaction {
+    fn deserialize_reader<R: std::io::Read>(reader: &mut R) -> std::io::Result<Self> {
+        let version = u32::deserialize_reader(reader)?;
+        let signatures = Vec::deserialize_reader(reader)?;
+        let message = Message::deserialize_reader(reader)?;
+        let recent_blockhash = if version != RUNTIME_TX_VERSION_LEGACY {
+            Option::deserialize_reader(reader)?
+        } else {
+            None
+        };
+        Ok(Self {
+            version,
+            signatures,
+            message,
+            recent_blockhash,
+        })
+    }
 }
 
 impl RuntimeTransaction {
-    pub fn txid(&self) -> String {
-        digest(digest(self.serialize()))
+    pub fn txid(&self) -> Result<Hash> {
+        Ok(Hash::digest(&self.serialize()?))
     }
 
-    pub fn serialize(&self) -> Vec<u8> {
+    /// The id nodes report for the transaction, such as the result of
+    /// `send_transaction`: the [legacy hex digest](hash::legacy_digest) of
+    /// its encoding, as [`Message::legacy_hash`] is of a message. Compare
+    /// with this rather than [`RuntimeTransaction::txid`].
+    pub fn legacy_txid(&self) -> Result<String> {
+        Ok(hash::legacy_digest(&self.serialize()?))
+    }
+
+    /// Encode the transaction in the layout its `version` selects.
+    pub fn serialize(&self) -> Result<Vec<u8>> {
         let mut serilized = vec![];
 
         serilized.extend(self.version.to_le_bytes());
-        serilized.push(self.signatures.len() as u8);
+        match self.version {
+            RUNTIME_TX_VERSION_LEGACY => {
+                if self.recent_blockhash.is_some() {
+                    return Err(VerifyError::LegacyBlockhash.into());
+                }
+                serilized.push(codec::u8_len(
+                    "runtime_transaction.signatures",
+                    self.signatures.len(),
+                )?);
+                self.serialize_signatures(&mut serilized)?;
+                serilized.extend(self.message.serialize()?);
+            }
+            RUNTIME_TX_VERSION_COMPACT => {
+                let mut sanitized = compile_table_form(&self.message)?;
+                sanitized.recent_blockhash = self.recent_blockhash;
+                codec::write_compact_len(&mut serilized, self.signatures.len());
+                self.serialize_signatures(&mut serilized)?;
+                serilized.extend(sanitized.serialize());
+            }
+            version => {
+                return Err(anyhow!(
+                    "unsupported runtime transaction version {}",
+                    version
+                ))
+            }
+        }
+
+        Ok(serilized)
+    }
+
+    fn serialize_signatures(&self, serilized: &mut Vec<u8>) -> Result<()> {
         for signature in self.signatures.iter() {
+            if signature.0.len() != SIGNATURE_LEN {
+                return Err(anyhow!(
+                    "signature has length {}, expected {}",
+                    signature.0.len(),
+                    SIGNATURE_LEN
+                ));
+            }
             serilized.extend(&signature.serialize());
         }
-        serilized.extend(self.message.serialize());
-
-        serilized
+        Ok(())
     }
 
     pub fn from_slice(data: &[u8]) -> Result<Self> {
-        let mut size = 4;
-        let signatures_len = data[size] as usize;
-        size += 1;
-        let mut signatures = Vec::with_capacity(data[size] as usize);
+        let mut reader = Reader::new(data);
 
+        let version = reader.read_u32("runtime_transaction.version")?;
+        let signatures_len = match version {
+            RUNTIME_TX_VERSION_LEGACY => {
+                reader.read_u8("runtime_transaction.signatures.len")? as usize
+            }
+            RUNTIME_TX_VERSION_COMPACT => {
+                reader.read_compact_len("runtime_transaction.signatures.len")?
+            }
+            version => {
+                return Err(anyhow!(
+                    "unsupported runtime transaction version {}",
+                    version
+                ))
+            }
+        };
+        let mut signatures = Vec::with_capacity(reader.capacity_for(signatures_len, SIGNATURE_LEN));
         for _ in 0..signatures_len {
-            signatures.push(Signature::from_slice(&data[size..(size + 64)]));
-            size += 64;
+            signatures.push(Signature(
+                reader
+                    .read_bytes("runtime_transaction.signatures", SIGNATURE_LEN)?
+                    .to_vec(),
+            ));
         }
-        let message = Message::from_slice(&data[size..]);
+        let (message, recent_blockhash) = match version {
+            RUNTIME_TX_VERSION_LEGACY => (Message::decode(&mut reader)?, None),
+            _ => {
+                let sanitized = SanitizedMessage::decode(&mut reader)?;
+                sanitized.sanitize()?;
+                let message = sanitized.decompile();
+                // Only accept the one encoding `serialize` would produce, so
+                // the transaction id is unambiguous.
+                let mut canonical = message.compile()?;
+                canonical.recent_blockhash = sanitized.recent_blockhash;
+                if canonical != sanitized {
+                    return Err(anyhow!("account table is not in canonical order"));
+                }
+                (message, sanitized.recent_blockhash)
+            }
+        };
 
         Ok(Self {
-            version: u32::from_le_bytes(data[..4].try_into().unwrap()),
+            version,
             signatures,
             message,
+            recent_blockhash,
         })
     }
 
-    pub fn hash(&self) -> String {
-        digest(digest(self.serialize()))
+    pub fn hash(&self) -> Result<Hash> {
+        self.txid()
+    }
+
+    /// The bytes every signer of the transaction signs, whatever its
+    /// signatures: [`Message::legacy_hash`] for version 0 and the
+    /// [`SanitizedMessage::hash`] of the compiled message and recent block
+    /// hash for version 1.
+    pub fn signing_digest(&self) -> Result<Vec<u8>, VerifyError> {
+        match self.version {
+            RUNTIME_TX_VERSION_LEGACY => {
+                if self.recent_blockhash.is_some() {
+                    return Err(VerifyError::LegacyBlockhash);
+                }
+                self.message.legacy_hash().map_err(VerifyError::Encode)
+            }
+            RUNTIME_TX_VERSION_COMPACT => {
+                let mut sanitized = compile_table_form(&self.message)?;
+                sanitized.recent_blockhash = self.recent_blockhash;
+                Ok(sanitized.hash().to_bytes().to_vec())
+            }
+            version => Err(VerifyError::UnsupportedVersion(version)),
+        }
+    }
+
+    /// Check that every message signer signed the transaction's
+    /// [signing digest](Self::signing_digest), the signature at each index
+    /// belonging to the signer at the same index.
+    pub fn verify(&self) -> Result<(), VerifyError> {
+        let signers = &self.message.signers;
+        if self.signatures.len() != signers.len() {
+            return Err(VerifyError::SignatureCount {
+                signatures: self.signatures.len(),
+                signers: signers.len(),
+            });
+        }
+
+        let message_hash = self.signing_digest()?;
+        for (index, (signature, signer)) in self.signatures.iter().zip(signers).enumerate() {
+            let invalid = || VerifyError::InvalidSignature {
+                index,
+                signer: *signer,
+            };
+            // `verify_message_bip322` expects a valid x-only key.
+            XOnlyPublicKey::from_slice(signer.as_ref()).map_err(|_| invalid())?;
+            let signature: [u8; SIGNATURE_LEN] =
+                signature.0.as_slice().try_into().map_err(|_| invalid())?;
+            verify_message_bip322(
+                &message_hash,
+                signer.serialize(),
+                signature,
+                true,
+                BITCOIN_NETWORK,
+            )
+            .map_err(|_| invalid())?;
+        }
+        Ok(())
     }
 
     pub fn check_tx_size_limit(&self) -> Result<()> {
-        let serialized_tx = self.serialize();
+        let serialized_tx = self.serialize()?;
         if serialized_tx.len() > RUNTIME_TX_SIZE_LIMIT {
             Err(anyhow!(format!(
                 "runtime tx size exceeds RUNTIME_TX_SIZE_LIMIT {} {}",
@@ -70,17 +320,308 @@ impl RuntimeTransaction {
     }
 }
 
+/// Compile `message` for a version 1 transaction, which can only carry a
+/// message that is already in account-table form.
+fn compile_table_form(message: &Message) -> Result<SanitizedMessage, VerifyError> {
+    let sanitized = message.compile().map_err(VerifyError::Compile)?;
+    if sanitized.decompile() != *message {
+        return Err(VerifyError::NotInTableForm);
+    }
+    Ok(sanitized)
+}
+
 use arch_program::instruction::Instruction;
-use arch_program::pubkey::Pubkey;
 use proptest::prelude::*;
 
+#[test]
+fn test_versions() {
+    use arch_program::account::AccountMeta;
+
+    let payer = Pubkey::from([1; 32]);
+    let accounts: Vec<AccountMeta> = (0..300u16)
+        .map(|i| {
+            let mut key = [0; 32];
+            key[..2].copy_from_slice(&i.to_le_bytes());
+            key[31] = 2;
+            AccountMeta {
+                pubkey: Pubkey::from(key),
+                is_signer: false,
+                is_writable: i % 2 == 0,
+            }
+        })
+        .collect();
+    let message = Message::new(
+        vec![payer],
+        vec![Instruction {
+            program_id: Pubkey::system_program(),
+            accounts,
+            data: vec![],
+        }],
+    )
+    .compile()
+    .unwrap()
+    .decompile();
+    let mut transaction = RuntimeTransaction {
+        version: RUNTIME_TX_VERSION_LEGACY,
+        signatures: vec![Signature(vec![7; SIGNATURE_LEN])],
+        message,
+        recent_blockhash: None,
+    };
+
+    // More accounts than a legacy instruction can count.
+    assert!(transaction.serialize().is_err());
+    transaction.version = RUNTIME_TX_VERSION_COMPACT;
+    let serialized = transaction.serialize().unwrap();
+    assert_eq!(
+        RuntimeTransaction::from_slice(&serialized).unwrap(),
+        transaction
+    );
+
+    // Version 1 only carries messages in table form.
+    let mut unmerged = transaction.clone();
+    unmerged.message.instructions[0].accounts[0].is_writable = false;
+    unmerged.message.instructions[0]
+        .accounts
+        .push(transaction.message.instructions[0].accounts[0].clone());
+    assert!(unmerged.serialize().is_err());
+
+    transaction.version = 2;
+    assert!(transaction.serialize().is_err());
+    let mut serialized = serialized;
+    serialized[..4].copy_from_slice(&2u32.to_le_bytes());
+    assert!(RuntimeTransaction::from_slice(&serialized).is_err());
+}
+
+#[test]
+fn test_verify_reports_failing_signer() {
+    use bip322::sign_message_bip322;
+    use bitcoin::key::{Keypair, Secp256k1};
+
+    let secp = Secp256k1::new();
+    let keypairs: Vec<Keypair> = (1..=2u8)
+        .map(|i| Keypair::from_seckey_slice(&secp, &[i; 32]).unwrap())
+        .collect();
+    let message = Message {
+        signers: keypairs
+            .iter()
+            .map(|keypair| Pubkey::from(XOnlyPublicKey::from_keypair(keypair).0.serialize()))
+            .collect(),
+        instructions: vec![Instruction {
+            program_id: Pubkey::system_program(),
+            accounts: vec![],
+            data: vec![1, 2, 3],
+        }],
+    };
+    let hash = message.legacy_hash().unwrap();
+    let mut transaction = RuntimeTransaction {
+        version: 0,
+        signatures: keypairs
+            .iter()
+            .map(|keypair| Signature(sign_message_bip322(keypair, &hash, BITCOIN_NETWORK).to_vec()))
+            .collect(),
+        message,
+        recent_blockhash: None,
+    };
+    assert_eq!(transaction.verify(), Ok(()));
+
+    transaction.signatures.swap(0, 1);
+    assert_eq!(
+        transaction.verify(),
+        Err(VerifyError::InvalidSignature {
+            index: 0,
+            signer: transaction.message.signers[0],
+        })
+    );
+
+    transaction.signatures.swap(0, 1);
+    transaction.signatures[1].0[10] ^= 1;
+    assert_eq!(
+        transaction.verify(),
+        Err(VerifyError::InvalidSignature {
+            index: 1,
+            signer: transaction.message.signers[1],
+        })
+    );
+
+    transaction.signatures.pop();
+    assert_eq!(
+        transaction.verify(),
+        Err(VerifyError::SignatureCount {
+            signatures: 1,
+            signers: 2,
+        })
+    );
+}
+
+#[test]
+fn test_verify_version_1_with_many_accounts() {
+    use arch_program::account::AccountMeta;
+    use bip322::sign_message_bip322;
+    use bitcoin::key::{Keypair, Secp256k1};
+
+    let keypair = Keypair::from_seckey_slice(&Secp256k1::new(), &[1; 32]).unwrap();
+    let signer = Pubkey::from(XOnlyPublicKey::from_keypair(&keypair).0.serialize());
+    let accounts: Vec<AccountMeta> = (0..300u16)
+        .map(|i| {
+            let mut key = [0; 32];
+            key[..2].copy_from_slice(&i.to_le_bytes());
+            key[31] = 2;
+            AccountMeta {
+                pubkey: Pubkey::from(key),
+                is_signer: false,
+                is_writable: true,
+            }
+        })
+        .collect();
+    let message = Message::new(
+        vec![signer],
+        vec![Instruction {
+            program_id: Pubkey::system_program(),
+            accounts,
+            data: vec![],
+        }],
+    )
+    .compile()
+    .unwrap()
+    .decompile();
+
+    let mut transaction = RuntimeTransaction {
+        version: RUNTIME_TX_VERSION_LEGACY,
+        signatures: vec![],
+        message,
+        recent_blockhash: None,
+    };
+
+    // The legacy digest cannot count this many accounts.
+    assert!(matches!(
+        transaction.signing_digest(),
+        Err(VerifyError::Encode(EncodeError::LengthOverflow { .. }))
+    ));
+
+    transaction.version = RUNTIME_TX_VERSION_COMPACT;
+    let digest = transaction.signing_digest().unwrap();
+    assert_eq!(
+        digest,
+        transaction
+            .message
+            .compile()
+            .unwrap()
+            .hash()
+            .to_bytes()
+            .to_vec()
+    );
+    transaction.signatures = vec![Signature(
+        sign_message_bip322(&keypair, &digest, BITCOIN_NETWORK).to_vec(),
+    )];
+    let transaction = RuntimeTransaction::from_slice(&transaction.serialize().unwrap()).unwrap();
+    assert_eq!(transaction.verify(), Ok(()));
+
+    // A version 0 signature does not carry over.
+    let mut legacy_signed = transaction.clone();
+    legacy_signed.message.instructions[0].accounts.truncate(2);
+    let legacy_digest = RuntimeTransaction {
+        version: RUNTIME_TX_VERSION_LEGACY,
+        ..legacy_signed.clone()
+    }
+    .signing_digest()
+    .unwrap();
+    legacy_signed.signatures = vec![Signature(
+        sign_message_bip322(&keypair, &legacy_digest, BITCOIN_NETWORK).to_vec(),
+    )];
+    assert_eq!(
+        legacy_signed.verify(),
+        Err(VerifyError::InvalidSignature { index: 0, signer })
+    );
+    legacy_signed.version = RUNTIME_TX_VERSION_LEGACY;
+    assert_eq!(legacy_signed.verify(), Ok(()));
+}
+
+#[test]
+fn test_legacy_txid() {
+    let transaction = RuntimeTransaction {
+        version: RUNTIME_TX_VERSION_LEGACY,
+        signatures: vec![],
+        message: Message::new(vec![], vec![]),
+        recent_blockhash: None,
+    };
+    // The id the original `txid` returned for these seven zero bytes.
+    assert_eq!(
+        transaction.legacy_txid().unwrap(),
+        "5a80020b53cfcae6d2f311a51f81026ab7f2df34091562ef99a8b9f39466a6bf"
+    );
+    assert_ne!(
+        transaction.legacy_txid().unwrap(),
+        transaction.txid().unwrap().to_string()
+    );
+}
+
+#[test]
+fn test_recent_blockhash() {
+    let message = Message::new(
+        vec![Pubkey::from([1; 32])],
+        vec![Instruction {
+            program_id: Pubkey::system_program(),
+            accounts: vec![],
+            data: vec![1, 2, 3],
+        }],
+    );
+    let legacy = RuntimeTransaction {
+        version: RUNTIME_TX_VERSION_LEGACY,
+        signatures: vec![Signature(vec![7; SIGNATURE_LEN])],
+        message,
+        recent_blockhash: None,
+    };
+
+    // Version 0 encodings have no trace of the block hash.
+    let json = serde_json::to_value(&legacy).unwrap();
+    assert!(json.get("recent_blockhash").is_none());
+    assert_eq!(
+        serde_json::from_value::<RuntimeTransaction>(json).unwrap(),
+        legacy
+    );
+    let mut borsh_bytes = borsh::to_vec(&legacy.version).unwrap();
+    borsh_bytes.extend(borsh::to_vec(&legacy.signatures).unwrap());
+    borsh_bytes.extend(borsh::to_vec(&legacy.message).unwrap());
+    assert_eq!(borsh::to_vec(&legacy).unwrap(), borsh_bytes);
+
+    // ...and cannot carry one.
+    let mut with_blockhash = legacy.clone();
+    with_blockhash.recent_blockhash = Some([9; 32]);
+    assert!(with_blockhash.serialize().is_err());
+    assert_eq!(
+        with_blockhash.signing_digest(),
+        Err(VerifyError::LegacyBlockhash)
+    );
+
+    // Version 1 carries and signs it.
+    let compact = RuntimeTransaction {
+        version: RUNTIME_TX_VERSION_COMPACT,
+        ..legacy.clone()
+    };
+    with_blockhash.version = RUNTIME_TX_VERSION_COMPACT;
+    assert_ne!(
+        compact.signing_digest().unwrap(),
+        with_blockhash.signing_digest().unwrap()
+    );
+    assert_ne!(compact.txid().unwrap(), with_blockhash.txid().unwrap());
+    assert_eq!(
+        RuntimeTransaction::from_slice(&with_blockhash.serialize().unwrap()).unwrap(),
+        with_blockhash
+    );
+    assert_eq!(
+        borsh::from_slice::<RuntimeTransaction>(&borsh::to_vec(&with_blockhash).unwrap()).unwrap(),
+        with_blockhash
+    );
+}
+
 proptest! {
     #[test]
     fn fuzz_serialize_deserialize_runtime_transaction(
-        version in any::<u32>(),
+        version in RUNTIME_TX_VERSION_LEGACY..=RUNTIME_TX_VERSION_COMPACT,
         signatures in prop::collection::vec(prop::collection::vec(any::<u8>(), 64), 0..10),
         signers in prop::collection::vec(any::<[u8; 32]>(), 0..10),
-        instructions in prop::collection::vec(prop::collection::vec(any::<u8>(), 0..100), 0..10)
+        instructions in prop::collection::vec(prop::collection::vec(any::<u8>(), 0..100), 0..10),
+        recent_blockhash in prop::option::of(any::<[u8; 32]>()),
     ) {
         let signatures: Vec<Signature> = signatures.into_iter()
             .map(|sig_bytes| Signature::from_slice(&sig_bytes))
@@ -98,19 +639,104 @@ proptest! {
             })
             .collect();
 
-        let message = Message {
+        let mut message = Message {
             signers,
             instructions,
         };
+        let mut recent_blockhash = recent_blockhash;
+        if version == RUNTIME_TX_VERSION_COMPACT {
+            message = message.compile().unwrap().decompile();
+        } else {
+            recent_blockhash = None;
+        }
 
         let transaction = RuntimeTransaction {
             version,
             signatures,
             message,
+            recent_blockhash,
         };
 
-        let serialized = transaction.serialize();
+        let serialized = transaction.serialize().unwrap();
         let deserialized = RuntimeTransaction::from_slice(&serialized).unwrap();
         assert_eq!(transaction, deserialized);
+
+        for len in 0..serialized.len() {
+            assert!(RuntimeTransaction::from_slice(&serialized[..len]).is_err());
+        }
+    }
+
+    #[test]
+    fn fuzz_deserialize_arbitrary_runtime_transaction(data in prop::collection::vec(any::<u8>(), 0..1024)) {
+        let _ = RuntimeTransaction::from_slice(&data);
+    }
+
+    #[test]
+    fn fuzz_cross_version_runtime_transaction(
+        signers in prop::collection::vec(0..4u8, 0..4),
+        keys in prop::collection::vec(0..8u8, 0..16),
+        is_writable_flags in prop::collection::vec(any::<bool>(), 16),
+        instruction_lens in prop::collection::vec(0..4usize, 0..4),
+        recent_blockhash in prop::option::of(any::<[u8; 32]>()),
+    ) {
+        use arch_program::account::AccountMeta;
+
+        let signers: Vec<Pubkey> = signers.into_iter().map(|key| Pubkey::from([key; 32])).collect();
+        let metas: Vec<AccountMeta> = keys
+            .iter()
+            .zip(is_writable_flags.iter())
+            .map(|(key, is_writable)| {
+                let pubkey = Pubkey::from([*key; 32]);
+                AccountMeta {
+                    pubkey,
+                    is_signer: signers.contains(&pubkey),
+                    is_writable: *is_writable,
+                }
+            })
+            .collect();
+        let mut remaining = &metas[..];
+        let instructions: Vec<Instruction> = instruction_lens
+            .iter()
+            .map(|len| {
+                let (accounts, rest) = remaining.split_at((*len).min(remaining.len()));
+                remaining = rest;
+                Instruction {
+                    program_id: Pubkey::system_program(),
+                    accounts: accounts.to_vec(),
+                    data: vec![*len as u8; *len],
+                }
+            })
+            .collect();
+        let message = Message {
+            signers,
+            instructions,
+        }
+        .compile()
+        .unwrap()
+        .decompile();
+
+        let legacy = RuntimeTransaction {
+            version: RUNTIME_TX_VERSION_LEGACY,
+            signatures: vec![Signature(vec![3; SIGNATURE_LEN]); message.signers.len()],
+            message,
+            recent_blockhash: None,
+        };
+        let compact = RuntimeTransaction {
+            version: RUNTIME_TX_VERSION_COMPACT,
+            recent_blockhash,
+            ..legacy.clone()
+        };
+
+        // Both layouts carry the same transaction, with the same message
+        // hash, but they are distinct encodings with distinct ids.
+        let from_legacy = RuntimeTransaction::from_slice(&legacy.serialize().unwrap()).unwrap();
+        let from_compact = RuntimeTransaction::from_slice(&compact.serialize().unwrap()).unwrap();
+        prop_assert_eq!(&from_legacy.message, &from_compact.message);
+        prop_assert_eq!(&from_legacy.signatures, &from_compact.signatures);
+        prop_assert_eq!(
+            from_legacy.message.hash().unwrap(),
+            from_compact.message.hash().unwrap()
+        );
+        prop_assert_ne!(legacy.txid().unwrap(), compact.txid().unwrap());
     }
 }
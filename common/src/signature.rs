@@ -1,3 +1,6 @@
+use std::fmt;
+use std::str::FromStr;
+
 use borsh::{BorshDeserialize, BorshSerialize};
 use serde::{Deserialize, Serialize};
 
@@ -14,6 +17,21 @@ impl Signature {
     }
 }
 
+/// The signature bytes in lowercase hex.
+impl fmt::Display for Signature {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", hex::encode(&self.0))
+    }
+}
+
+impl FromStr for Signature {
+    type Err = hex::FromHexError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self(hex::decode(s)?))
+    }
+}
+
 use proptest::prelude::*;
 
 proptest! {
@@ -24,4 +42,12 @@ proptest! {
         let deserialized = Signature::from_slice(&serialized);
         assert_eq!(signature, deserialized);
     }
+
+    #[test]
+    fn fuzz_signature_string_roundtrip(signature_bytes in prop::collection::vec(any::<u8>(), 64)) {
+        let signature = Signature(signature_bytes);
+        let hex = signature.to_string();
+        assert_eq!(hex.len(), 128);
+        assert_eq!(hex.parse::<Signature>(), Ok(signature));
+    }
 }
@@ -1,4 +1,8 @@
-use arch_program::{input_to_sign::InputToSign, pubkey::Pubkey};
+use arch_program::{
+    codec::{self, DecodeError, EncodeError, Reader},
+    input_to_sign::InputToSign,
+    pubkey::Pubkey,
+};
 
 #[derive(Debug, Clone, Default, PartialEq)]
 pub struct TransactionToSign {
@@ -7,45 +11,49 @@ pub struct TransactionToSign {
 }
 
 impl TransactionToSign {
-    pub fn from_slice(data: &[u8]) -> Self {
-        let tx_bytes_len =
-            u32::from_le_bytes(data[0..4].try_into().expect("slice with incorrect length"));
-        let tx_bytes = data[4..(4 + tx_bytes_len as usize)].to_vec();
+    pub fn from_slice(data: &[u8]) -> Result<Self, DecodeError> {
+        let mut reader = Reader::new(data);
 
-        let mut size = tx_bytes_len as usize + 4;
-        let inputs_to_sign_length = u32::from_le_bytes(data[size..(size + 4)].try_into().unwrap());
-        size += 4;
-        let mut inputs_to_sign = vec![];
+        let tx_bytes_len = reader.read_u32("transaction_to_sign.tx_bytes.len")? as usize;
+        let tx_bytes = reader
+            .read_bytes("transaction_to_sign.tx_bytes", tx_bytes_len)?
+            .to_vec();
+
+        let inputs_to_sign_length =
+            reader.read_u32("transaction_to_sign.inputs_to_sign.len")? as usize;
+        let mut inputs_to_sign = Vec::with_capacity(reader.capacity_for(inputs_to_sign_length, 36));
         for _ in 0..inputs_to_sign_length {
-            let index = u32::from_le_bytes(
-                data[size..size + 4]
-                    .try_into()
-                    .expect("slice with incorrect length"),
-            );
-            size += 4;
-            let signer = Pubkey::from_slice(&data[size..size + 32]);
-            size += 32;
+            let index = reader.read_u32("input_to_sign.index")?;
+            let signer = Pubkey::from(reader.read_array("input_to_sign.signer")?);
             inputs_to_sign.push(InputToSign { index, signer });
         }
 
-        TransactionToSign {
+        Ok(TransactionToSign {
             tx_bytes,
             inputs_to_sign,
-        }
+        })
     }
 
-    pub fn serialise(&self) -> Vec<u8> {
+    pub fn serialise(&self) -> Result<Vec<u8>, EncodeError> {
         let mut serialized = vec![];
 
-        serialized.extend_from_slice(&(self.tx_bytes.len() as u32).to_le_bytes());
+        serialized.extend_from_slice(
+            &codec::u32_len("transaction_to_sign.tx_bytes", self.tx_bytes.len())?.to_le_bytes(),
+        );
         serialized.extend_from_slice(&self.tx_bytes);
-        serialized.extend_from_slice(&(self.inputs_to_sign.len() as u32).to_le_bytes());
+        serialized.extend_from_slice(
+            &codec::u32_len(
+                "transaction_to_sign.inputs_to_sign",
+                self.inputs_to_sign.len(),
+            )?
+            .to_le_bytes(),
+        );
         for input_to_sign in self.inputs_to_sign.iter() {
             serialized.extend_from_slice(&input_to_sign.index.to_le_bytes());
             serialized.extend_from_slice(&input_to_sign.signer.serialize());
         }
 
-        serialized
+        Ok(serialized)
     }
 }
 
@@ -664,11 +672,15 @@ mod tests {
         };
         assert_eq!(
             program_return.tx_bytes,
-            TransactionToSign::from_slice(&program_return.serialise()).tx_bytes
+            TransactionToSign::from_slice(&program_return.serialise().unwrap())
+                .unwrap()
+                .tx_bytes
         );
         assert_eq!(
             program_return.inputs_to_sign,
-            TransactionToSign::from_slice(&program_return.serialise()).inputs_to_sign
+            TransactionToSign::from_slice(&program_return.serialise().unwrap())
+                .unwrap()
+                .inputs_to_sign
         );
 
         // let program_return = TransactionToSign {
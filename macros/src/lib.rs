@@ -0,0 +1,526 @@
+//! Procedural macros for `arch_program`.
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{format_ident, quote};
+use syn::{
+    parse_macro_input, spanned::Spanned, Data, DeriveInput, Error, Expr, Fields, GenericParam,
+    Ident, Lifetime, LitInt, LitStr, Type,
+};
+
+/// Derives `arch_program::accounts::Accounts` for a struct of accounts.
+///
+/// Fields are taken from the instruction's accounts in declaration order.
+/// Each field is either a `&AccountInfo` or an `arch_program::account_data::Account<T>`,
+/// and may carry an `#[account(...)]` attribute with any of:
+///
+/// - `signer`: the account must have signed (`MissingRequiredSignature`).
+/// - `mut`: the account must be writable (`Immutable`).
+/// - `executable`: the account must be a program (`IncorrectProgramId`).
+/// - `owner = <expr>`: the account must be owned by `<expr>`, which may use
+///   `program_id` (`InvalidAccountOwner`). `Account<T>` fields always require
+///   the account to be owned by `program_id`.
+/// - `has_one = <field>`: the `Account<T>`'s data must have a `<field>` equal
+///   to the key of the sibling account named `<field>` (`IncorrectAuthority`).
+///
+/// Missing accounts fail with `NotEnoughAccountKeys`.
+#[proc_macro_derive(Accounts, attributes(account))]
+pub fn derive_accounts(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_accounts(input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+#[derive(Default)]
+struct Constraints {
+    signer: bool,
+    writable: bool,
+    executable: bool,
+    owner: Option<Expr>,
+    has_one: Vec<Ident>,
+}
+
+struct AccountField {
+    ident: Ident,
+    ty: Type,
+    is_typed: bool,
+    constraints: Constraints,
+}
+
+fn expand_accounts(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(Error::new(
+                    input.span(),
+                    "Accounts can only be derived for structs with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(Error::new(
+                input.span(),
+                "Accounts can only be derived for structs",
+            ))
+        }
+    };
+
+    let lifetimes: Vec<&Lifetime> = input
+        .generics
+        .params
+        .iter()
+        .filter_map(|param| match param {
+            GenericParam::Lifetime(param) => Some(&param.lifetime),
+            _ => None,
+        })
+        .collect();
+    let (info_lifetime, data_lifetime) = match lifetimes.as_slice() {
+        [lifetime] => (*lifetime, *lifetime),
+        [info_lifetime, data_lifetime] => (*info_lifetime, *data_lifetime),
+        _ => {
+            return Err(Error::new(
+                input.generics.span(),
+                "Accounts structs must have one or two lifetime parameters",
+            ))
+        }
+    };
+
+    let mut account_fields = Vec::with_capacity(fields.len());
+    for field in fields {
+        let mut constraints = Constraints::default();
+        for attr in field
+            .attrs
+            .iter()
+            .filter(|attr| attr.path().is_ident("account"))
+        {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("signer") {
+                    constraints.signer = true;
+                } else if meta.path.is_ident("mut") {
+                    constraints.writable = true;
+                } else if meta.path.is_ident("executable") {
+                    constraints.executable = true;
+                } else if meta.path.is_ident("owner") {
+                    constraints.owner = Some(meta.value()?.parse()?);
+                } else if meta.path.is_ident("has_one") {
+                    constraints.has_one.push(meta.value()?.parse()?);
+                } else {
+                    return Err(meta.error("unsupported account constraint"));
+                }
+                Ok(())
+            })?;
+        }
+        account_fields.push(AccountField {
+            ident: field.ident.clone().expect("named field"),
+            ty: field.ty.clone(),
+            is_typed: is_typed_account(&field.ty),
+            constraints,
+        });
+    }
+
+    let program_id = Ident::new("program_id", Span::call_site());
+    let info_ident = |ident: &Ident| format_ident!("__{}_info", ident);
+
+    let mut parse = Vec::with_capacity(account_fields.len());
+    for field in &account_fields {
+        let info = info_ident(&field.ident);
+        let constraints = &field.constraints;
+        parse.push(quote! {
+            let #info = ::arch_program::program::next_account_info(account_iter)?;
+        });
+        if constraints.signer {
+            parse.push(quote! {
+                if !#info.is_signer {
+                    return Err(::arch_program::program_error::ProgramError::MissingRequiredSignature);
+                }
+            });
+        }
+        if constraints.writable {
+            parse.push(quote! {
+                if !#info.is_writable {
+                    return Err(::arch_program::program_error::ProgramError::Immutable);
+                }
+            });
+        }
+        if constraints.executable {
+            parse.push(quote! {
+                if !#info.is_executable {
+                    return Err(::arch_program::program_error::ProgramError::IncorrectProgramId);
+                }
+            });
+        }
+        if let Some(owner) = &constraints.owner {
+            parse.push(quote! {
+                {
+                    let owner = &(#owner);
+                    let owner: &::arch_program::pubkey::Pubkey =
+                        ::core::borrow::Borrow::borrow(owner);
+                    if #info.owner != owner {
+                        return Err(::arch_program::program_error::ProgramError::InvalidAccountOwner);
+                    }
+                }
+            });
+        }
+        if field.is_typed {
+            parse.push(quote! {
+                if #info.owner != #program_id {
+                    return Err(::arch_program::program_error::ProgramError::InvalidAccountOwner);
+                }
+            });
+        }
+    }
+
+    let mut has_one = Vec::new();
+    for field in &account_fields {
+        for target in &field.constraints.has_one {
+            if !field.is_typed {
+                return Err(Error::new(
+                    target.span(),
+                    "has_one requires an Account<T> field",
+                ));
+            }
+            if !account_fields.iter().any(|other| other.ident == *target) {
+                return Err(Error::new(
+                    target.span(),
+                    format!("no account named `{}`", target),
+                ));
+            }
+            let ident = &field.ident;
+            let target_info = info_ident(target);
+            has_one.push(quote! {
+                if #ident.load()?.#target != *#target_info.key {
+                    return Err(::arch_program::program_error::ProgramError::IncorrectAuthority);
+                }
+            });
+        }
+    }
+
+    let wrap = account_fields.iter().map(|field| {
+        let ident = &field.ident;
+        let ty = &field.ty;
+        let info = info_ident(ident);
+        if field.is_typed {
+            quote! { let #ident: #ty = ::arch_program::account_data::Account::new(#info, #program_id); }
+        } else {
+            quote! { let #ident: #ty = #info; }
+        }
+    });
+    let idents = account_fields.iter().map(|field| &field.ident);
+
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::arch_program::accounts::Accounts<#info_lifetime, #data_lifetime>
+            for #name #ty_generics #where_clause
+        {
+            fn try_accounts(
+                #program_id: &#info_lifetime ::arch_program::pubkey::Pubkey,
+                accounts: &#info_lifetime [::arch_program::account::AccountInfo<#data_lifetime>],
+            ) -> Result<Self, ::arch_program::program_error::ProgramError> {
+                let _ = #program_id;
+                let account_iter = &mut accounts.iter();
+                #(#parse)*
+                #(#wrap)*
+                #(#has_one)*
+                Ok(Self { #(#idents),* })
+            }
+        }
+    })
+}
+
+/// Derives `arch_program::instruction::ProgramInstruction` for an enum of
+/// instructions.
+///
+/// Each variant is encoded as a one-byte tag followed by the borsh encoding of
+/// its fields in order. Tags count up from 0 like Rust discriminants, and a
+/// variant can pin its tag with `#[instruction(tag = N)]`, after which
+/// counting continues from `N + 1`. Variants can be reordered or added
+/// without changing the wire format as long as their tags are pinned.
+#[proc_macro_derive(ProgramInstruction, attributes(instruction))]
+pub fn derive_program_instruction(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_program_instruction(input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+fn expand_program_instruction(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let variants = match &input.data {
+        Data::Enum(data) => &data.variants,
+        _ => {
+            return Err(Error::new(
+                input.span(),
+                "ProgramInstruction can only be derived for enums",
+            ))
+        }
+    };
+
+    let mut tags: Vec<u8> = Vec::with_capacity(variants.len());
+    let mut discriminant = Vec::with_capacity(variants.len());
+    let mut pack = Vec::with_capacity(variants.len());
+    let mut unpack = Vec::with_capacity(variants.len());
+    let mut next_tag: u16 = 0;
+    for variant in variants {
+        let mut tag = next_tag;
+        for attr in variant
+            .attrs
+            .iter()
+            .filter(|attr| attr.path().is_ident("instruction"))
+        {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("tag") {
+                    tag = meta.value()?.parse::<LitInt>()?.base10_parse()?;
+                    Ok(())
+                } else {
+                    Err(meta.error("unsupported instruction attribute"))
+                }
+            })?;
+        }
+        let tag = u8::try_from(tag)
+            .map_err(|_| Error::new(variant.span(), "instruction tags must fit in a u8"))?;
+        if tags.contains(&tag) {
+            return Err(Error::new(
+                variant.span(),
+                format!("instruction tag {} is used more than once", tag),
+            ));
+        }
+        tags.push(tag);
+        next_tag = tag as u16 + 1;
+
+        let ident = &variant.ident;
+        let bindings: Vec<Ident> = (0..variant.fields.len())
+            .map(|index| format_ident!("__field{}", index))
+            .collect();
+        let deserialize = variant.fields.iter().map(|_| {
+            quote! {
+                ::arch_program::borsh::BorshDeserialize::deserialize_reader(&mut data)
+                    .map_err(|_| ::arch_program::program_error::ProgramError::InvalidInstructionData)?
+            }
+        });
+        let (pattern, construct) = match &variant.fields {
+            Fields::Unit => (quote! { Self::#ident }, quote! { Self::#ident }),
+            Fields::Unnamed(_) => (
+                quote! { Self::#ident(#(#bindings),*) },
+                quote! { Self::#ident(#(#deserialize),*) },
+            ),
+            Fields::Named(fields) => {
+                let names: Vec<&Ident> = fields
+                    .named
+                    .iter()
+                    .map(|field| field.ident.as_ref().expect("named field"))
+                    .collect();
+                (
+                    quote! { Self::#ident { #(#names: #bindings),* } },
+                    quote! { Self::#ident { #(#names: #deserialize),* } },
+                )
+            }
+        };
+
+        discriminant.push(quote! { #pattern => #tag, });
+        pack.push(quote! {
+            #pattern => {
+                #(
+                    ::arch_program::borsh::BorshSerialize::serialize(#bindings, &mut data)
+                        .expect("serializing into a Vec cannot fail");
+                )*
+            }
+        });
+        unpack.push(quote! { #tag => #construct, });
+    }
+
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::arch_program::instruction::ProgramInstruction
+            for #name #ty_generics #where_clause
+        {
+            #[allow(unused_variables)]
+            fn discriminant(&self) -> u8 {
+                match self {
+                    #(#discriminant)*
+                }
+            }
+
+            fn pack(&self) -> Vec<u8> {
+                let mut data = vec![self.discriminant()];
+                match self {
+                    #(#pack)*
+                }
+                data
+            }
+
+            fn unpack(
+                instruction_data: &[u8],
+            ) -> Result<Self, ::arch_program::program_error::ProgramError> {
+                let (tag, mut data) = instruction_data
+                    .split_first()
+                    .ok_or(::arch_program::program_error::ProgramError::InvalidInstructionData)?;
+                let instruction = match *tag {
+                    #(#unpack)*
+                    _ => {
+                        return Err(::arch_program::program_error::ProgramError::InvalidInstructionData)
+                    }
+                };
+                if !data.is_empty() {
+                    return Err(::arch_program::program_error::ProgramError::InvalidInstructionData);
+                }
+                Ok(instruction)
+            }
+        }
+    })
+}
+
+/// Derives `arch_program::program_error::CustomError` for an enum of unit
+/// variants, along with `Display` and `From<Self> for ProgramError`.
+///
+/// The enum may set the first code with `#[custom_error(offset = N)]`. Each
+/// variant takes the next code unless it pins one with
+/// `#[custom_error(code = N)]`, and may set its message with
+/// `#[custom_error(msg = "...")]`, which defaults to the variant's name.
+#[proc_macro_derive(CustomError, attributes(custom_error))]
+pub fn derive_custom_error(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_custom_error(input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+fn expand_custom_error(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let variants = match &input.data {
+        Data::Enum(data) => &data.variants,
+        _ => {
+            return Err(Error::new(
+                input.span(),
+                "CustomError can only be derived for enums",
+            ))
+        }
+    };
+
+    let mut next_code: u64 = 0;
+    for attr in input
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("custom_error"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("offset") {
+                next_code = meta.value()?.parse::<LitInt>()?.base10_parse()?;
+                Ok(())
+            } else {
+                Err(meta.error("unsupported custom_error attribute"))
+            }
+        })?;
+    }
+
+    let mut codes: Vec<u32> = Vec::with_capacity(variants.len());
+    let mut error_codes = Vec::with_capacity(variants.len());
+    let mut to_code = Vec::with_capacity(variants.len());
+    let mut from_code = Vec::with_capacity(variants.len());
+    let mut display = Vec::with_capacity(variants.len());
+    for variant in variants {
+        if !matches!(variant.fields, Fields::Unit) {
+            return Err(Error::new(
+                variant.span(),
+                "CustomError variants cannot have fields",
+            ));
+        }
+
+        let mut code = next_code;
+        let mut message = None;
+        for attr in variant
+            .attrs
+            .iter()
+            .filter(|attr| attr.path().is_ident("custom_error"))
+        {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("code") {
+                    code = meta.value()?.parse::<LitInt>()?.base10_parse()?;
+                } else if meta.path.is_ident("msg") {
+                    message = Some(meta.value()?.parse::<LitStr>()?.value());
+                } else {
+                    return Err(meta.error("unsupported custom_error attribute"));
+                }
+                Ok(())
+            })?;
+        }
+        let code = u32::try_from(code)
+            .map_err(|_| Error::new(variant.span(), "error codes must fit in a u32"))?;
+        if codes.contains(&code) {
+            return Err(Error::new(
+                variant.span(),
+                format!("error code {} is used more than once", code),
+            ));
+        }
+        codes.push(code);
+        next_code = code as u64 + 1;
+
+        let ident = &variant.ident;
+        let variant_name = ident.to_string();
+        let message = message.unwrap_or_else(|| variant_name.clone());
+        error_codes.push(quote! {
+            ::arch_program::program_error::ErrorCode {
+                code: #code,
+                name: #variant_name,
+                message: #message,
+            },
+        });
+        to_code.push(quote! { Self::#ident => #code, });
+        from_code.push(quote! { #code => Some(Self::#ident), });
+        display.push(quote! { Self::#ident => #message, });
+    }
+
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::arch_program::program_error::CustomError
+            for #name #ty_generics #where_clause
+        {
+            const ERROR_CODES: &'static [::arch_program::program_error::ErrorCode] =
+                &[#(#error_codes)*];
+
+            fn code(&self) -> u32 {
+                match self {
+                    #(#to_code)*
+                }
+            }
+
+            fn from_code(code: u32) -> Option<Self> {
+                match code {
+                    #(#from_code)*
+                    _ => None,
+                }
+            }
+        }
+
+        impl #impl_generics ::core::convert::From<#name #ty_generics>
+            for ::arch_program::program_error::ProgramError #where_clause
+        {
+            fn from(error: #name #ty_generics) -> Self {
+                ::arch_program::program_error::ProgramError::Custom(
+                    ::arch_program::program_error::CustomError::code(&error),
+                )
+            }
+        }
+
+        impl #impl_generics ::core::fmt::Display for #name #ty_generics #where_clause {
+            fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+                f.write_str(match self {
+                    #(#display)*
+                })
+            }
+        }
+    })
+}
+
+fn is_typed_account(ty: &Type) -> bool {
+    match ty {
+        Type::Path(path) => path
+            .path
+            .segments
+            .last()
+            .is_some_and(|segment| segment.ident == "Account"),
+        _ => false,
+    }
+}
@@ -1,4 +1,9 @@
-use crate::{msg, pubkey::Pubkey, utxo::UtxoMeta};
+use crate::{
+    codec::{DecodeError, Reader},
+    msg,
+    pubkey::Pubkey,
+    utxo::UtxoMeta,
+};
 
 use borsh::{BorshDeserialize, BorshSerialize};
 use serde::{Deserialize, Serialize};
@@ -34,12 +39,16 @@ impl AccountMeta {
         serilized
     }
 
-    pub fn from_slice(data: &[u8]) -> Self {
-        Self {
-            pubkey: Pubkey::from_slice(&data[..size_of::<Pubkey>()]),
-            is_signer: data[size_of::<Pubkey>()] != 0,
-            is_writable: data[size_of::<Pubkey>() + 1] != 0,
-        }
+    pub fn from_slice(data: &[u8]) -> Result<Self, DecodeError> {
+        Self::decode(&mut Reader::new(data))
+    }
+
+    pub fn decode(reader: &mut Reader) -> Result<Self, DecodeError> {
+        Ok(Self {
+            pubkey: Pubkey::from(reader.read_array("account_meta.pubkey")?),
+            is_signer: reader.read_bool("account_meta.is_signer")?,
+            is_writable: reader.read_bool("account_meta.is_writable")?,
+        })
     }
 }
 
@@ -99,7 +108,7 @@ impl<'a> AccountInfo<'a> {
         self.data.borrow().len()
     }
 
-    pub fn try_borrow_data(&self) -> Result<Ref<&mut [u8]>, ProgramError> {
+    pub fn try_borrow_data(&self) -> Result<Ref<'_, &mut [u8]>, ProgramError> {
         self.data
             .try_borrow()
             .map_err(|_| ProgramError::AccountBorrowFailed)
@@ -109,7 +118,7 @@ impl<'a> AccountInfo<'a> {
         self.data.borrow().is_empty()
     }
 
-    pub fn try_borrow_mut_data(&self) -> Result<RefMut<&'a mut [u8]>, ProgramError> {
+    pub fn try_borrow_mut_data(&self) -> Result<RefMut<'_, &'a mut [u8]>, ProgramError> {
         self.data
             .try_borrow_mut()
             .map_err(|_| ProgramError::AccountBorrowFailed)
@@ -234,7 +243,7 @@ mod tests {
             };
 
             let serialized = account_meta.serialize();
-            let deserialized = AccountMeta::from_slice(&serialized);
+            let deserialized = AccountMeta::from_slice(&serialized).unwrap();
 
             assert_eq!(account_meta, deserialized);
         }
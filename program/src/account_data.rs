@@ -0,0 +1,323 @@
+//! Typed access to program-owned account data.
+//!
+//! An [`Account<T>`] stores a `T` as an 8-byte [`AccountData::DISCRIMINATOR`]
+//! followed by the borsh encoding of the value. Loading checks that the
+//! account is owned by the program and that the discriminator matches, so an
+//! uninitialized account or an account of another type is an error rather than
+//! an empty value. Storing resizes the account data to fit, within the
+//! `MAX_PERMITTED_DATA_INCREASE` a single instruction may grow it by.
+
+use std::marker::PhantomData;
+
+use borsh::{BorshDeserialize, BorshSerialize};
+
+use crate::{account::AccountInfo, program_error::ProgramError, pubkey::Pubkey};
+
+/// Length of the discriminator prefixed to every [`Account`].
+pub const DISCRIMINATOR_LEN: usize = 8;
+
+/// A type that can be stored in an [`Account`].
+pub trait AccountData: BorshSerialize + BorshDeserialize {
+    /// Identifies the type in the first bytes of the account data. It must be
+    /// unique among the program's account types and must not be all zeroes,
+    /// which marks an uninitialized account.
+    const DISCRIMINATOR: [u8; DISCRIMINATOR_LEN];
+}
+
+/// An [`AccountInfo`] whose data holds a `T` owned by `program_id`.
+pub struct Account<'a, 'b, T: AccountData> {
+    info: &'a AccountInfo<'b>,
+    program_id: &'a Pubkey,
+    _data: PhantomData<T>,
+}
+
+impl<'a, 'b, T: AccountData> Account<'a, 'b, T> {
+    pub fn new(info: &'a AccountInfo<'b>, program_id: &'a Pubkey) -> Self {
+        Self {
+            info,
+            program_id,
+            _data: PhantomData,
+        }
+    }
+
+    pub fn info(&self) -> &'a AccountInfo<'b> {
+        self.info
+    }
+
+    pub fn key(&self) -> &'b Pubkey {
+        self.info.key
+    }
+
+    /// Whether the account data starts with `T`'s discriminator.
+    pub fn is_initialized(&self) -> Result<bool, ProgramError> {
+        Self::check_discriminator()?;
+        self.check_owner()?;
+        let data = self.info.try_borrow_data()?;
+        Ok(data.starts_with(&T::DISCRIMINATOR))
+    }
+
+    /// Deserialize the stored value.
+    ///
+    /// Fails with [`ProgramError::UninitializedAccount`] if nothing has been
+    /// stored yet, and with [`ProgramError::InvalidAccountData`] if the
+    /// account holds a different type or `T`'s discriminator is all zeroes.
+    pub fn load(&self) -> Result<T, ProgramError> {
+        Self::check_discriminator()?;
+        self.check_owner()?;
+        let data = self.info.try_borrow_data()?;
+        if !data.starts_with(&T::DISCRIMINATOR) {
+            return Err(if is_uninitialized(&data) {
+                ProgramError::UninitializedAccount
+            } else {
+                ProgramError::InvalidAccountData
+            });
+        }
+        T::deserialize(&mut &data[DISCRIMINATOR_LEN..])
+            .map_err(|e| ProgramError::BorshIoError(e.to_string()))
+    }
+
+    /// Like [`Account::load`], but also requires the account to be writable
+    /// so the value can be [stored](Account::store) back.
+    pub fn load_mut(&self) -> Result<T, ProgramError> {
+        self.check_writable()?;
+        self.load()
+    }
+
+    /// Serialize `value` into the account, resizing its data to fit.
+    ///
+    /// The account must be writable and either uninitialized or already hold
+    /// a `T`.
+    pub fn store(&self, value: &T) -> Result<(), ProgramError> {
+        Self::check_discriminator()?;
+        self.check_owner()?;
+        self.check_writable()?;
+        {
+            let data = self.info.try_borrow_data()?;
+            if !data.starts_with(&T::DISCRIMINATOR) && !is_uninitialized(&data) {
+                return Err(ProgramError::InvalidAccountData);
+            }
+        }
+
+        let mut serialized = T::DISCRIMINATOR.to_vec();
+        value
+            .serialize(&mut serialized)
+            .map_err(|e| ProgramError::BorshIoError(e.to_string()))?;
+
+        if serialized.len() != self.info.data_len() {
+            self.info.realloc(serialized.len(), false)?;
+        }
+        self.info
+            .try_borrow_mut_data()?
+            .copy_from_slice(&serialized);
+        Ok(())
+    }
+
+    /// An all-zero discriminator can't be told apart from an uninitialized
+    /// account, so a type declaring one is never loaded or stored.
+    fn check_discriminator() -> Result<(), ProgramError> {
+        if is_uninitialized(&T::DISCRIMINATOR) {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        Ok(())
+    }
+
+    fn check_owner(&self) -> Result<(), ProgramError> {
+        if self.info.owner != self.program_id {
+            return Err(ProgramError::InvalidAccountOwner);
+        }
+        Ok(())
+    }
+
+    fn check_writable(&self) -> Result<(), ProgramError> {
+        if !self.info.is_writable {
+            return Err(ProgramError::Immutable);
+        }
+        Ok(())
+    }
+}
+
+fn is_uninitialized(data: &[u8]) -> bool {
+    data.iter().take(DISCRIMINATOR_LEN).all(|byte| *byte == 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use borsh::{BorshDeserialize, BorshSerialize};
+
+    use super::{Account, AccountData};
+    use crate::{
+        account::{AccountInfo, AccountMeta},
+        entrypoint::{ProgramResult, MAX_PERMITTED_DATA_INCREASE},
+        instruction::{Instruction, InstructionError},
+        message::Message,
+        program::next_account_info,
+        program_error::ProgramError,
+        program_test::{ProgramTest, TestAccount},
+        pubkey::Pubkey,
+        utxo::UtxoMeta,
+    };
+
+    #[derive(BorshSerialize, BorshDeserialize)]
+    struct Counter {
+        count: u64,
+        history: Vec<u8>,
+    }
+
+    impl AccountData for Counter {
+        const DISCRIMINATOR: [u8; 8] = *b"counter_";
+    }
+
+    #[derive(BorshSerialize, BorshDeserialize)]
+    struct Other {
+        value: u64,
+    }
+
+    impl AccountData for Other {
+        const DISCRIMINATOR: [u8; 8] = *b"other___";
+    }
+
+    #[derive(BorshSerialize, BorshDeserialize)]
+    struct Zeroed {
+        value: u64,
+    }
+
+    impl AccountData for Zeroed {
+        const DISCRIMINATOR: [u8; 8] = [0; 8];
+    }
+
+    fn program_id() -> Pubkey {
+        Pubkey::from([5; 32])
+    }
+
+    /// Increments the counter, appending `instruction_data` to its history.
+    /// An empty instruction loads the account as [`Other`] instead.
+    fn process_instruction(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        instruction_data: &[u8],
+    ) -> ProgramResult {
+        let info = next_account_info(&mut accounts.iter())?;
+        if instruction_data.is_empty() {
+            Account::<Other>::new(info, program_id).load()?;
+            return Ok(());
+        }
+
+        let account = Account::<Counter>::new(info, program_id);
+        let mut counter = if account.is_initialized()? {
+            account.load_mut()?
+        } else {
+            Counter {
+                count: 0,
+                history: vec![],
+            }
+        };
+        counter.count += 1;
+        counter.history.extend_from_slice(instruction_data);
+        account.store(&counter)
+    }
+
+    fn message(account: Pubkey, data: Vec<u8>) -> Message {
+        Message {
+            signers: vec![],
+            instructions: vec![Instruction {
+                program_id: program_id(),
+                accounts: vec![AccountMeta {
+                    pubkey: account,
+                    is_signer: false,
+                    is_writable: true,
+                }],
+                data,
+            }],
+        }
+    }
+
+    fn setup(owner: Pubkey) -> (ProgramTest, Pubkey) {
+        let mut program_test = ProgramTest::new();
+        program_test.add_program(program_id(), process_instruction);
+        let account = Pubkey::from([6; 32]);
+        program_test.add_account(account, TestAccount::new(owner, vec![]));
+        (program_test, account)
+    }
+
+    #[test]
+    fn test_store_and_load() {
+        let (mut program_test, account) = setup(program_id());
+
+        program_test
+            .process_message(&message(account, vec![1, 2]))
+            .unwrap();
+        program_test
+            .process_message(&message(account, vec![3]))
+            .unwrap();
+
+        let data = &program_test.get_account(&account).unwrap().data;
+        assert_eq!(data[..8], Counter::DISCRIMINATOR);
+        let counter = Counter::try_from_slice(&data[8..]).unwrap();
+        assert_eq!(counter.count, 2);
+        assert_eq!(counter.history, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_load_wrong_type() {
+        let (mut program_test, account) = setup(program_id());
+        program_test
+            .process_message(&message(account, vec![1]))
+            .unwrap();
+
+        assert_eq!(
+            program_test.process_message(&message(account, vec![])),
+            Err(InstructionError::ProgramError(
+                ProgramError::InvalidAccountData
+            ))
+        );
+    }
+
+    #[test]
+    fn test_load_uninitialized() {
+        let (mut program_test, account) = setup(program_id());
+
+        assert_eq!(
+            program_test.process_message(&message(account, vec![])),
+            Err(InstructionError::ProgramError(
+                ProgramError::UninitializedAccount
+            ))
+        );
+    }
+
+    #[test]
+    fn test_zero_discriminator() {
+        let (key, owner) = (Pubkey::from([6; 32]), program_id());
+        let utxo = UtxoMeta::from([0; 32], 0);
+        let mut data = vec![0; 16];
+        let info = AccountInfo::new(&key, &mut data, &owner, &utxo, false, true, false);
+        let account = Account::<Zeroed>::new(&info, &owner);
+
+        assert_eq!(account.load().err(), Some(ProgramError::InvalidAccountData));
+        assert_eq!(
+            account.store(&Zeroed { value: 1 }),
+            Err(ProgramError::InvalidAccountData)
+        );
+    }
+
+    #[test]
+    fn test_wrong_owner() {
+        let (mut program_test, account) = setup(Pubkey::system_program());
+
+        assert_eq!(
+            program_test.process_message(&message(account, vec![1])),
+            Err(InstructionError::ProgramError(
+                ProgramError::InvalidAccountOwner
+            ))
+        );
+    }
+
+    #[test]
+    fn test_store_beyond_max_data_increase() {
+        let (mut program_test, account) = setup(program_id());
+
+        assert_eq!(
+            program_test.process_message(&message(account, vec![0; MAX_PERMITTED_DATA_INCREASE])),
+            Err(InstructionError::ProgramError(ProgramError::InvalidRealloc))
+        );
+    }
+}
@@ -0,0 +1,189 @@
+//! Parsing and validating an instruction's accounts.
+//!
+//! Instead of pulling accounts off with [`next_account_info`] and checking
+//! each one by hand, a program can describe them with a struct and
+//! `#[derive(Accounts)]`:
+//!
+//! ```ignore
+//! #[derive(Accounts)]
+//! struct Withdraw<'a, 'b> {
+//!     #[account(signer)]
+//!     authority: &'a AccountInfo<'b>,
+//!     #[account(mut, has_one = authority)]
+//!     vault: Account<'a, 'b, Vault>,
+//!     #[account(executable)]
+//!     token_program: &'a AccountInfo<'b>,
+//! }
+//!
+//! let accounts = Withdraw::try_accounts(program_id, accounts)?;
+//! ```
+//!
+//! See [`derive@Accounts`] for the supported constraints.
+//!
+//! [`next_account_info`]: crate::program::next_account_info
+
+use crate::{account::AccountInfo, program_error::ProgramError, pubkey::Pubkey};
+
+pub use arch_program_macros::Accounts;
+
+/// A set of accounts that can be parsed from an instruction's account list.
+pub trait Accounts<'a, 'b>: Sized {
+    /// Take the accounts in order, failing if any constraint is not met.
+    fn try_accounts(
+        program_id: &'a Pubkey,
+        accounts: &'a [AccountInfo<'b>],
+    ) -> Result<Self, ProgramError>;
+}
+
+#[cfg(test)]
+mod tests {
+    use borsh::{BorshDeserialize, BorshSerialize};
+
+    use super::Accounts;
+    use crate::{
+        account::AccountInfo,
+        account_data::{Account, AccountData},
+        program_error::ProgramError,
+        pubkey::Pubkey,
+        utxo::UtxoMeta,
+    };
+
+    #[derive(BorshSerialize, BorshDeserialize)]
+    struct Vault {
+        authority: Pubkey,
+    }
+
+    impl AccountData for Vault {
+        const DISCRIMINATOR: [u8; 8] = *b"vault___";
+    }
+
+    #[derive(Accounts)]
+    struct Withdraw<'a, 'b> {
+        #[account(signer)]
+        authority: &'a AccountInfo<'b>,
+        #[account(mut, has_one = authority)]
+        vault: Account<'a, 'b, Vault>,
+        #[account(executable, owner = Pubkey::system_program())]
+        token_program: &'a AccountInfo<'b>,
+    }
+
+    struct TestAccount {
+        key: Pubkey,
+        owner: Pubkey,
+        data: Vec<u8>,
+        is_signer: bool,
+        is_writable: bool,
+        is_executable: bool,
+    }
+
+    fn program_id() -> Pubkey {
+        Pubkey::from([4; 32])
+    }
+
+    fn accounts(authority: Pubkey) -> Vec<TestAccount> {
+        let mut vault_data = Vault::DISCRIMINATOR.to_vec();
+        vault_data.extend(borsh::to_vec(&Vault { authority }).unwrap());
+        vec![
+            TestAccount {
+                key: Pubkey::from([1; 32]),
+                owner: Pubkey::system_program(),
+                data: vec![],
+                is_signer: true,
+                is_writable: false,
+                is_executable: false,
+            },
+            TestAccount {
+                key: Pubkey::from([2; 32]),
+                owner: program_id(),
+                data: vault_data,
+                is_signer: false,
+                is_writable: true,
+                is_executable: false,
+            },
+            TestAccount {
+                key: Pubkey::from([3; 32]),
+                owner: Pubkey::system_program(),
+                data: vec![],
+                is_signer: false,
+                is_writable: false,
+                is_executable: true,
+            },
+        ]
+    }
+
+    fn try_withdraw(accounts: &mut [TestAccount]) -> Result<(), ProgramError> {
+        let utxo = UtxoMeta::from([0; 32], 0);
+        let infos: Vec<AccountInfo> = accounts
+            .iter_mut()
+            .map(|account| {
+                AccountInfo::new(
+                    &account.key,
+                    &mut account.data,
+                    &account.owner,
+                    &utxo,
+                    account.is_signer,
+                    account.is_writable,
+                    account.is_executable,
+                )
+            })
+            .collect();
+        let program_id = program_id();
+        let withdraw = Withdraw::try_accounts(&program_id, &infos)?;
+        assert_eq!(withdraw.authority.key, &Pubkey::from([1; 32]));
+        assert_eq!(withdraw.vault.key(), &Pubkey::from([2; 32]));
+        assert!(withdraw.token_program.is_executable);
+        Ok(())
+    }
+
+    #[test]
+    fn test_valid_accounts() {
+        assert_eq!(try_withdraw(&mut accounts(Pubkey::from([1; 32]))), Ok(()));
+    }
+
+    #[test]
+    fn test_constraint_errors() {
+        let mut missing = accounts(Pubkey::from([1; 32]));
+        missing.pop();
+        assert_eq!(
+            try_withdraw(&mut missing),
+            Err(ProgramError::NotEnoughAccountKeys)
+        );
+
+        let mut unsigned = accounts(Pubkey::from([1; 32]));
+        unsigned[0].is_signer = false;
+        assert_eq!(
+            try_withdraw(&mut unsigned),
+            Err(ProgramError::MissingRequiredSignature)
+        );
+
+        let mut readonly = accounts(Pubkey::from([1; 32]));
+        readonly[1].is_writable = false;
+        assert_eq!(try_withdraw(&mut readonly), Err(ProgramError::Immutable));
+
+        let mut foreign = accounts(Pubkey::from([1; 32]));
+        foreign[1].owner = Pubkey::system_program();
+        assert_eq!(
+            try_withdraw(&mut foreign),
+            Err(ProgramError::InvalidAccountOwner)
+        );
+
+        let mut not_executable = accounts(Pubkey::from([1; 32]));
+        not_executable[2].is_executable = false;
+        assert_eq!(
+            try_withdraw(&mut not_executable),
+            Err(ProgramError::IncorrectProgramId)
+        );
+
+        let mut wrong_program = accounts(Pubkey::from([1; 32]));
+        wrong_program[2].owner = program_id();
+        assert_eq!(
+            try_withdraw(&mut wrong_program),
+            Err(ProgramError::InvalidAccountOwner)
+        );
+
+        assert_eq!(
+            try_withdraw(&mut accounts(Pubkey::from([9; 32]))),
+            Err(ProgramError::IncorrectAuthority)
+        );
+    }
+}
@@ -1,9 +1,81 @@
 use borsh::{BorshDeserialize, BorshSerialize};
 
+use crate::program_error::ProgramError;
+
+/// Seconds in a day, for converting elapsed time into whole days.
+pub const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
 // Add the Clock struct definition
-#[derive(Debug, Clone, Copy, Default, BorshSerialize, BorshDeserialize)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
 pub struct Clock {
     pub slot: u64,
     pub epoch: u64,
     pub unix_timestamp: i64,
 }
+
+impl Clock {
+    /// Fetch the clock sysvar.
+    ///
+    /// In native builds this returns the clock of the installed
+    /// [`SyscallStubs`](crate::program_stubs::SyscallStubs), which by default
+    /// is the one set with [`set_native_clock`](crate::program_stubs::set_native_clock).
+    /// Like [`get_clock`](crate::program::get_clock), it doesn't check the
+    /// syscall's return value.
+    pub fn get() -> Result<Self, ProgramError> {
+        Ok(crate::program::get_clock())
+    }
+
+    /// Seconds elapsed between the unix timestamp `since` and this clock.
+    ///
+    /// Fails with [`ProgramError::ArithmeticOverflow`] if `since` is in the
+    /// future or the clock is before the unix epoch.
+    pub fn elapsed_seconds(&self, since: u64) -> Result<u64, ProgramError> {
+        u64::try_from(self.unix_timestamp)
+            .ok()
+            .and_then(|now| now.checked_sub(since))
+            .ok_or(ProgramError::ArithmeticOverflow)
+    }
+
+    /// Whole days elapsed between the unix timestamp `since` and this clock.
+    pub fn elapsed_days(&self, since: u64) -> Result<u64, ProgramError> {
+        Ok(self.elapsed_seconds(since)? / SECONDS_PER_DAY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Clock, SECONDS_PER_DAY};
+    use crate::{program_error::ProgramError, program_stubs};
+
+    #[test]
+    fn test_native_clock_advances() {
+        program_stubs::set_native_clock(Clock {
+            unix_timestamp: 1_000,
+            ..Clock::default()
+        });
+        program_stubs::advance_native_clock(2 * SECONDS_PER_DAY + 5);
+
+        let clock = Clock::get().unwrap();
+        assert_eq!(clock.unix_timestamp, 1_000 + 2 * SECONDS_PER_DAY as i64 + 5);
+        assert_eq!(clock.elapsed_seconds(1_000), Ok(2 * SECONDS_PER_DAY + 5));
+        assert_eq!(clock.elapsed_days(1_000), Ok(2));
+    }
+
+    #[test]
+    fn test_elapsed_rejects_future_timestamps() {
+        let clock = Clock {
+            unix_timestamp: 100,
+            ..Clock::default()
+        };
+        assert_eq!(
+            clock.elapsed_seconds(101),
+            Err(ProgramError::ArithmeticOverflow)
+        );
+
+        let clock = Clock {
+            unix_timestamp: -1,
+            ..Clock::default()
+        };
+        assert_eq!(clock.elapsed_days(0), Err(ProgramError::ArithmeticOverflow));
+    }
+}
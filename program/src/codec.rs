@@ -0,0 +1,294 @@
+//! Errors and helpers shared by the wire encoders and decoders.
+//!
+//! Every `from_slice` in this crate reads through a [`Reader`], so malformed
+//! or truncated input surfaces as a [`DecodeError`] naming the field and the
+//! byte offset where decoding stopped, rather than as a panic.
+
+use thiserror::Error;
+
+use crate::program_error::ProgramError;
+
+/// Reasons a byte slice could not be decoded into a wire type
+#[derive(Clone, Debug, Eq, Error, PartialEq)]
+pub enum DecodeError {
+    #[error("unexpected end of input reading `{field}` at offset {offset}: needed {needed} bytes, {remaining} remaining")]
+    UnexpectedEnd {
+        field: &'static str,
+        offset: usize,
+        needed: usize,
+        remaining: usize,
+    },
+    #[error("invalid tag {tag} for `{field}` at offset {offset}")]
+    InvalidTag {
+        field: &'static str,
+        offset: usize,
+        tag: u8,
+    },
+    #[error("malformed compact integer for `{field}` at offset {offset}")]
+    InvalidCompact { field: &'static str, offset: usize },
+}
+
+/// Reasons a wire type could not be encoded
+#[derive(Clone, Debug, Eq, Error, PartialEq)]
+pub enum EncodeError {
+    #[error("`{field}` has length {len}, which does not fit its length prefix (max {max})")]
+    LengthOverflow {
+        field: &'static str,
+        len: usize,
+        max: usize,
+    },
+}
+
+impl From<DecodeError> for ProgramError {
+    fn from(_: DecodeError) -> Self {
+        ProgramError::InvalidInstructionData
+    }
+}
+
+impl From<EncodeError> for ProgramError {
+    fn from(_: EncodeError) -> Self {
+        ProgramError::InvalidArgument
+    }
+}
+
+/// Reasons a string is not the 64 hex characters of a 32-byte value, such as
+/// a [`Pubkey`](crate::pubkey::Pubkey) or [`Hash`](crate::hash::Hash)
+#[derive(Clone, Debug, Eq, Error, PartialEq)]
+pub enum ParseHexError {
+    #[error("value is {0} hex characters, expected 64")]
+    InvalidLength(usize),
+    #[error("value is not valid hex")]
+    InvalidHex,
+}
+
+/// Parses the 64 hex characters of a 32-byte value.
+pub fn parse_hex32(s: &str) -> Result<[u8; 32], ParseHexError> {
+    if s.len() != 64 {
+        return Err(ParseHexError::InvalidLength(s.len()));
+    }
+    let mut bytes = [0; 32];
+    hex::decode_to_slice(s, &mut bytes).map_err(|_| ParseHexError::InvalidHex)?;
+    Ok(bytes)
+}
+
+/// Encodes `len` as a `u8` length prefix, rejecting lengths that would be truncated.
+pub fn u8_len(field: &'static str, len: usize) -> Result<u8, EncodeError> {
+    u8::try_from(len).map_err(|_| EncodeError::LengthOverflow {
+        field,
+        len,
+        max: u8::MAX as usize,
+    })
+}
+
+/// Encodes `len` as a `u32` length prefix, rejecting lengths that would be truncated.
+pub fn u32_len(field: &'static str, len: usize) -> Result<u32, EncodeError> {
+    u32::try_from(len).map_err(|_| EncodeError::LengthOverflow {
+        field,
+        len,
+        max: u32::MAX as usize,
+    })
+}
+
+/// Appends `value` as a compact integer: little-endian groups of seven bits,
+/// the high bit of each byte set when another byte follows.
+pub fn write_compact(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+/// Appends `len` as a compact length prefix.
+pub fn write_compact_len(buf: &mut Vec<u8>, len: usize) {
+    write_compact(buf, len as u64);
+}
+
+/// Bounds-checked cursor over an encoded byte slice.
+#[derive(Clone, Debug)]
+pub struct Reader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, offset: 0 }
+    }
+
+    /// Number of bytes consumed so far.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Number of bytes left to read.
+    pub fn remaining(&self) -> usize {
+        self.data.len() - self.offset
+    }
+
+    pub fn read_bytes(&mut self, field: &'static str, len: usize) -> Result<&'a [u8], DecodeError> {
+        if len > self.remaining() {
+            return Err(DecodeError::UnexpectedEnd {
+                field,
+                offset: self.offset,
+                needed: len,
+                remaining: self.remaining(),
+            });
+        }
+        let bytes = &self.data[self.offset..self.offset + len];
+        self.offset += len;
+        Ok(bytes)
+    }
+
+    pub fn read_array<const N: usize>(
+        &mut self,
+        field: &'static str,
+    ) -> Result<[u8; N], DecodeError> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.read_bytes(field, N)?);
+        Ok(array)
+    }
+
+    pub fn read_u8(&mut self, field: &'static str) -> Result<u8, DecodeError> {
+        Ok(self.read_array::<1>(field)?[0])
+    }
+
+    pub fn read_bool(&mut self, field: &'static str) -> Result<bool, DecodeError> {
+        Ok(self.read_u8(field)? != 0)
+    }
+
+    pub fn read_u32(&mut self, field: &'static str) -> Result<u32, DecodeError> {
+        Ok(u32::from_le_bytes(self.read_array(field)?))
+    }
+
+    pub fn read_u64(&mut self, field: &'static str) -> Result<u64, DecodeError> {
+        Ok(u64::from_le_bytes(self.read_array(field)?))
+    }
+
+    /// Reads a `u64` length prefix followed by that many bytes.
+    pub fn read_u64_prefixed(&mut self, field: &'static str) -> Result<&'a [u8], DecodeError> {
+        let len = self.read_u64(field)?;
+        self.read_bytes(field, usize::try_from(len).unwrap_or(usize::MAX))
+    }
+
+    /// Reads a compact integer written by [`write_compact`], rejecting
+    /// encodings that overflow a `u64` or carry redundant trailing bytes, so
+    /// every value has exactly one encoding.
+    pub fn read_compact(&mut self, field: &'static str) -> Result<u64, DecodeError> {
+        let start = self.offset;
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.read_u8(field)?;
+            let bits = u64::from(byte & 0x7f);
+            if (bits << shift) >> shift != bits || (byte == 0 && shift > 0) {
+                return Err(DecodeError::InvalidCompact {
+                    field,
+                    offset: start,
+                });
+            }
+            value |= bits << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(DecodeError::InvalidCompact {
+            field,
+            offset: start,
+        })
+    }
+
+    /// Reads a compact length prefix.
+    pub fn read_compact_len(&mut self, field: &'static str) -> Result<usize, DecodeError> {
+        let start = self.offset;
+        let len = self.read_compact(field)?;
+        usize::try_from(len).map_err(|_| DecodeError::InvalidCompact {
+            field,
+            offset: start,
+        })
+    }
+
+    /// Reads a compact integer that must fit a `u16`.
+    pub fn read_compact_u16(&mut self, field: &'static str) -> Result<u16, DecodeError> {
+        let start = self.offset;
+        let value = self.read_compact(field)?;
+        u16::try_from(value).map_err(|_| DecodeError::InvalidCompact {
+            field,
+            offset: start,
+        })
+    }
+
+    /// Capacity to reserve for `count` entries of `entry_size` bytes each,
+    /// capped by what the remaining input could actually hold so a hostile
+    /// length prefix can't force a huge allocation.
+    pub fn capacity_for(&self, count: usize, entry_size: usize) -> usize {
+        count.min(self.remaining() / entry_size.max(1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{write_compact, DecodeError, Reader};
+
+    use proptest::prelude::*;
+
+    #[test]
+    fn test_reader_reports_field_and_offset() {
+        let mut reader = Reader::new(&[1, 2, 3]);
+        assert_eq!(reader.read_u8("tag"), Ok(1));
+        assert_eq!(
+            reader.read_u32("len"),
+            Err(DecodeError::UnexpectedEnd {
+                field: "len",
+                offset: 1,
+                needed: 4,
+                remaining: 2,
+            })
+        );
+        assert_eq!(reader.offset(), 1);
+    }
+
+    #[test]
+    fn test_compact_encoding() {
+        let encode = |value| {
+            let mut buf = vec![];
+            write_compact(&mut buf, value);
+            buf
+        };
+        assert_eq!(encode(0), [0]);
+        assert_eq!(encode(0x7f), [0x7f]);
+        assert_eq!(encode(0x80), [0x80, 0x01]);
+        assert_eq!(encode(300), [0xac, 0x02]);
+        assert_eq!(encode(u64::MAX).len(), 10);
+
+        let invalid = |data: &[u8]| Reader::new(data).read_compact("len");
+        // Redundant trailing zero group.
+        assert_eq!(
+            invalid(&[0x80, 0x00]),
+            Err(DecodeError::InvalidCompact {
+                field: "len",
+                offset: 0
+            })
+        );
+        // More than 64 bits.
+        assert!(invalid(&[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x02]).is_err());
+        assert!(invalid(&[0xff; 11]).is_err());
+        assert!(matches!(
+            invalid(&[0x80]),
+            Err(DecodeError::UnexpectedEnd { .. })
+        ));
+        assert!(Reader::new(&encode(1 << 16))
+            .read_compact_u16("index")
+            .is_err());
+    }
+
+    proptest! {
+        #[test]
+        fn fuzz_compact_roundtrip(value in any::<u64>()) {
+            let mut buf = vec![];
+            write_compact(&mut buf, value);
+            let mut reader = Reader::new(&buf);
+            prop_assert_eq!(reader.read_compact("value"), Ok(value));
+            prop_assert_eq!(reader.remaining(), 0);
+        }
+    }
+}
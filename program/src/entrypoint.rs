@@ -13,6 +13,10 @@ use alloc::vec::Vec;
 pub const HEAP_START_ADDRESS: u64 = 0x300000000;
 /// Length of the heap memory region used for program heap.
 pub const HEAP_LENGTH: usize = 32 * 1024;
+/// Largest heap a program can request from the runtime.
+pub const MAX_HEAP_LENGTH: usize = 256 * 1024;
+/// Heap requests are made in multiples of this many bytes.
+pub const HEAP_LENGTH_GRANULARITY: usize = 1024;
 /// Maximum permitted size of account data (10 MiB).
 pub const MAX_PERMITTED_DATA_LENGTH: usize = 10 * 1024 * 1024;
 /// Maximum number of bytes a program may add to an account during a single realloc
@@ -37,11 +41,41 @@ pub const SUCCESS: u64 = 0;
 pub type ProcessInstruction =
     fn(program_id: &Pubkey, accounts: &[AccountInfo], instruction_data: &[u8]) -> ProgramResult;
 
+/// Checks at compile time that `len` is a heap size the runtime can provide:
+/// a multiple of [`HEAP_LENGTH_GRANULARITY`] between [`HEAP_LENGTH`] and
+/// [`MAX_HEAP_LENGTH`].
+pub const fn checked_heap_length(len: usize) -> usize {
+    assert!(
+        len >= HEAP_LENGTH && len <= MAX_HEAP_LENGTH && len & (HEAP_LENGTH_GRANULARITY - 1) == 0,
+        "heap size must be a multiple of 1 KiB between 32 KiB and 256 KiB"
+    );
+    len
+}
+
+/// The allocator installed by [`entrypoint!`](crate::entrypoint!): a
+/// [`FreeListAllocator`] with the `free-list-allocator` feature, and a
+/// [`BumpAllocator`] otherwise.
+#[cfg(not(feature = "free-list-allocator"))]
+pub type HeapAllocator = BumpAllocator;
+#[cfg(feature = "free-list-allocator")]
+pub type HeapAllocator = FreeListAllocator;
+
+fn log_allocation_failure() {
+    // `msg!` with a literal does not allocate.
+    crate::msg!("Error: memory allocation failed, out of heap space");
+}
+
 /// The bump allocator used as the default rust heap when running programs.
 pub struct BumpAllocator {
     pub start: usize,
     pub len: usize,
 }
+
+impl BumpAllocator {
+    pub const fn new(start: usize, len: usize) -> Self {
+        Self { start, len }
+    }
+}
 /// Integer arithmetic in this global allocator implementation is safe when
 /// operating on the prescribed `HEAP_START_ADDRESS` and `HEAP_LENGTH`. Any
 /// other use may overflow and is thus unsupported and at one's own risk.
@@ -59,6 +93,7 @@ unsafe impl std::alloc::GlobalAlloc for BumpAllocator {
         pos = pos.saturating_sub(layout.size());
         pos &= !(layout.align().wrapping_sub(1));
         if pos < self.start + size_of::<*mut u8>() {
+            log_allocation_failure();
             return null_mut();
         }
         *pos_ptr = pos;
@@ -70,6 +105,148 @@ unsafe impl std::alloc::GlobalAlloc for BumpAllocator {
     }
 }
 
+/// An allocator that reuses freed blocks, for programs that allocate and drop
+/// more than fits in the heap over one instruction.
+///
+/// Blocks are carved upward from the start of the heap and kept on a free list
+/// when released. Allocations take the first free block that fits, splitting
+/// off the rest. A freed block at the top of the heap is returned to the
+/// unallocated space instead. Neighbouring free blocks are not merged.
+pub struct FreeListAllocator {
+    pub start: usize,
+    pub len: usize,
+}
+
+/// Every block starts aligned to, and is sized in multiples of, this.
+const BLOCK_ALIGN: usize = 16;
+/// Heap bookkeeping, kept in the first bytes of the heap.
+#[repr(C)]
+struct FreeListState {
+    top: usize,
+    free: usize,
+}
+/// Kept at the start of a free block.
+#[repr(C)]
+struct FreeBlock {
+    size: usize,
+    next: usize,
+}
+/// Kept just before the memory handed out.
+#[repr(C)]
+struct BlockHeader {
+    size: usize,
+    block: usize,
+}
+
+const _: () = assert!(size_of::<FreeListState>() <= BLOCK_ALIGN);
+const _: () = assert!(size_of::<FreeBlock>() <= BLOCK_ALIGN);
+const _: () = assert!(size_of::<BlockHeader>() <= BLOCK_ALIGN);
+
+impl FreeListAllocator {
+    pub const fn new(start: usize, len: usize) -> Self {
+        Self { start, len }
+    }
+
+    /// # Safety
+    ///
+    /// The heap region must be valid, exclusively used by this allocator and
+    /// start zeroed.
+    unsafe fn state(&self) -> *mut FreeListState {
+        let state = self.start as *mut FreeListState;
+        if (*state).top == 0 {
+            (*state).top = self.start + BLOCK_ALIGN;
+        }
+        state
+    }
+
+    /// Take a free block of at least `size` bytes off the free list,
+    /// returning it and its size.
+    unsafe fn take_free_block(
+        &self,
+        state: &mut FreeListState,
+        size: usize,
+    ) -> Option<(usize, usize)> {
+        let mut link = &mut state.free as *mut usize;
+        while *link != 0 {
+            let block = *link;
+            let free_block = &mut *(block as *mut FreeBlock);
+            if free_block.size >= size {
+                let remainder = free_block.size - size;
+                if remainder >= BLOCK_ALIGN {
+                    let rest = block + size;
+                    *(rest as *mut FreeBlock) = FreeBlock {
+                        size: remainder,
+                        next: free_block.next,
+                    };
+                    *link = rest;
+                    return Some((block, size));
+                }
+                *link = free_block.next;
+                return Some((block, free_block.size));
+            }
+            link = &mut free_block.next;
+        }
+        None
+    }
+}
+
+/// Integer arithmetic in this global allocator implementation is safe when
+/// operating on the prescribed `HEAP_START_ADDRESS` and heap length. Any
+/// other use may overflow and is thus unsupported and at one's own risk.
+#[allow(clippy::arithmetic_side_effects)]
+unsafe impl std::alloc::GlobalAlloc for FreeListAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        // The block holds the header, padding up to `align` and the rounded
+        // up allocation.
+        let align = layout.align().max(BLOCK_ALIGN);
+        let size = match layout
+            .size()
+            .checked_add(BLOCK_ALIGN - 1)
+            .map(|size| size & !(BLOCK_ALIGN - 1))
+            .and_then(|size| size.checked_add(align))
+        {
+            Some(size) => size,
+            None => {
+                log_allocation_failure();
+                return null_mut();
+            }
+        };
+
+        let state = &mut *self.state();
+        let (block, size) = match self.take_free_block(state, size) {
+            Some(free_block) => free_block,
+            None => {
+                if self.start + self.len - state.top < size {
+                    log_allocation_failure();
+                    return null_mut();
+                }
+                let block = state.top;
+                state.top += size;
+                (block, size)
+            }
+        };
+
+        let ptr = (block + BLOCK_ALIGN + align - 1) & !(align - 1);
+        *((ptr - BLOCK_ALIGN) as *mut BlockHeader) = BlockHeader { size, block };
+        ptr as *mut u8
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, _: Layout) {
+        let header = &*((ptr as usize - BLOCK_ALIGN) as *const BlockHeader);
+        let (block, size) = (header.block, header.size);
+        let state = &mut *self.state();
+        if block + size == state.top {
+            state.top = block;
+        } else {
+            *(block as *mut FreeBlock) = FreeBlock {
+                size,
+                next: state.free,
+            };
+            state.free = block;
+        }
+    }
+}
+
 #[allow(clippy::missing_safety_doc)]
 pub unsafe fn deserialize<'a>(input: *mut u8) -> (&'a Pubkey, Vec<AccountInfo<'a>>, &'a [u8]) {
     let mut offset: usize = 0;
@@ -152,13 +329,27 @@ pub unsafe fn deserialize<'a>(input: *mut u8) -> (&'a Pubkey, Vec<AccountInfo<'a
     (program_id, accounts, instruction_data)
 }
 
+/// Declare the program entrypoint and set up the global allocator and panic
+/// handler.
+///
+/// The heap is [`HEAP_LENGTH`] bytes unless `heap_size` is given, which must
+/// match the heap the transaction requests from the runtime:
+///
+/// ```ignore
+/// entrypoint!(process_instruction, heap_size = 64 * 1024);
+/// ```
 #[macro_export]
 macro_rules! entrypoint {
     ($process_instruction:ident) => {
+        $crate::entrypoint!(
+            $process_instruction,
+            heap_size = $crate::entrypoint::HEAP_LENGTH
+        );
+    };
+    ($process_instruction:ident, heap_size = $heap_size:expr) => {
         /// # Safety
         #[no_mangle]
         pub unsafe extern "C" fn entrypoint(input: *mut u8) -> u64 {
-            use std::collections::HashMap;
             let (program_id, utxos, instruction_data) =
                 unsafe { $crate::entrypoint::deserialize(input) };
             match $process_instruction(&program_id, &utxos, &instruction_data) {
@@ -166,25 +357,32 @@ macro_rules! entrypoint {
                 Err(error) => error.into(),
             }
         }
-        $crate::custom_heap_default!();
+        $crate::custom_heap_default!($heap_size);
         $crate::custom_panic_default!();
     };
 }
 
+/// Install a [`HeapAllocator`] over the program heap, [`HEAP_LENGTH`] bytes
+/// long unless another size is given.
 #[macro_export]
 macro_rules! custom_heap_default {
     () => {
+        $crate::custom_heap_default!($crate::entrypoint::HEAP_LENGTH);
+    };
+    ($heap_size:expr) => {
+        #[cfg(target_os = "solana")]
         #[global_allocator]
-        static A: $crate::entrypoint::BumpAllocator = $crate::entrypoint::BumpAllocator {
-            start: $crate::entrypoint::HEAP_START_ADDRESS as usize,
-            len: $crate::entrypoint::HEAP_LENGTH,
-        };
+        static A: $crate::entrypoint::HeapAllocator = $crate::entrypoint::HeapAllocator::new(
+            $crate::entrypoint::HEAP_START_ADDRESS as usize,
+            $crate::entrypoint::checked_heap_length($heap_size),
+        );
     };
 }
 
 #[macro_export]
 macro_rules! custom_panic_default {
     () => {
+        #[cfg(target_os = "solana")]
         #[no_mangle]
         fn custom_panic(info: &core::panic::PanicInfo<'_>) {
             // Full panic reporting
@@ -192,3 +390,92 @@ macro_rules! custom_panic_default {
         }
     };
 }
+
+#[cfg(test)]
+mod tests {
+    use std::alloc::{GlobalAlloc, Layout};
+
+    use super::{
+        checked_heap_length, BumpAllocator, FreeListAllocator, HEAP_LENGTH, MAX_HEAP_LENGTH,
+    };
+
+    #[repr(C, align(16))]
+    struct Heap([u8; 4096]);
+
+    impl Heap {
+        fn new() -> Box<Self> {
+            Box::new(Heap([0; 4096]))
+        }
+
+        fn range(&self) -> (usize, usize) {
+            (self.0.as_ptr() as usize, self.0.len())
+        }
+    }
+
+    #[test]
+    fn test_free_list_allocator_reuses_freed_blocks() {
+        let heap = Heap::new();
+        let (start, len) = heap.range();
+        let allocator = FreeListAllocator::new(start, len);
+        let layout = Layout::from_size_align(100, 8).unwrap();
+
+        unsafe {
+            // Far more than the heap holds, freed as it goes.
+            for _ in 0..1000 {
+                let ptr = allocator.alloc(layout);
+                assert!(!ptr.is_null());
+                ptr.write_bytes(0xff, layout.size());
+                allocator.dealloc(ptr, layout);
+            }
+
+            let first = allocator.alloc(layout);
+            let second = allocator.alloc(layout);
+            assert!(!first.is_null() && !second.is_null());
+            allocator.dealloc(first, layout);
+            let small = allocator.alloc(Layout::from_size_align(10, 1).unwrap());
+            assert_eq!(small, first);
+
+            let aligned_layout = Layout::from_size_align(24, 64).unwrap();
+            let aligned = allocator.alloc(aligned_layout);
+            assert_eq!(aligned as usize % 64, 0);
+            assert!(aligned as usize + 24 <= start + len);
+        }
+    }
+
+    #[test]
+    fn test_allocators_fail_when_heap_is_exhausted() {
+        let heap = Heap::new();
+        let (start, len) = heap.range();
+        let layout = Layout::from_size_align(1024, 8).unwrap();
+
+        let allocator = FreeListAllocator::new(start, len);
+        let allocated: Vec<*mut u8> = (0..8)
+            .map(|_| unsafe { allocator.alloc(layout) })
+            .take_while(|ptr| !ptr.is_null())
+            .collect();
+        assert_eq!(allocated.len(), 3);
+        assert!(unsafe { allocator.alloc(layout) }.is_null());
+        unsafe { allocator.dealloc(allocated[1], layout) };
+        assert_eq!(unsafe { allocator.alloc(layout) }, allocated[1]);
+
+        let heap = Heap::new();
+        let (start, len) = heap.range();
+        let allocator = BumpAllocator::new(start, len);
+        for _ in 0..3 {
+            let ptr = unsafe { allocator.alloc(layout) };
+            assert!(!ptr.is_null());
+            unsafe { allocator.dealloc(ptr, layout) };
+        }
+        assert!(unsafe { allocator.alloc(layout) }.is_null());
+    }
+
+    #[test]
+    fn test_checked_heap_length() {
+        assert_eq!(checked_heap_length(HEAP_LENGTH), HEAP_LENGTH);
+        assert_eq!(checked_heap_length(64 * 1024), 64 * 1024);
+        assert_eq!(checked_heap_length(MAX_HEAP_LENGTH), MAX_HEAP_LENGTH);
+        for len in [HEAP_LENGTH - 1024, HEAP_LENGTH + 1, MAX_HEAP_LENGTH + 1024] {
+            assert!(std::panic::catch_unwind(|| checked_heap_length(len)).is_err());
+        }
+    }
+}
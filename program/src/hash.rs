@@ -0,0 +1,137 @@
+//! The 32-byte digest identifying instructions, messages and transactions.
+
+use core::fmt;
+use core::str::FromStr;
+
+use bitcoin::hashes::{sha256d, Hash as _};
+use borsh::{BorshDeserialize, BorshSerialize};
+use serde::{Deserialize, Serialize};
+
+use crate::codec::{self, ParseHexError};
+
+/// A double SHA-256 digest.
+///
+/// Displayed and parsed as 64 lowercase hex characters in byte order. Like
+/// [`Pubkey`](crate::pubkey::Pubkey), it serializes as its 32 bytes; use
+/// [`serde_hex`](crate::serde_hex) for the hex form.
+#[derive(
+    Clone,
+    Copy,
+    Default,
+    Eq,
+    PartialEq,
+    Hash,
+    PartialOrd,
+    Ord,
+    Serialize,
+    Deserialize,
+    BorshSerialize,
+    BorshDeserialize,
+)]
+pub struct Hash(pub [u8; 32]);
+
+impl Hash {
+    pub const LEN: usize = 32;
+
+    /// Double SHA-256 of `data`.
+    pub fn digest(data: &[u8]) -> Self {
+        Self(sha256d::Hash::hash(data).to_byte_array())
+    }
+
+    pub fn to_bytes(self) -> [u8; 32] {
+        self.0
+    }
+}
+
+/// The digest the original hash functions produced: SHA-256 of the hex
+/// string of SHA-256 of `data`, itself returned as a hex string.
+///
+/// Message signatures commit to this digest (as the bytes of the string), so
+/// it is kept for producing and checking them.
+pub fn legacy_digest(data: &[u8]) -> String {
+    sha256::digest(sha256::digest(data))
+}
+
+impl fmt::Display for Hash {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::LowerHex::fmt(self, f)
+    }
+}
+
+impl fmt::LowerHex for Hash {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for byte in self.0.iter() {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Hash {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Hash({})", self)
+    }
+}
+
+impl FromStr for Hash {
+    type Err = ParseHexError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        codec::parse_hex32(s).map(Self)
+    }
+}
+
+impl AsRef<[u8]> for Hash {
+    fn as_ref(&self) -> &[u8] {
+        &self.0[..]
+    }
+}
+
+impl From<[u8; 32]> for Hash {
+    fn from(value: [u8; 32]) -> Self {
+        Hash(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{legacy_digest, Hash};
+    use crate::codec::ParseHexError;
+
+    use proptest::prelude::*;
+
+    #[test]
+    fn test_double_sha256() {
+        // SHA-256 of SHA-256 of the empty string.
+        assert_eq!(
+            Hash::digest(&[]).to_string(),
+            "5df6e0e2761359d30a8275058e299fcc0381534545f55cf43e41983f5d4c9456"
+        );
+        assert_eq!(
+            legacy_digest(&[]),
+            "cd372fb85148700fa88095e3492d3f9f5beb43e555e5ff26d95f5a6adc36f8e6"
+        );
+        assert_eq!("00".parse::<Hash>(), Err(ParseHexError::InvalidLength(2)));
+        assert_eq!(
+            "zz".repeat(32).parse::<Hash>(),
+            Err(ParseHexError::InvalidHex)
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn fuzz_hash_encodings(bytes in any::<[u8; 32]>()) {
+            let hash = Hash(bytes);
+            prop_assert_eq!(hash.to_string().parse::<Hash>(), Ok(hash));
+            prop_assert_eq!(
+                serde_json::to_value(hash).unwrap(),
+                serde_json::json!(bytes)
+            );
+            prop_assert_eq!(
+                serde_json::from_value::<Hash>(serde_json::json!(bytes)).unwrap(),
+                hash
+            );
+            prop_assert_eq!(borsh::to_vec(&hash).unwrap(), bytes.to_vec());
+        }
+    }
+}
@@ -1,70 +1,30 @@
-use std::str::FromStr;
-
-use bitcoin::{
-    absolute::LockTime, transaction::Version, OutPoint, ScriptBuf, Sequence, Transaction, TxIn,
-    TxOut, Txid, Witness,
-};
+use bitcoin::Transaction;
 
 use crate::{
-    account::AccountInfo,
-    program::{get_account_script_pubkey, get_bitcoin_tx},
+    account::AccountInfo, program_error::ProgramError, transaction_builder::TransactionBuilder,
 };
 
-pub fn get_state_transition_tx(accounts: &[AccountInfo]) -> Transaction {
-    Transaction {
-        version: Version::TWO,
-        lock_time: LockTime::ZERO,
-        input: accounts
-            .iter()
-            .filter(|account| account.is_writable)
-            .map(|account| TxIn {
-                previous_output: OutPoint {
-                    txid: Txid::from_str(&hex::encode(account.utxo.txid())).unwrap(),
-                    vout: account.utxo.vout(),
-                },
-                script_sig: ScriptBuf::new(),
-                sequence: Sequence::MAX,
-                witness: Witness::new(),
-            })
-            .collect::<Vec<TxIn>>(),
-        output: accounts
-            .iter()
-            .filter(|account| account.is_writable)
-            .map(|account| {
-                let tx: Transaction = bitcoin::consensus::deserialize(
-                    &get_bitcoin_tx(account.utxo.txid().try_into().unwrap()).unwrap(),
-                )
-                .unwrap();
-
-                TxOut {
-                    value: tx.output[account.utxo.vout() as usize].value,
-                    script_pubkey: ScriptBuf::from_bytes(
-                        get_account_script_pubkey(account.key).to_vec(),
-                    ),
-                }
-            })
-            .collect::<Vec<TxOut>>(),
-    }
+/// A transaction moving every writable account in `accounts` to a new UTXO.
+///
+/// Use a [`TransactionBuilder`] to also add payouts, fee inputs or change.
+pub fn get_state_transition_tx(accounts: &[AccountInfo]) -> Result<Transaction, ProgramError> {
+    let mut builder = TransactionBuilder::new();
+    builder.add_state_transitions(accounts)?;
+    builder.build()
 }
 
-pub fn add_state_transition(transaction: &mut Transaction, account: &AccountInfo) {
-    assert!(account.is_writable);
-    transaction.input.push(TxIn {
-        previous_output: OutPoint {
-            txid: Txid::from_str(&hex::encode(account.utxo.txid())).unwrap(),
-            vout: account.utxo.vout(),
-        },
-        script_sig: ScriptBuf::new(),
-        sequence: Sequence::MAX,
-        witness: Witness::new(),
-    });
-
-    let tx: Transaction = bitcoin::consensus::deserialize(
-        &get_bitcoin_tx(account.utxo.txid().try_into().unwrap()).unwrap(),
-    )
-    .unwrap();
-    transaction.output.push(TxOut {
-        value: tx.output[account.utxo.vout() as usize].value,
-        script_pubkey: ScriptBuf::from_bytes(get_account_script_pubkey(account.key).to_vec()),
-    });
+/// Append the state transition of `account` to `transaction`.
+///
+/// The account's new UTXO is only right if its input and output end up at the
+/// same index, so prefer a [`TransactionBuilder`].
+pub fn add_state_transition(
+    transaction: &mut Transaction,
+    account: &AccountInfo,
+) -> Result<(), ProgramError> {
+    let mut builder = TransactionBuilder::new();
+    builder.add_state_transition(account)?;
+    let state_transition = builder.build()?;
+    transaction.input.extend(state_transition.input);
+    transaction.output.extend(state_transition.output);
+    Ok(())
 }
@@ -1,14 +1,13 @@
-use std::mem::size_of;
-
 use thiserror::Error;
 
+use crate::codec::{self, DecodeError, EncodeError, Reader};
+use crate::hash::Hash;
 use crate::program_error::*;
 use crate::pubkey::Pubkey;
 use crate::{account::AccountMeta, program_error::ProgramError};
 
 use borsh::{BorshDeserialize, BorshSerialize};
 use serde::{Deserialize, Serialize};
-use sha256::digest;
 
 #[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize, BorshSerialize, BorshDeserialize)]
 pub struct Instruction {
@@ -18,46 +17,110 @@ pub struct Instruction {
 }
 
 impl Instruction {
-    pub fn serialize(&self) -> Vec<u8> {
+    pub fn serialize(&self) -> Result<Vec<u8>, EncodeError> {
         let mut serilized = vec![];
 
         serilized.extend(self.program_id.serialize());
-        // accounts length should fit in a u8
-        serilized.push(self.accounts.len() as u8);
+        serilized.push(codec::u8_len("instruction.accounts", self.accounts.len())?);
         for meta in self.accounts.iter() {
             serilized.extend(&meta.serialize());
         }
-        // data length should fit in a u64
-        serilized.extend(self.data.len().to_le_bytes());
+        serilized.extend((self.data.len() as u64).to_le_bytes());
         serilized.extend(&self.data);
 
-        serilized
+        Ok(serilized)
+    }
+
+    pub fn from_slice(data: &[u8]) -> Result<Self, DecodeError> {
+        Self::decode(&mut Reader::new(data))
     }
 
-    pub fn from_slice(data: &[u8]) -> Self {
-        let mut size = 32;
-        let accounts_len = data[size] as usize;
-        size += 1;
+    /// Decodes an instruction from the front of `reader`, leaving it
+    /// positioned just past the instruction data.
+    pub fn decode(reader: &mut Reader) -> Result<Self, DecodeError> {
+        let program_id = Pubkey::from(reader.read_array("instruction.program_id")?);
+        let accounts_len = reader.read_u8("instruction.accounts.len")? as usize;
         let mut accounts = Vec::with_capacity(accounts_len);
         for _ in 0..accounts_len {
-            accounts.push(AccountMeta::from_slice(&data[size..(size + 34)]));
-            size += size_of::<AccountMeta>();
+            accounts.push(AccountMeta::decode(reader)?);
         }
-        let data_len = u64::from_le_bytes(data[size..(size + 8)].try_into().unwrap());
-        size += size_of::<u64>();
+        let data = reader.read_u64_prefixed("instruction.data")?.to_vec();
 
-        Self {
-            program_id: Pubkey::from_slice(&data[..32]),
+        Ok(Self {
+            program_id,
             accounts,
-            data: (data[size..(size + data_len as usize)]).to_vec(),
-        }
+            data,
+        })
     }
 
-    pub fn hash(&self) -> String {
-        digest(digest(self.serialize()))
+    pub fn hash(&self) -> Result<Hash, EncodeError> {
+        Ok(Hash::digest(&self.serialize()?))
+    }
+
+    /// The [legacy hex digest](crate::hash::legacy_digest) of the
+    /// instruction, the form nodes report instruction hashes in.
+    pub fn legacy_hash(&self) -> Result<String, EncodeError> {
+        Ok(crate::hash::legacy_digest(&self.serialize()?))
+    }
+}
+
+/// An enum of a program's instructions that can be decoded from, and encoded
+/// into, instruction data.
+///
+/// The data is a one-byte discriminant followed by the borsh encoding of the
+/// variant's fields. Programs dispatch with `match MyInstruction::unpack(data)?`
+/// and clients build an [`Instruction`] with [`ProgramInstruction::instruction`],
+/// so both sides always agree on the layout. Usually derived:
+///
+/// ```
+/// use arch_program::instruction::ProgramInstruction;
+/// use arch_program::pubkey::Pubkey;
+///
+/// #[derive(ProgramInstruction)]
+/// enum CounterInstruction {
+///     Initialize,
+///     Increment { amount: u64 },
+///     #[instruction(tag = 10)]
+///     Close,
+/// }
+///
+/// let data = CounterInstruction::Increment { amount: 5 }.pack();
+/// assert_eq!(data, [1, 5, 0, 0, 0, 0, 0, 0, 0]);
+/// assert!(matches!(
+///     CounterInstruction::unpack(&data),
+///     Ok(CounterInstruction::Increment { amount: 5 })
+/// ));
+/// assert_eq!(CounterInstruction::Close.discriminant(), 10);
+///
+/// let instruction = CounterInstruction::Initialize.instruction(Pubkey::system_program(), vec![]);
+/// assert_eq!(instruction.data, [0]);
+/// ```
+pub trait ProgramInstruction: Sized {
+    /// The tag identifying this variant in the first byte of the data.
+    fn discriminant(&self) -> u8;
+
+    /// Encode the instruction into instruction data.
+    fn pack(&self) -> Vec<u8>;
+
+    /// Decode instruction data.
+    ///
+    /// Fails with [`ProgramError::InvalidInstructionData`] if the data is
+    /// empty, has an unknown discriminant, does not decode as the variant's
+    /// fields or has bytes left over.
+    fn unpack(instruction_data: &[u8]) -> Result<Self, ProgramError>;
+
+    /// Build an [`Instruction`] invoking `program_id` with this data.
+    fn instruction(&self, program_id: Pubkey, accounts: Vec<AccountMeta>) -> Instruction {
+        Instruction {
+            program_id,
+            accounts,
+            data: self.pack(),
+        }
     }
 }
 
+pub use arch_program_macros::ProgramInstruction;
+
 #[derive(Debug, Error, PartialEq, Eq, Clone)]
 pub enum InstructionError {
     /// Deprecated! Use CustomError instead!
@@ -354,7 +417,7 @@ mod tests {
 
         assert_eq!(
             instruction,
-            Instruction::from_slice(&instruction.serialize())
+            Instruction::from_slice(&instruction.serialize().unwrap()).unwrap()
         );
 
         let instruction = Instruction {
@@ -369,7 +432,118 @@ mod tests {
 
         assert_eq!(
             instruction,
-            Instruction::from_slice(&instruction.serialize())
+            Instruction::from_slice(&instruction.serialize().unwrap()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_serialize_rejects_account_overflow() {
+        let instruction = Instruction {
+            program_id: Pubkey::system_program(),
+            accounts: vec![
+                AccountMeta {
+                    pubkey: Pubkey::system_program(),
+                    is_signer: false,
+                    is_writable: false,
+                };
+                256
+            ],
+            data: vec![],
+        };
+
+        assert_eq!(
+            instruction.serialize(),
+            Err(EncodeError::LengthOverflow {
+                field: "instruction.accounts",
+                len: 256,
+                max: 255,
+            })
+        );
+    }
+
+    #[test]
+    fn test_from_slice_reports_truncated_field() {
+        let instruction = Instruction {
+            program_id: Pubkey::system_program(),
+            accounts: vec![],
+            data: vec![1, 2, 3],
+        };
+        let serialized = instruction.serialize().unwrap();
+
+        assert_eq!(
+            Instruction::from_slice(&serialized[..serialized.len() - 1]),
+            Err(DecodeError::UnexpectedEnd {
+                field: "instruction.data",
+                offset: 41,
+                needed: 3,
+                remaining: 2,
+            })
+        );
+    }
+
+    #[derive(Debug, PartialEq, BorshSerialize, BorshDeserialize)]
+    struct TransferInput {
+        amount: u64,
+        memo: String,
+    }
+
+    #[derive(Debug, PartialEq, ProgramInstruction)]
+    enum TestInstruction {
+        Initialize,
+        Transfer(TransferInput),
+        Mint {
+            amount: u64,
+            recipient: Pubkey,
+        },
+        #[instruction(tag = 7)]
+        Burn(u64, bool),
+        Close,
+    }
+
+    #[test]
+    fn test_program_instruction_discriminants() {
+        assert_eq!(TestInstruction::Initialize.discriminant(), 0);
+        assert_eq!(TestInstruction::Burn(1, true).discriminant(), 7);
+        assert_eq!(TestInstruction::Close.discriminant(), 8);
+        assert_eq!(
+            TestInstruction::Burn(1, true).pack(),
+            [7, 1, 0, 0, 0, 0, 0, 0, 0, 1]
+        );
+    }
+
+    #[test]
+    fn test_program_instruction_rejects_invalid_data() {
+        for data in [
+            &[][..],
+            &[2][..],
+            &[9][..],
+            &[0, 0][..],
+            &[7, 1, 0, 0, 0, 0, 0, 0, 0, 2][..],
+        ] {
+            assert_eq!(
+                TestInstruction::unpack(data),
+                Err(ProgramError::InvalidInstructionData)
+            );
+        }
+    }
+
+    #[test]
+    fn test_program_instruction_builder() {
+        let accounts = vec![AccountMeta {
+            pubkey: Pubkey::from([1; 32]),
+            is_signer: true,
+            is_writable: false,
+        }];
+        let instruction =
+            TestInstruction::Close.instruction(Pubkey::system_program(), accounts.clone());
+
+        assert_eq!(
+            instruction,
+            Instruction {
+                program_id: Pubkey::system_program(),
+                accounts,
+                data: vec![8],
+            }
         );
     }
 
@@ -407,10 +581,48 @@ mod tests {
                 data: data.clone(),
             };
 
-            let serialized = instruction.serialize();
-            let deserialized = Instruction::from_slice(&serialized);
+            let serialized = instruction.serialize().unwrap();
+            let deserialized = Instruction::from_slice(&serialized).unwrap();
 
             assert_eq!(instruction, deserialized);
+
+            for len in 0..serialized.len() {
+                assert!(Instruction::from_slice(&serialized[..len]).is_err());
+            }
+        }
+
+        #[test]
+        fn fuzz_pack_unpack_program_instruction(
+            amount in any::<u64>(),
+            memo in ".{0,32}",
+            recipient in prop::array::uniform32(any::<u8>()),
+            flag in any::<bool>(),
+        ) {
+            for instruction in [
+                TestInstruction::Initialize,
+                TestInstruction::Transfer(TransferInput { amount, memo: memo.clone() }),
+                TestInstruction::Mint { amount, recipient: Pubkey::from(recipient) },
+                TestInstruction::Burn(amount, flag),
+                TestInstruction::Close,
+            ] {
+                let packed = instruction.pack();
+                assert_eq!(packed[0], instruction.discriminant());
+                assert_eq!(TestInstruction::unpack(&packed), Ok(instruction));
+
+                for len in 0..packed.len() {
+                    assert!(TestInstruction::unpack(&packed[..len]).is_err());
+                }
+            }
+        }
+
+        #[test]
+        fn fuzz_unpack_arbitrary_program_instruction(data in prop::collection::vec(any::<u8>(), 0..64)) {
+            let _ = TestInstruction::unpack(&data);
+        }
+
+        #[test]
+        fn fuzz_deserialize_arbitrary_instruction(data in prop::collection::vec(any::<u8>(), 0..256)) {
+            let _ = Instruction::from_slice(&data);
         }
     }
 }
@@ -0,0 +1,398 @@
+//! An entrypoint that reads the program input on demand.
+//!
+//! [`entrypoint!`](crate::entrypoint!) deserializes every account into an
+//! [`AccountInfo`] before calling the program. [`lazy_entrypoint!`] instead
+//! hands the program an [`InstructionContext`], a cursor over the input buffer
+//! that yields accounts one at a time as [`LazyAccount`]s pointing into the
+//! buffer, so a program only pays for the accounts it reads and allocates
+//! nothing to do so.
+//!
+//! ```ignore
+//! lazy_entrypoint!(process_instruction);
+//!
+//! fn process_instruction(mut context: InstructionContext) -> ProgramResult {
+//!     let MaybeAccount::Account(mut from) = context.next_account()? else {
+//!         return Err(ProgramError::InvalidArgument);
+//!     };
+//!     let instruction_data = context.instruction_data();
+//!     from.data_mut()[..instruction_data.len()].copy_from_slice(instruction_data);
+//!     Ok(())
+//! }
+//! ```
+
+use std::{
+    cell::RefCell,
+    marker::PhantomData,
+    mem::size_of,
+    rc::Rc,
+    slice::{from_raw_parts, from_raw_parts_mut},
+};
+
+use crate::{
+    account::AccountInfo,
+    entrypoint::{BPF_ALIGN_OF_U128, MAX_PERMITTED_DATA_INCREASE, NON_DUP_MARKER},
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    utxo::UtxoMeta,
+};
+
+// Offsets within a serialized account, from its duplicate marker.
+const IS_SIGNER: usize = 5;
+const IS_WRITABLE: usize = 6;
+const IS_EXECUTABLE: usize = 7;
+const KEY: usize = 8;
+const DATA_LEN: usize = KEY + size_of::<Pubkey>() + size_of::<u64>();
+const DATA: usize = DATA_LEN + size_of::<u64>();
+/// Size of a serialized duplicate account.
+const DUPLICATE_SIZE: usize = 8;
+
+/// A cursor over the serialized input of an instruction.
+pub struct InstructionContext<'a> {
+    input: *mut u8,
+    offset: usize,
+    remaining: u64,
+    _input: PhantomData<&'a mut [u8]>,
+}
+
+/// An account read from an [`InstructionContext`].
+pub enum MaybeAccount<'a> {
+    Account(LazyAccount<'a>),
+    /// An account passed more than once, given as the index of its first
+    /// occurrence.
+    Duplicated(u8),
+}
+
+/// An account in the input buffer, read in place.
+pub struct LazyAccount<'a> {
+    account: *mut u8,
+    _input: PhantomData<&'a mut [u8]>,
+}
+
+impl<'a> InstructionContext<'a> {
+    /// # Safety
+    ///
+    /// `input` must point to the serialized program input the runtime passes
+    /// to the entrypoint, and nothing else may access it for `'a`.
+    pub unsafe fn new(input: *mut u8) -> Self {
+        Self {
+            input,
+            offset: size_of::<u64>(),
+            remaining: *(input as *const u64),
+            _input: PhantomData,
+        }
+    }
+
+    /// Number of accounts not yet read.
+    pub fn remaining(&self) -> u64 {
+        self.remaining
+    }
+
+    /// Read the next account.
+    ///
+    /// Fails with [`ProgramError::NotEnoughAccountKeys`] once every account
+    /// has been read.
+    pub fn next_account(&mut self) -> Result<MaybeAccount<'a>, ProgramError> {
+        if self.remaining == 0 {
+            return Err(ProgramError::NotEnoughAccountKeys);
+        }
+        self.remaining -= 1;
+
+        // SAFETY: the runtime serializes `remaining` more accounts from `offset`.
+        unsafe {
+            let account = self.input.add(self.offset);
+            let dup_info = *account;
+            if dup_info != NON_DUP_MARKER {
+                self.offset += DUPLICATE_SIZE;
+                return Ok(MaybeAccount::Duplicated(dup_info));
+            }
+
+            let data_len = *(account.add(DATA_LEN) as *const u64) as usize;
+            self.offset += DATA + data_len + MAX_PERMITTED_DATA_INCREASE;
+            self.offset += (self.offset as *const u8).align_offset(BPF_ALIGN_OF_U128);
+            self.offset += size_of::<Pubkey>() + size_of::<UtxoMeta>() + 4;
+
+            Ok(MaybeAccount::Account(LazyAccount {
+                account,
+                _input: PhantomData,
+            }))
+        }
+    }
+
+    /// Skip the accounts not yet read.
+    fn skip_accounts(&mut self) {
+        while self.remaining > 0 {
+            let _ = self.next_account();
+        }
+    }
+
+    /// The instruction data, skipping any accounts not yet read.
+    pub fn instruction_data(&mut self) -> &'a [u8] {
+        self.skip_accounts();
+        // SAFETY: the instruction data follows the last account.
+        unsafe {
+            let len = *(self.input.add(self.offset) as *const u64) as usize;
+            from_raw_parts(self.input.add(self.offset + size_of::<u64>()), len)
+        }
+    }
+
+    /// The id of the program being run, skipping any accounts not yet read.
+    pub fn program_id(&mut self) -> &'a Pubkey {
+        let instruction_data = self.instruction_data();
+        // SAFETY: the program id follows the instruction data.
+        unsafe { &*(instruction_data.as_ptr().add(instruction_data.len()) as *const Pubkey) }
+    }
+}
+
+impl<'a> LazyAccount<'a> {
+    fn flag(&self, offset: usize) -> bool {
+        // SAFETY: `account` points to a serialized account.
+        unsafe { *self.account.add(offset) != 0 }
+    }
+
+    pub fn key(&self) -> &'a Pubkey {
+        // SAFETY: `account` points to a serialized account.
+        unsafe { &*(self.account.add(KEY) as *const Pubkey) }
+    }
+
+    pub fn is_signer(&self) -> bool {
+        self.flag(IS_SIGNER)
+    }
+
+    pub fn is_writable(&self) -> bool {
+        self.flag(IS_WRITABLE)
+    }
+
+    pub fn is_executable(&self) -> bool {
+        self.flag(IS_EXECUTABLE)
+    }
+
+    pub fn data_len(&self) -> usize {
+        // SAFETY: `account` points to a serialized account.
+        unsafe { *(self.account.add(DATA_LEN) as *const u64) as usize }
+    }
+
+    pub fn data(&self) -> &[u8] {
+        // SAFETY: the data follows the account's data length.
+        unsafe { from_raw_parts(self.account.add(DATA), self.data_len()) }
+    }
+
+    /// The account data, for writing. The runtime rejects writes to accounts
+    /// that are not writable or not owned by the program.
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        // SAFETY: the data follows the account's data length, and `&mut self`
+        // is the only access to it.
+        unsafe { from_raw_parts_mut(self.account.add(DATA), self.data_len()) }
+    }
+
+    /// Offset of the owner, which follows the data and its realloc space.
+    fn owner_offset(&self) -> usize {
+        let offset = DATA + self.data_len() + MAX_PERMITTED_DATA_INCREASE;
+        // The account starts 8-byte aligned, so its offsets align the same way.
+        offset + (offset as *const u8).align_offset(BPF_ALIGN_OF_U128)
+    }
+
+    pub fn owner(&self) -> &'a Pubkey {
+        // SAFETY: `account` points to a serialized account.
+        unsafe { &*(self.account.add(self.owner_offset()) as *const Pubkey) }
+    }
+
+    pub fn utxo(&self) -> &'a UtxoMeta {
+        // SAFETY: the utxo follows the owner.
+        unsafe {
+            &*(self.account.add(self.owner_offset() + size_of::<Pubkey>()) as *const UtxoMeta)
+        }
+    }
+
+    /// Convert into an [`AccountInfo`] for code that needs one. This
+    /// allocates like [`entrypoint::deserialize`](crate::entrypoint::deserialize).
+    pub fn into_account_info(self) -> AccountInfo<'a> {
+        // SAFETY: the account is read once, so this is the only access to
+        // its data for `'a`.
+        let data = unsafe { from_raw_parts_mut(self.account.add(DATA), self.data_len()) };
+        AccountInfo {
+            key: self.key(),
+            utxo: self.utxo(),
+            data: Rc::new(RefCell::new(data)),
+            owner: self.owner(),
+            is_signer: self.is_signer(),
+            is_writable: self.is_writable(),
+            is_executable: self.is_executable(),
+        }
+    }
+}
+
+/// Declare a program entrypoint that takes an [`InstructionContext`].
+///
+/// Like [`entrypoint!`](crate::entrypoint!), it installs the allocator and
+/// panic handler and accepts a `heap_size`.
+#[macro_export]
+macro_rules! lazy_entrypoint {
+    ($process_instruction:ident) => {
+        $crate::lazy_entrypoint!(
+            $process_instruction,
+            heap_size = $crate::entrypoint::HEAP_LENGTH
+        );
+    };
+    ($process_instruction:ident, heap_size = $heap_size:expr) => {
+        /// # Safety
+        #[no_mangle]
+        pub unsafe extern "C" fn entrypoint(input: *mut u8) -> u64 {
+            let context = unsafe { $crate::lazy_entrypoint::InstructionContext::new(input) };
+            match $process_instruction(context) {
+                Ok(()) => $crate::entrypoint::SUCCESS,
+                Err(error) => error.into(),
+            }
+        }
+        $crate::custom_heap_default!($heap_size);
+        $crate::custom_panic_default!();
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use std::mem::size_of;
+
+    use super::{InstructionContext, MaybeAccount};
+    use crate::{
+        entrypoint::{deserialize, MAX_PERMITTED_DATA_INCREASE, NON_DUP_MARKER},
+        program_error::ProgramError,
+        pubkey::Pubkey,
+        utxo::UtxoMeta,
+    };
+
+    struct TestAccount {
+        key: Pubkey,
+        owner: Pubkey,
+        utxo: UtxoMeta,
+        data: Vec<u8>,
+        is_signer: bool,
+        is_writable: bool,
+    }
+
+    enum Input {
+        Account(TestAccount),
+        Duplicate(u8),
+    }
+
+    /// Serialize the input the way the runtime does, into an 8-byte aligned buffer.
+    fn serialize(accounts: &[Input], instruction_data: &[u8], program_id: &Pubkey) -> Vec<u64> {
+        let mut input = vec![];
+        input.extend((accounts.len() as u64).to_le_bytes());
+        for account in accounts {
+            match account {
+                Input::Account(account) => {
+                    input.push(NON_DUP_MARKER);
+                    input.extend([0; 4]);
+                    input.extend([account.is_signer as u8, account.is_writable as u8, 0]);
+                    input.extend(account.key.serialize());
+                    input.extend((account.data.len() as u64).to_le_bytes());
+                    input.extend((account.data.len() as u64).to_le_bytes());
+                    input.extend(&account.data);
+                    input.resize(input.len() + MAX_PERMITTED_DATA_INCREASE, 0);
+                    input.resize(input.len().next_multiple_of(8), 0);
+                    input.extend(account.owner.serialize());
+                    input.extend(account.utxo.serialize());
+                    input.extend([0; 4]);
+                }
+                Input::Duplicate(index) => {
+                    input.push(*index);
+                    input.extend([0; 7]);
+                }
+            }
+        }
+        input.extend((instruction_data.len() as u64).to_le_bytes());
+        input.extend(instruction_data);
+        input.extend(program_id.serialize());
+
+        let mut aligned = vec![0u64; input.len().div_ceil(size_of::<u64>())];
+        // SAFETY: `aligned` holds at least `input.len()` bytes.
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                input.as_ptr(),
+                aligned.as_mut_ptr() as *mut u8,
+                input.len(),
+            )
+        };
+        aligned
+    }
+
+    fn accounts() -> Vec<Input> {
+        vec![
+            Input::Account(TestAccount {
+                key: Pubkey::from([1; 32]),
+                owner: Pubkey::from([2; 32]),
+                utxo: UtxoMeta::from([3; 32], 4),
+                data: vec![5; 13],
+                is_signer: true,
+                is_writable: false,
+            }),
+            Input::Duplicate(0),
+            Input::Account(TestAccount {
+                key: Pubkey::from([6; 32]),
+                owner: Pubkey::from([7; 32]),
+                utxo: UtxoMeta::from([8; 32], 9),
+                data: vec![],
+                is_signer: false,
+                is_writable: true,
+            }),
+        ]
+    }
+
+    #[test]
+    fn test_lazy_matches_deserialize() {
+        let program_id = Pubkey::from([10; 32]);
+        let mut input = serialize(&accounts(), &[11, 12, 13], &program_id);
+        let mut lazy_input = input.clone();
+
+        let (expected_program_id, expected_accounts, expected_data) =
+            unsafe { deserialize(input.as_mut_ptr() as *mut u8) };
+        let mut context = unsafe { InstructionContext::new(lazy_input.as_mut_ptr() as *mut u8) };
+
+        assert_eq!(context.remaining(), 3);
+        for (index, expected) in expected_accounts.iter().enumerate() {
+            let account = match context.next_account().unwrap() {
+                MaybeAccount::Account(account) => account,
+                MaybeAccount::Duplicated(original) => {
+                    assert_eq!(index, 1);
+                    assert_eq!(original, 0);
+                    continue;
+                }
+            };
+            assert_eq!(account.key(), expected.key);
+            assert_eq!(account.owner(), expected.owner);
+            assert_eq!(account.utxo(), expected.utxo);
+            assert_eq!(account.is_signer(), expected.is_signer);
+            assert_eq!(account.is_writable(), expected.is_writable);
+            assert_eq!(account.is_executable(), expected.is_executable);
+            assert_eq!(account.data(), &expected.data.borrow()[..]);
+
+            let account_info = account.into_account_info();
+            assert_eq!(account_info.key, expected.key);
+            assert_eq!(account_info.data_len(), expected.data_len());
+        }
+        assert_eq!(context.remaining(), 0);
+        assert!(matches!(
+            context.next_account(),
+            Err(ProgramError::NotEnoughAccountKeys)
+        ));
+        assert_eq!(context.instruction_data(), expected_data);
+        assert_eq!(context.program_id(), expected_program_id);
+    }
+
+    #[test]
+    fn test_lazy_skips_unread_accounts() {
+        let program_id = Pubkey::from([10; 32]);
+        let mut input = serialize(&accounts(), &[11, 12, 13], &program_id);
+        let mut context = unsafe { InstructionContext::new(input.as_mut_ptr() as *mut u8) };
+
+        let MaybeAccount::Account(mut first) = context.next_account().unwrap() else {
+            panic!("the first account is not a duplicate");
+        };
+        first.data_mut()[0] = 42;
+
+        assert_eq!(context.program_id(), &program_id);
+        assert_eq!(context.instruction_data(), &[11, 12, 13]);
+        assert_eq!(context.remaining(), 0);
+        assert_eq!(first.data()[..2], [42, 5]);
+    }
+}
@@ -2,29 +2,44 @@ use account::AccountInfo;
 use program_error::ProgramError;
 
 pub use bitcoin;
+pub use borsh;
+
+// Lets the derive macros refer to `::arch_program` from within this crate.
+extern crate self as arch_program;
 
 pub mod account;
+pub mod account_data;
+pub mod accounts;
 pub mod atomic_u64;
 pub mod clock;
+pub mod codec;
 pub mod debug_account_data;
 pub mod entrypoint;
+pub mod hash;
 pub mod helper;
 pub mod input_to_sign;
 pub mod instruction;
+pub mod lazy_entrypoint;
 pub mod log;
+pub mod math;
 pub mod message;
 pub mod program;
 pub mod program_error;
 pub mod program_stubs;
+pub mod program_test;
 pub mod pubkey;
 pub mod sanitized;
+pub mod serde_hex;
 pub mod stable_layout;
 pub mod syscalls;
 pub mod system_instruction;
+pub mod transaction_builder;
 pub mod transaction_to_sign;
 pub mod utxo;
 
-pub const MAX_BTC_TX_SIZE: usize = 1024;
+/// Largest Bitcoin transaction [`program::get_bitcoin_tx`] will allocate for.
+/// Larger transactions can be fetched with [`program::get_bitcoin_tx_into`].
+pub const MAX_BTC_TX_SIZE: usize = 16 * 1024;
 
 // Helper Funtions
 pub fn next_account_info<'a, 'b, I: Iterator<Item = &'a AccountInfo<'b>>>(
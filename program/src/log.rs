@@ -32,6 +32,13 @@
 //!
 //! [`Pubkey`]: crate::pubkey::Pubkey
 //! [`Pubkey::log`]: crate::pubkey::Pubkey::log
+//!
+//! Structured data for indexers is logged as an [`Event`] with [`emit!`],
+//! which can be decoded again with [`decode_event`].
+//!
+//! [`emit!`]: crate::emit!
+
+use borsh::{BorshDeserialize, BorshSerialize};
 
 use crate::account::AccountInfo;
 
@@ -102,14 +109,87 @@ pub fn sol_log(message: &str) {
 /// Print 64-bit values represented as hexadecimal to the log.
 #[inline]
 pub fn sol_log_64(arg1: u64, arg2: u64, arg3: u64, arg4: u64, arg5: u64) {
+    #[cfg(target_os = "solana")]
     unsafe {
         crate::syscalls::sol_log_64_(arg1, arg2, arg3, arg4, arg5);
     }
+    #[cfg(not(target_os = "solana"))]
+    crate::program_stubs::sol_log_64(arg1, arg2, arg3, arg4, arg5);
 }
 
 /// Print some slices as base64.
 pub fn sol_log_data(data: &[&[u8]]) {
-    unsafe { crate::syscalls::sol_log_data(data as *const _ as *const u8, data.len() as u64) };
+    #[cfg(target_os = "solana")]
+    unsafe {
+        crate::syscalls::sol_log_data(data as *const _ as *const u8, data.len() as u64)
+    };
+    #[cfg(not(target_os = "solana"))]
+    crate::program_stubs::sol_log_data(data);
+}
+
+/// A typed record logged with [`emit!`](crate::emit!).
+///
+/// An event is logged through [`sol_log_data`] as two fields: its
+/// [`Event::DISCRIMINATOR`] followed by its borsh encoding.
+pub trait Event: BorshSerialize + BorshDeserialize {
+    /// Identifies the event type. It must be unique among the program's events.
+    const DISCRIMINATOR: [u8; 8];
+}
+
+/// Log an [`Event`].
+///
+/// # Examples
+///
+/// ```
+/// use arch_program::{emit, log::Event};
+/// use borsh::{BorshDeserialize, BorshSerialize};
+///
+/// #[derive(BorshSerialize, BorshDeserialize)]
+/// struct Deposited {
+///     amount: u64,
+/// }
+///
+/// impl Event for Deposited {
+///     const DISCRIMINATOR: [u8; 8] = *b"deposit_";
+/// }
+///
+/// emit!(Deposited { amount: 100 });
+/// ```
+#[macro_export]
+macro_rules! emit {
+    ($event:expr) => {
+        $crate::log::emit_event(&$event)
+    };
+}
+
+/// Log an [`Event`]. Usually called through [`emit!`](crate::emit!).
+pub fn emit_event<E: Event>(event: &E) {
+    let data = borsh::to_vec(event).expect("serializing into a Vec cannot fail");
+    sol_log_data(&[&E::DISCRIMINATOR, &data]);
+}
+
+/// Decode an event from the fields of a [`sol_log_data`] call.
+///
+/// Returns `None` if the fields hold a different event or do not decode as `E`.
+pub fn decode_event<E: Event, T: AsRef<[u8]>>(fields: &[T]) -> Option<E> {
+    match fields {
+        [discriminator, data] if discriminator.as_ref() == E::DISCRIMINATOR => {
+            E::try_from_slice(data.as_ref()).ok()
+        }
+        _ => None,
+    }
+}
+
+/// Take the events of type `E` emitted on the current thread since the log
+/// data captured by the native stubs was last taken.
+///
+/// This drains all captured log data, including that of other event types.
+#[cfg(not(target_os = "solana"))]
+pub fn take_events<E: Event>() -> Vec<E> {
+    crate::program_stubs::take_log_data()
+        .iter()
+        .filter_map(|fields| decode_event(fields))
+        .collect()
 }
 
 /// Print the hexadecimal representation of a slice.
@@ -141,3 +221,80 @@ pub fn sol_log_params(accounts: &[AccountInfo], data: &[u8]) {
     msg!("Instruction data");
     sol_log_slice(data);
 }
+
+#[cfg(test)]
+mod tests {
+    use borsh::{BorshDeserialize, BorshSerialize};
+
+    use super::{decode_event, take_events, Event};
+    use crate::program_stubs;
+
+    #[derive(Debug, PartialEq, BorshSerialize, BorshDeserialize)]
+    struct Deposited {
+        amount: u64,
+        depositor: [u8; 32],
+    }
+
+    impl Event for Deposited {
+        const DISCRIMINATOR: [u8; 8] = *b"deposit_";
+    }
+
+    #[derive(Debug, PartialEq, BorshSerialize, BorshDeserialize)]
+    struct Withdrawn {
+        amount: u64,
+    }
+
+    impl Event for Withdrawn {
+        const DISCRIMINATOR: [u8; 8] = *b"withdraw";
+    }
+
+    #[test]
+    fn test_emit_captures_events() {
+        program_stubs::take_log_data();
+
+        emit!(Deposited {
+            amount: 5,
+            depositor: [1; 32],
+        });
+        emit!(Withdrawn { amount: 2 });
+
+        let log_data = program_stubs::take_log_data();
+        assert_eq!(log_data.len(), 2);
+        assert_eq!(log_data[0][0], Deposited::DISCRIMINATOR);
+        assert_eq!(
+            decode_event::<Deposited, _>(&log_data[0]),
+            Some(Deposited {
+                amount: 5,
+                depositor: [1; 32],
+            })
+        );
+        assert_eq!(decode_event::<Deposited, _>(&log_data[1]), None);
+        assert_eq!(
+            decode_event::<Withdrawn, _>(&log_data[1]),
+            Some(Withdrawn { amount: 2 })
+        );
+
+        emit!(Withdrawn { amount: 3 });
+        assert_eq!(take_events::<Withdrawn>(), vec![Withdrawn { amount: 3 }]);
+        assert!(program_stubs::take_log_data().is_empty());
+    }
+
+    #[test]
+    fn test_decode_event_rejects_malformed_data() {
+        let data = borsh::to_vec(&Withdrawn { amount: 1 }).unwrap();
+
+        assert_eq!(decode_event::<Withdrawn, &[u8]>(&[]), None);
+        assert_eq!(
+            decode_event::<Withdrawn, _>(&[&Withdrawn::DISCRIMINATOR[..]]),
+            None
+        );
+        assert_eq!(
+            decode_event::<Withdrawn, _>(&[&Withdrawn::DISCRIMINATOR[..], &data[..4]]),
+            None
+        );
+        assert_eq!(
+            decode_event::<Withdrawn, _>(&[&Withdrawn::DISCRIMINATOR[..], &data, &data]),
+            None
+        );
+    }
+}
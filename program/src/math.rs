@@ -0,0 +1,522 @@
+//! Checked fixed-point arithmetic for rates, yields and token amounts.
+//!
+//! [`Wad`] holds a fraction scaled by 10^18 and [`Bps`] one scaled by 10^4
+//! (basis points). Intermediate products are carried in 256 bits, so
+//! `amount * rate / scale` never overflows part way through; only a result
+//! that does not fit its type fails. Every operation that can lose precision
+//! takes a [`Rounding`], and every failure, including division by zero, is
+//! [`ProgramError::ArithmeticOverflow`].
+//!
+//! ```
+//! use arch_program::clock::SECONDS_PER_DAY;
+//! use arch_program::math::{simple_interest, Bps, Rounding};
+//!
+//! // A day of interest at 7.5% a year on 1_000_000 units.
+//! assert_eq!(
+//!     simple_interest(1_000_000, Bps(750).to_wad(), SECONDS_PER_DAY, Rounding::Down),
+//!     Ok(205)
+//! );
+//! ```
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use serde::{Deserialize, Serialize};
+
+use crate::clock::SECONDS_PER_DAY;
+use crate::program_error::ProgramError;
+
+/// The scale of a [`Wad`].
+pub const WAD: u128 = 1_000_000_000_000_000_000;
+
+/// The scale of a [`Bps`]: 10_000 basis points make one.
+pub const BPS: u128 = 10_000;
+
+/// Seconds in a 365-day year, the period annual rates refer to.
+pub const SECONDS_PER_YEAR: u64 = 365 * SECONDS_PER_DAY;
+
+/// Which way to round a result that is not a whole number of units.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Rounding {
+    /// Towards zero. Use when paying out, so the program never gives away
+    /// more than it holds.
+    Down,
+    /// Away from zero. Use when charging.
+    Up,
+    /// To the nearest unit, halves rounding up.
+    Nearest,
+}
+
+/// `a * b / denominator`, rounded as asked, computed without intermediate
+/// overflow.
+pub fn mul_div(
+    a: u128,
+    b: u128,
+    denominator: u128,
+    rounding: Rounding,
+) -> Result<u128, ProgramError> {
+    if denominator == 0 {
+        return Err(ProgramError::ArithmeticOverflow);
+    }
+    let (high, low) = full_mul(a, b);
+    // The quotient only fits in 128 bits if the high half is below the
+    // denominator.
+    if high >= denominator {
+        return Err(ProgramError::ArithmeticOverflow);
+    }
+    let (quotient, remainder) = div_wide(high, low, denominator);
+    let round_up = match rounding {
+        Rounding::Down => false,
+        Rounding::Up => remainder != 0,
+        Rounding::Nearest => remainder >= denominator - remainder,
+    };
+    if round_up {
+        quotient
+            .checked_add(1)
+            .ok_or(ProgramError::ArithmeticOverflow)
+    } else {
+        Ok(quotient)
+    }
+}
+
+/// The 256-bit product of `a` and `b` as (high, low) halves.
+fn full_mul(a: u128, b: u128) -> (u128, u128) {
+    const MASK: u128 = u64::MAX as u128;
+    let (a_high, a_low) = (a >> 64, a & MASK);
+    let (b_high, b_low) = (b >> 64, b & MASK);
+
+    let low_low = a_low * b_low;
+    let high_low = a_high * b_low;
+    let low_high = a_low * b_high;
+    let high_high = a_high * b_high;
+
+    let middle = (low_low >> 64) + (high_low & MASK) + (low_high & MASK);
+    let low = (middle << 64) | (low_low & MASK);
+    let high = high_high + (high_low >> 64) + (low_high >> 64) + (middle >> 64);
+    (high, low)
+}
+
+/// Divide the 256-bit `high:low` by `divisor`, which must be greater than
+/// `high`, returning the quotient and remainder.
+fn div_wide(high: u128, low: u128, divisor: u128) -> (u128, u128) {
+    if high == 0 {
+        return (low / divisor, low % divisor);
+    }
+    let mut remainder = high;
+    let mut quotient = 0u128;
+    for bit in (0..128).rev() {
+        // The remainder is below the divisor, so after shifting it in the
+        // next bit it is below twice the divisor, and one subtraction
+        // suffices even when the shift carries out of 128 bits.
+        let carry = remainder >> 127;
+        remainder = (remainder << 1) | ((low >> bit) & 1);
+        quotient <<= 1;
+        if carry == 1 || remainder >= divisor {
+            remainder = remainder.wrapping_sub(divisor);
+            quotient |= 1;
+        }
+    }
+    (quotient, remainder)
+}
+
+fn to_u64(value: u128) -> Result<u64, ProgramError> {
+    u64::try_from(value).map_err(|_| ProgramError::ArithmeticOverflow)
+}
+
+/// A non-negative fraction with 18 decimals: `Wad(WAD)` is one.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    Eq,
+    PartialEq,
+    PartialOrd,
+    Ord,
+    Serialize,
+    Deserialize,
+    BorshSerialize,
+    BorshDeserialize,
+)]
+pub struct Wad(pub u128);
+
+impl Wad {
+    pub const ZERO: Self = Self(0);
+    pub const ONE: Self = Self(WAD);
+
+    pub fn from_integer(value: u64) -> Self {
+        Self(value as u128 * WAD)
+    }
+
+    /// `numerator / denominator`.
+    pub fn from_ratio(
+        numerator: u128,
+        denominator: u128,
+        rounding: Rounding,
+    ) -> Result<Self, ProgramError> {
+        mul_div(numerator, WAD, denominator, rounding).map(Self)
+    }
+
+    /// A whole percentage, such as the `apy` fields programs have stored.
+    pub fn from_percent(percent: u64) -> Self {
+        Self(percent as u128 * (WAD / 100))
+    }
+
+    pub fn checked_add(self, other: Self) -> Result<Self, ProgramError> {
+        self.0
+            .checked_add(other.0)
+            .map(Self)
+            .ok_or(ProgramError::ArithmeticOverflow)
+    }
+
+    pub fn checked_sub(self, other: Self) -> Result<Self, ProgramError> {
+        self.0
+            .checked_sub(other.0)
+            .map(Self)
+            .ok_or(ProgramError::ArithmeticOverflow)
+    }
+
+    pub fn checked_mul(self, other: Self, rounding: Rounding) -> Result<Self, ProgramError> {
+        mul_div(self.0, other.0, WAD, rounding).map(Self)
+    }
+
+    pub fn checked_div(self, other: Self, rounding: Rounding) -> Result<Self, ProgramError> {
+        mul_div(self.0, WAD, other.0, rounding).map(Self)
+    }
+
+    /// `amount * self`, in whole units of `amount`.
+    pub fn checked_mul_int(self, amount: u64, rounding: Rounding) -> Result<u64, ProgramError> {
+        to_u64(mul_div(amount as u128, self.0, WAD, rounding)?)
+    }
+
+    /// The whole part, rounded as asked.
+    pub fn to_integer(self, rounding: Rounding) -> Result<u64, ProgramError> {
+        self.checked_mul_int(1, rounding)
+    }
+
+    /// `self` raised to `exponent` by repeated squaring, rounding down after
+    /// each multiplication.
+    pub fn checked_pow(self, mut exponent: u64) -> Result<Self, ProgramError> {
+        let mut base = self;
+        let mut result = Self::ONE;
+        while exponent > 0 {
+            if exponent & 1 == 1 {
+                result = result.checked_mul(base, Rounding::Down)?;
+            }
+            exponent >>= 1;
+            if exponent > 0 {
+                base = base.checked_mul(base, Rounding::Down)?;
+            }
+        }
+        Ok(result)
+    }
+
+    /// What one unit grows to over `seconds` at `annual_rate`, compounded
+    /// every second: `(1 + annual_rate / SECONDS_PER_YEAR) ^ seconds`. Rounds
+    /// down throughout, so it never overstates growth.
+    pub fn compound_factor(annual_rate: Self, seconds: u64) -> Result<Self, ProgramError> {
+        let rate_per_second = Self(annual_rate.0 / SECONDS_PER_YEAR as u128);
+        Self::ONE.checked_add(rate_per_second)?.checked_pow(seconds)
+    }
+}
+
+/// A fraction in basis points: `Bps(10_000)` is one, `Bps(1)` is 0.01%.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    Eq,
+    PartialEq,
+    PartialOrd,
+    Ord,
+    Serialize,
+    Deserialize,
+    BorshSerialize,
+    BorshDeserialize,
+)]
+pub struct Bps(pub u64);
+
+impl Bps {
+    pub fn to_wad(self) -> Wad {
+        Wad(self.0 as u128 * (WAD / BPS))
+    }
+
+    /// `amount * self`, in whole units of `amount`.
+    pub fn checked_mul_int(self, amount: u64, rounding: Rounding) -> Result<u64, ProgramError> {
+        to_u64(mul_div(amount as u128, self.0 as u128, BPS, rounding)?)
+    }
+}
+
+/// Extension for scaling token amounts by a ratio of integers.
+pub trait CheckedMulDiv: Sized {
+    /// `self * numerator / denominator`, rounded as asked.
+    fn checked_mul_div(
+        self,
+        numerator: Self,
+        denominator: Self,
+        rounding: Rounding,
+    ) -> Result<Self, ProgramError>;
+}
+
+impl CheckedMulDiv for u64 {
+    fn checked_mul_div(
+        self,
+        numerator: u64,
+        denominator: u64,
+        rounding: Rounding,
+    ) -> Result<u64, ProgramError> {
+        to_u64(mul_div(
+            self as u128,
+            numerator as u128,
+            denominator as u128,
+            rounding,
+        )?)
+    }
+}
+
+impl CheckedMulDiv for u128 {
+    fn checked_mul_div(
+        self,
+        numerator: u128,
+        denominator: u128,
+        rounding: Rounding,
+    ) -> Result<u128, ProgramError> {
+        mul_div(self, numerator, denominator, rounding)
+    }
+}
+
+/// Simple interest on `principal` at `annual_rate` over `seconds`, rounded
+/// once as asked.
+pub fn simple_interest(
+    principal: u64,
+    annual_rate: Wad,
+    seconds: u64,
+    rounding: Rounding,
+) -> Result<u64, ProgramError> {
+    // `principal * seconds` fits in 128 bits, as does `WAD * SECONDS_PER_YEAR`.
+    to_u64(mul_div(
+        principal as u128 * seconds as u128,
+        annual_rate.0,
+        WAD * SECONDS_PER_YEAR as u128,
+        rounding,
+    )?)
+}
+
+/// Interest on `principal` at `annual_rate` over `seconds`, compounded every
+/// second and rounded down.
+pub fn compound_interest(
+    principal: u64,
+    annual_rate: Wad,
+    seconds: u64,
+) -> Result<u64, ProgramError> {
+    let factor = Wad::compound_factor(annual_rate, seconds)?;
+    factor
+        .checked_sub(Wad::ONE)?
+        .checked_mul_int(principal, Rounding::Down)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        compound_interest, mul_div, simple_interest, Bps, CheckedMulDiv, Rounding, Wad,
+        SECONDS_PER_YEAR, WAD,
+    };
+    use crate::{clock::SECONDS_PER_DAY, program_error::ProgramError};
+
+    use num_bigint::BigUint;
+    use proptest::prelude::*;
+
+    fn reference_mul_div(a: u128, b: u128, denominator: u128, rounding: Rounding) -> Option<u128> {
+        if denominator == 0 {
+            return None;
+        }
+        let product = BigUint::from(a) * BigUint::from(b);
+        let denominator = BigUint::from(denominator);
+        let quotient = &product / &denominator;
+        let remainder = &product % &denominator;
+        let round_up = match rounding {
+            Rounding::Down => false,
+            Rounding::Up => remainder != BigUint::from(0u8),
+            Rounding::Nearest => remainder * 2u8 >= denominator,
+        };
+        let result = if round_up { quotient + 1u8 } else { quotient };
+        u128::try_from(result).ok()
+    }
+
+    fn rounding() -> impl Strategy<Value = Rounding> {
+        prop_oneof![
+            Just(Rounding::Down),
+            Just(Rounding::Up),
+            Just(Rounding::Nearest)
+        ]
+    }
+
+    fn wide() -> impl Strategy<Value = u128> {
+        prop_oneof![
+            any::<u128>(),
+            any::<u64>().prop_map(u128::from),
+            0..1_000u128
+        ]
+    }
+
+    #[test]
+    fn test_rounding() {
+        assert_eq!(mul_div(7, 1, 2, Rounding::Down), Ok(3));
+        assert_eq!(mul_div(7, 1, 2, Rounding::Up), Ok(4));
+        assert_eq!(mul_div(7, 1, 2, Rounding::Nearest), Ok(4));
+        assert_eq!(mul_div(5, 1, 3, Rounding::Nearest), Ok(2));
+        assert_eq!(mul_div(4, 1, 3, Rounding::Nearest), Ok(1));
+        assert_eq!(
+            mul_div(1, 1, 0, Rounding::Down),
+            Err(ProgramError::ArithmeticOverflow)
+        );
+        assert_eq!(
+            mul_div(u128::MAX, u128::MAX, u128::MAX, Rounding::Up),
+            Ok(u128::MAX)
+        );
+        assert_eq!(
+            mul_div(u128::MAX, 2, 1, Rounding::Down),
+            Err(ProgramError::ArithmeticOverflow)
+        );
+        assert_eq!(
+            u64::MAX.checked_mul_div(3, 2, Rounding::Down),
+            Err(ProgramError::ArithmeticOverflow)
+        );
+    }
+
+    #[test]
+    fn test_rates() {
+        assert_eq!(
+            Bps(750).to_wad(),
+            Wad::from_ratio(75, 1000, Rounding::Down).unwrap()
+        );
+        assert_eq!(Wad::from_percent(12), Bps(1200).to_wad());
+        assert_eq!(Bps(250).checked_mul_int(1_000, Rounding::Down), Ok(25));
+        assert_eq!(Bps(1).checked_mul_int(1, Rounding::Up), Ok(1));
+
+        // Whole-percent, whole-day math lost this entirely.
+        let ten_percent = Wad::from_percent(10);
+        assert_eq!(
+            simple_interest(1_000, ten_percent, SECONDS_PER_DAY, Rounding::Down),
+            Ok(0)
+        );
+        assert_eq!(
+            simple_interest(1_000, ten_percent, SECONDS_PER_DAY, Rounding::Up),
+            Ok(1)
+        );
+        assert_eq!(
+            simple_interest(1_000_000, ten_percent, SECONDS_PER_DAY, Rounding::Down),
+            Ok(273)
+        );
+        assert_eq!(
+            simple_interest(1_000_000, ten_percent, SECONDS_PER_YEAR, Rounding::Down),
+            Ok(100_000)
+        );
+        // Rounded once: 2.5 a year for two years, not 2 a year.
+        assert_eq!(
+            simple_interest(
+                5,
+                Wad::from_percent(50),
+                2 * SECONDS_PER_YEAR,
+                Rounding::Down
+            ),
+            Ok(5)
+        );
+    }
+
+    #[test]
+    fn test_compound_interest() {
+        let rate = Wad::from_percent(5);
+        assert_eq!(Wad::compound_factor(rate, 0), Ok(Wad::ONE));
+        assert_eq!(
+            Wad::compound_factor(Wad::ZERO, SECONDS_PER_YEAR),
+            Ok(Wad::ONE)
+        );
+
+        // Compounding every second over a year approaches e^0.05.
+        let factor = Wad::compound_factor(rate, SECONDS_PER_YEAR).unwrap();
+        let continuous = 1_051_271_096_376_024_039u128;
+        assert!(factor.0 <= continuous);
+        assert!(continuous - factor.0 < WAD / 1_000_000_000);
+
+        let principal = 1_000_000_000;
+        let interest = compound_interest(principal, rate, SECONDS_PER_YEAR).unwrap();
+        assert!(
+            interest > simple_interest(principal, rate, SECONDS_PER_YEAR, Rounding::Down).unwrap()
+        );
+        assert_eq!(interest, 51_271_096);
+
+        assert_eq!(
+            Wad::compound_factor(Wad::from_integer(1_000_000), SECONDS_PER_YEAR),
+            Err(ProgramError::ArithmeticOverflow)
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn fuzz_mul_div(a in wide(), b in wide(), denominator in wide(), rounding in rounding()) {
+            prop_assert_eq!(
+                mul_div(a, b, denominator, rounding).ok(),
+                reference_mul_div(a, b, denominator, rounding)
+            );
+        }
+
+        #[test]
+        fn fuzz_wad(a in wide(), b in wide(), rounding in rounding()) {
+            prop_assert_eq!(
+                Wad(a).checked_mul(Wad(b), rounding).ok(),
+                reference_mul_div(a, b, WAD, rounding).map(Wad)
+            );
+            prop_assert_eq!(
+                Wad(a).checked_div(Wad(b), rounding).ok(),
+                reference_mul_div(a, WAD, b, rounding).map(Wad)
+            );
+            prop_assert_eq!(
+                Wad(a).checked_add(Wad(b)).ok(),
+                a.checked_add(b).map(Wad)
+            );
+        }
+
+        #[test]
+        fn fuzz_mul_int(amount in any::<u64>(), rate in wide(), bps in any::<u64>(), rounding in rounding()) {
+            prop_assert_eq!(
+                Wad(rate).checked_mul_int(amount, rounding).ok(),
+                reference_mul_div(amount.into(), rate, WAD, rounding)
+                    .and_then(|value| u64::try_from(value).ok())
+            );
+            prop_assert_eq!(
+                Bps(bps).checked_mul_int(amount, rounding).ok(),
+                reference_mul_div(amount.into(), bps.into(), 10_000, rounding)
+                    .and_then(|value| u64::try_from(value).ok())
+            );
+        }
+
+        #[test]
+        fn fuzz_simple_interest(principal in any::<u64>(), rate in wide(), seconds in any::<u64>(), rounding in rounding()) {
+            prop_assert_eq!(
+                simple_interest(principal, Wad(rate), seconds, rounding).ok(),
+                reference_mul_div(
+                    principal as u128 * seconds as u128,
+                    rate,
+                    WAD * SECONDS_PER_YEAR as u128,
+                    rounding
+                )
+                .and_then(|value| u64::try_from(value).ok())
+            );
+        }
+
+        #[test]
+        fn fuzz_compound_factor(rate_bps in 0..100_000u64, first in 0..SECONDS_PER_YEAR, second in 0..SECONDS_PER_YEAR) {
+            let rate = Bps(rate_bps).to_wad();
+            let factor = Wad::compound_factor(rate, first).unwrap();
+            let longer = Wad::compound_factor(rate, first + second).unwrap();
+            prop_assert!(factor >= Wad::ONE);
+            prop_assert!(longer >= factor);
+
+            // Splitting the period changes nothing beyond rounding.
+            let split = factor
+                .checked_mul(Wad::compound_factor(rate, second).unwrap(), Rounding::Down)
+                .unwrap();
+            prop_assert!(split.0.abs_diff(longer.0) <= longer.0 / 1_000_000_000_000);
+        }
+    }
+}
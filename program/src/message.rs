@@ -1,9 +1,11 @@
+use crate::codec::{self, DecodeError, EncodeError, Reader};
+use crate::hash::{self, Hash};
 use crate::instruction::Instruction;
 use crate::pubkey::Pubkey;
+use crate::sanitized::{SanitizeError, SanitizedMessage};
 
 use borsh::{BorshDeserialize, BorshSerialize};
 use serde::{Deserialize, Serialize};
-use sha256::digest;
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, BorshSerialize, BorshDeserialize)]
 pub struct Message {
@@ -12,50 +14,71 @@ pub struct Message {
 }
 
 impl Message {
-    pub fn serialize(&self) -> Vec<u8> {
+    pub fn new(signers: Vec<Pubkey>, instructions: Vec<Instruction>) -> Self {
+        Self {
+            signers,
+            instructions,
+        }
+    }
+
+    pub fn serialize(&self) -> Result<Vec<u8>, EncodeError> {
         let mut serialized = vec![];
 
-        serialized.push(self.signers.len() as u8);
+        serialized.push(codec::u8_len("message.signers", self.signers.len())?);
         for signer in self.signers.iter() {
             serialized.extend(&signer.serialize());
         }
-        serialized.push(self.instructions.len() as u8);
+        serialized.push(codec::u8_len(
+            "message.instructions",
+            self.instructions.len(),
+        )?);
         for instruction in self.instructions.iter() {
-            serialized.extend(&instruction.serialize());
+            serialized.extend(&instruction.serialize()?);
         }
 
-        serialized
+        Ok(serialized)
     }
 
-    pub fn from_slice(data: &[u8]) -> Self {
-        let mut size = 0;
+    pub fn from_slice(data: &[u8]) -> Result<Self, DecodeError> {
+        Self::decode(&mut Reader::new(data))
+    }
 
-        let signers_len = data[size] as usize;
-        size += 1;
+    /// Decodes a message from the front of `reader`, leaving it positioned
+    /// just past the last instruction.
+    pub fn decode(reader: &mut Reader) -> Result<Self, DecodeError> {
+        let signers_len = reader.read_u8("message.signers.len")? as usize;
         let mut signers = Vec::with_capacity(signers_len);
         for _ in 0..signers_len {
-            signers.push(Pubkey::from_slice(&data[size..(size + 32)]));
-            size += 32;
+            signers.push(Pubkey::from(reader.read_array("message.signers")?));
         }
 
-        let instructions_len = data[size] as usize;
-        size += 1;
+        let instructions_len = reader.read_u8("message.instructions.len")? as usize;
         let mut instructions = Vec::with_capacity(instructions_len);
         for _ in 0..instructions_len {
-            instructions.push(Instruction::from_slice(&data[size..]));
-            size += instructions.last().unwrap().serialize().len();
+            instructions.push(Instruction::decode(reader)?);
         }
 
-        Self {
+        Ok(Self {
             signers,
             instructions,
-        }
+        })
+    }
+
+    /// Compile the message into a deduplicated account table with
+    /// index-based instructions; see [`SanitizedMessage::compile`].
+    pub fn compile(&self) -> Result<SanitizedMessage, SanitizeError> {
+        SanitizedMessage::compile(self)
+    }
+
+    pub fn hash(&self) -> Result<Hash, EncodeError> {
+        Ok(Hash::digest(&self.serialize()?))
     }
 
-    pub fn hash(&self) -> Vec<u8> {
-        let serialized_message = self.serialize();
-        let first_hash = digest(serialized_message);
-        digest(first_hash.as_bytes()).as_bytes().to_vec()
+    /// The digest signers of a version 0 transaction sign: the bytes of the
+    /// [legacy hex digest](hash::legacy_digest) of the message. Version 1
+    /// transactions sign [`SanitizedMessage::hash`] instead.
+    pub fn legacy_hash(&self) -> Result<Vec<u8>, EncodeError> {
+        Ok(hash::legacy_digest(&self.serialize()?).into_bytes())
     }
 }
 
@@ -82,14 +105,20 @@ mod tests {
             signers: vec![],
         };
 
-        assert_eq!(message, Message::from_slice(&message.serialize()));
+        assert_eq!(
+            message,
+            Message::from_slice(&message.serialize().unwrap()).unwrap()
+        );
 
         let message = Message {
             instructions: vec![instruction],
             signers: vec![Pubkey::system_program()],
         };
 
-        assert_eq!(message, Message::from_slice(&message.serialize()));
+        assert_eq!(
+            message,
+            Message::from_slice(&message.serialize().unwrap()).unwrap()
+        );
     }
 
     use proptest::prelude::*;
@@ -102,7 +131,7 @@ mod tests {
             account_pubkeys in prop::collection::vec(prop::array::uniform32(any::<u8>()), 0..10),
             is_signer_flags in prop::collection::vec(any::<bool>(), 0..10),
             is_writable_flags in prop::collection::vec(any::<bool>(), 0..10),
-            instruction_data in prop::collection::vec(any::<u8>(), 0..1024)
+            instruction_data in prop::collection::vec(any::<u8>(), 0..1024),
         ) {
             let instructions: Vec<Instruction> = program_ids.into_iter()
                 .zip(account_pubkeys.into_iter())
@@ -130,10 +159,15 @@ mod tests {
                 instructions,
             };
 
-            let serialized = message.serialize();
-            let deserialized = Message::from_slice(&serialized);
+            let serialized = message.serialize().unwrap();
+            let deserialized = Message::from_slice(&serialized).unwrap();
 
             assert_eq!(message, deserialized);
         }
+
+        #[test]
+        fn fuzz_deserialize_arbitrary_message(data in prop::collection::vec(any::<u8>(), 0..1024)) {
+            let _ = Message::from_slice(&data);
+        }
     }
 }
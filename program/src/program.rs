@@ -1,4 +1,6 @@
 use bitcoin::Transaction;
+use borsh::{BorshDeserialize, BorshSerialize};
+use thiserror::Error;
 
 use crate::instruction::Instruction;
 use crate::program_error::ProgramError;
@@ -42,7 +44,26 @@ pub fn invoke(instruction: &Instruction, account_infos: &[AccountInfo]) -> Progr
     }
 
     #[cfg(not(target_os = "solana"))]
-    crate::program_stubs::sol_invoke_signed_rust(instruction, account_infos)
+    crate::program_stubs::sol_invoke_signed_rust(instruction, account_infos, &[])
+}
+
+/// Experimental: invoke a cross-program instruction with program signatures.
+///
+/// Each entry of `signers_seeds` is the seed list, bump seed included, of a
+/// program address derived from the calling program's id with
+/// [`Pubkey::create_program_address`]. Those addresses are treated as
+/// signers of `instruction`.
+///
+/// Only [`ProgramTest`](crate::program_test::ProgramTest) honours the seeds:
+/// the runtime's syscall takes none, so this is native only, behind the
+/// `experimental-pda` feature.
+#[cfg(all(not(target_os = "solana"), any(test, feature = "experimental-pda")))]
+pub fn invoke_signed(
+    instruction: &Instruction,
+    account_infos: &[AccountInfo],
+    signers_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    crate::program_stubs::sol_invoke_signed_rust(instruction, account_infos, signers_seeds)
 }
 
 pub fn next_account_info<'a, 'b, I: Iterator<Item = &'a AccountInfo<'b>>>(
@@ -57,7 +78,7 @@ pub fn set_transaction_to_sign(
     accounts: &[AccountInfo],
     transaction_to_sign: TransactionToSign,
 ) -> ProgramResult {
-    let serialized_transaction_to_sign = &transaction_to_sign.serialise();
+    let serialized_transaction_to_sign = &transaction_to_sign.serialise()?;
     #[cfg(target_os = "solana")]
     let result = unsafe {
         crate::syscalls::arch_set_transaction_to_sign(
@@ -66,10 +87,7 @@ pub fn set_transaction_to_sign(
         )
     };
     #[cfg(not(target_os = "solana"))]
-    let result = crate::program_stubs::arch_set_transaction_to_sign(
-        serialized_transaction_to_sign.as_ptr(),
-        serialized_transaction_to_sign.len(),
-    );
+    let result = crate::program_stubs::arch_set_transaction_to_sign(serialized_transaction_to_sign);
 
     match result {
         crate::entrypoint::SUCCESS => {
@@ -78,13 +96,7 @@ pub fn set_transaction_to_sign(
             for input in transaction_to_sign.inputs_to_sign {
                 if let Some(account) = accounts.iter().find(|account| *account.key == input.signer)
                 {
-                    account.set_utxo(&UtxoMeta::from(
-                        hex::decode(tx.compute_txid().to_string())
-                            .expect("failed to decode_hex")
-                            .try_into()
-                            .expect("failed to try_into"),
-                        input.index,
-                    ));
+                    account.set_utxo(&UtxoMeta::from_outpoint(tx.compute_txid(), input.index));
                 }
             }
             Ok(())
@@ -104,7 +116,13 @@ pub const MAX_RETURN_DATA: usize = 1024;
 /// The maximum size of return data is [`MAX_RETURN_DATA`]. Return data is
 /// retrieved by the caller with [`get_return_data`].
 pub fn set_return_data(data: &[u8]) {
-    unsafe { crate::syscalls::sol_set_return_data(data.as_ptr(), data.len() as u64) };
+    #[cfg(target_os = "solana")]
+    unsafe {
+        crate::syscalls::sol_set_return_data(data.as_ptr(), data.len() as u64)
+    };
+
+    #[cfg(not(target_os = "solana"))]
+    crate::program_stubs::sol_set_return_data(data);
 }
 
 /// Get the return data from an invoked program.
@@ -137,47 +155,132 @@ pub fn set_return_data(data: &[u8]) {
 ///
 /// [rdp]: https://docs.solanalabs.com/proposals/return-data
 pub fn get_return_data() -> Option<(Pubkey, Vec<u8>)> {
-    use std::cmp::min;
+    #[cfg(target_os = "solana")]
+    {
+        use std::cmp::min;
 
-    let mut buf = [0u8; MAX_RETURN_DATA];
-    let mut program_id = Pubkey::default();
+        let mut buf = [0u8; MAX_RETURN_DATA];
+        let mut program_id = Pubkey::default();
 
-    let size = unsafe {
-        crate::syscalls::sol_get_return_data(buf.as_mut_ptr(), buf.len() as u64, &mut program_id)
-    };
+        let size = unsafe {
+            crate::syscalls::sol_get_return_data(
+                buf.as_mut_ptr(),
+                buf.len() as u64,
+                &mut program_id,
+            )
+        };
+
+        if size == 0 {
+            None
+        } else {
+            let size = min(size as usize, MAX_RETURN_DATA);
+            Some((program_id, buf[..size as usize].to_vec()))
+        }
+    }
+
+    #[cfg(not(target_os = "solana"))]
+    crate::program_stubs::sol_get_return_data()
+}
 
-    if size == 0 {
-        None
-    } else {
-        let size = min(size as usize, MAX_RETURN_DATA);
-        Some((program_id, buf[..size as usize].to_vec()))
+/// Set the running program's return data to the borsh encoding of `value`.
+///
+/// Fails with [`ProgramError::InvalidArgument`] if the encoding is longer
+/// than [`MAX_RETURN_DATA`].
+pub fn set_return_data_borsh<T: BorshSerialize>(value: &T) -> ProgramResult {
+    let data = borsh::to_vec(value).map_err(|e| ProgramError::BorshIoError(e.to_string()))?;
+    if data.len() > MAX_RETURN_DATA {
+        return Err(ProgramError::InvalidArgument);
     }
+    set_return_data(&data);
+    Ok(())
 }
 
-pub fn get_bitcoin_tx(txid: [u8; 32]) -> Option<Vec<u8>> {
-    use std::cmp::min;
+/// Decode the return data from an invoked program as a `T`, along with the
+/// id of the program that set it.
+///
+/// Returns `None` if there is no return data; see [`get_return_data`] for
+/// which program it may have come from.
+pub fn get_return_data_borsh<T: BorshDeserialize>() -> Result<Option<(Pubkey, T)>, ProgramError> {
+    get_return_data()
+        .map(|(program_id, data)| {
+            T::try_from_slice(&data)
+                .map(|value| (program_id, value))
+                .map_err(|e| ProgramError::BorshIoError(e.to_string()))
+        })
+        .transpose()
+}
 
-    let mut buf = [0u8; MAX_BTC_TX_SIZE];
+/// Reasons a Bitcoin transaction could not be fetched.
+#[derive(Clone, Copy, Debug, Eq, Error, PartialEq)]
+pub enum BitcoinTxError {
+    #[error("bitcoin transaction not found")]
+    NotFound,
+    #[error("bitcoin transaction of {size} bytes does not fit in {capacity} bytes")]
+    TooLarge { size: usize, capacity: usize },
+}
+
+impl From<BitcoinTxError> for ProgramError {
+    fn from(_: BitcoinTxError) -> Self {
+        ProgramError::InvalidArgument
+    }
+}
 
+/// Copy as much of the transaction as fits into `buf`, returning its full size.
+fn arch_get_bitcoin_tx(buf: &mut [u8], txid: &[u8; 32]) -> Result<usize, BitcoinTxError> {
     #[cfg(target_os = "solana")]
     let size =
-        unsafe { crate::syscalls::arch_get_bitcoin_tx(buf.as_mut_ptr(), buf.len() as u64, &txid) };
+        unsafe { crate::syscalls::arch_get_bitcoin_tx(buf.as_mut_ptr(), buf.len() as u64, txid) };
 
     #[cfg(not(target_os = "solana"))]
-    let size = crate::program_stubs::arch_get_bitcoin_tx(buf.as_mut_ptr(), buf.len(), &txid);
+    let size = crate::program_stubs::arch_get_bitcoin_tx(buf, txid);
 
-    if size == 0 {
-        None
-    } else {
-        let size = min(size as usize, MAX_BTC_TX_SIZE);
-        Some(buf[..size as usize].to_vec())
+    match size {
+        0 => Err(BitcoinTxError::NotFound),
+        size => Ok(size as usize),
     }
 }
 
+/// The serialized size of a Bitcoin transaction, without fetching it.
+pub fn get_bitcoin_tx_size(txid: &[u8; 32]) -> Result<usize, BitcoinTxError> {
+    arch_get_bitcoin_tx(&mut [], txid)
+}
+
+/// Fetch a Bitcoin transaction into `buf`, returning its size.
+///
+/// Fails with [`BitcoinTxError::TooLarge`] rather than truncating if the
+/// transaction does not fit. [`get_bitcoin_tx_size`] gives the size needed.
+pub fn get_bitcoin_tx_into(txid: &[u8; 32], buf: &mut [u8]) -> Result<usize, BitcoinTxError> {
+    let size = arch_get_bitcoin_tx(buf, txid)?;
+    if size > buf.len() {
+        return Err(BitcoinTxError::TooLarge {
+            size,
+            capacity: buf.len(),
+        });
+    }
+    Ok(size)
+}
+
+/// Fetch a serialized Bitcoin transaction of up to [`MAX_BTC_TX_SIZE`] bytes.
+///
+/// Makes a single syscall. Larger transactions fail with
+/// [`BitcoinTxError::TooLarge`]; fetch them with [`get_bitcoin_tx_into`].
+pub fn get_bitcoin_tx(txid: [u8; 32]) -> Result<Vec<u8>, BitcoinTxError> {
+    let mut buf = vec![0; MAX_BTC_TX_SIZE];
+    let size = get_bitcoin_tx_into(&txid, &mut buf)?;
+    buf.truncate(size);
+    Ok(buf)
+}
+
 pub fn get_network_xonly_pubkey() -> [u8; 32] {
-    let mut buf = [0u8; 32];
-    let _ = unsafe { crate::syscalls::arch_get_network_xonly_pubkey(buf.as_mut_ptr()) };
-    buf
+    #[cfg(target_os = "solana")]
+    {
+        let mut buf = [0u8; 32];
+        let _ = unsafe { crate::syscalls::arch_get_network_xonly_pubkey(buf.as_mut_ptr()) };
+        buf
+    }
+
+    #[cfg(not(target_os = "solana"))]
+    crate::program_stubs::arch_get_network_xonly_pubkey()
 }
 
 pub fn validate_utxo_ownership(utxo: &UtxoMeta, owner: &Pubkey) -> bool {
@@ -188,26 +291,40 @@ pub fn validate_utxo_ownership(utxo: &UtxoMeta, owner: &Pubkey) -> bool {
 
     #[cfg(not(target_os = "solana"))]
     {
-        crate::program_stubs::arch_validate_utxo_ownership(utxo, owner) != 0
+        crate::program_stubs::arch_validate_utxo_ownership(utxo, owner)
     }
 }
 pub fn get_account_script_pubkey(pubkey: &Pubkey) -> [u8; 34] {
-    let mut buf = [0u8; 34];
-
     #[cfg(target_os = "solana")]
-    let _ = unsafe { crate::syscalls::arch_get_account_script_pubkey(buf.as_mut_ptr(), pubkey) };
+    {
+        let mut buf = [0u8; 34];
+        let _ =
+            unsafe { crate::syscalls::arch_get_account_script_pubkey(buf.as_mut_ptr(), pubkey) };
+        buf
+    }
 
     #[cfg(not(target_os = "solana"))]
-    crate::program_stubs::arch_get_account_script_pubkey(&mut buf, pubkey);
-    buf
+    crate::program_stubs::arch_get_account_script_pubkey(pubkey)
 }
 
 pub fn get_bitcoin_block_height() -> u64 {
-    unsafe { crate::syscalls::arch_get_bitcoin_block_height() }
+    #[cfg(target_os = "solana")]
+    unsafe {
+        crate::syscalls::arch_get_bitcoin_block_height()
+    }
+
+    #[cfg(not(target_os = "solana"))]
+    crate::program_stubs::arch_get_bitcoin_block_height()
 }
 
 pub fn get_clock() -> Clock {
-    let mut clock = Clock::default();
-    unsafe { crate::syscalls::arch_get_clock(&mut clock) };
-    clock
+    #[cfg(target_os = "solana")]
+    {
+        let mut clock = Clock::default();
+        unsafe { crate::syscalls::arch_get_clock(&mut clock) };
+        clock
+    }
+
+    #[cfg(not(target_os = "solana"))]
+    crate::program_stubs::arch_get_clock()
 }
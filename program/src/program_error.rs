@@ -1,3 +1,5 @@
+use std::collections::BTreeMap;
+
 use thiserror::Error;
 
 /// Reasons the program may fail
@@ -172,3 +174,171 @@ impl From<u64> for ProgramError {
         }
     }
 }
+
+/// A variant of a program's [`CustomError`] type.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ErrorCode {
+    /// The code returned in [`ProgramError::Custom`].
+    pub code: u32,
+    /// The variant's name, e.g. `"SenderMintMismatch"`.
+    pub name: &'static str,
+    pub message: &'static str,
+}
+
+/// A program-specific error returned to the runtime as [`ProgramError::Custom`].
+///
+/// Usually derived, which also implements `Display` and
+/// `From<Self> for ProgramError`:
+///
+/// ```
+/// use arch_program::program_error::{CustomError, ProgramError};
+///
+/// #[derive(Debug, CustomError)]
+/// #[custom_error(offset = 6000)]
+/// enum VaultError {
+///     #[custom_error(msg = "The vault is locked")]
+///     Locked,
+///     Empty,
+///     #[custom_error(code = 7000, msg = "Only the admin may do this")]
+///     NotAdmin,
+/// }
+///
+/// assert_eq!(ProgramError::from(VaultError::Empty), ProgramError::Custom(6001));
+/// assert_eq!(VaultError::NotAdmin.to_string(), "Only the admin may do this");
+/// assert!(matches!(VaultError::from_code(6000), Some(VaultError::Locked)));
+/// ```
+///
+/// Codes count up from the `offset` (0 by default) in declaration order, and a
+/// variant can pin its code with `code = N`. Variants without a `msg` use
+/// their name as the message.
+pub trait CustomError: Sized {
+    /// Every variant of the error, for registering with an [`ErrorRegistry`].
+    const ERROR_CODES: &'static [ErrorCode];
+
+    fn code(&self) -> u32;
+
+    fn from_code(code: u32) -> Option<Self>;
+
+    /// The error `error` holds, if it is one of this type's codes.
+    fn from_program_error(error: &ProgramError) -> Option<Self> {
+        match error {
+            ProgramError::Custom(code) => Self::from_code(*code),
+            _ => None,
+        }
+    }
+}
+
+pub use arch_program_macros::CustomError;
+
+/// Maps the codes of [`ProgramError::Custom`] back to the error variants of
+/// the programs a client talks to.
+#[derive(Clone, Debug, Default)]
+pub struct ErrorRegistry {
+    codes: BTreeMap<u32, ErrorCode>,
+}
+
+impl ErrorRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the codes of `E`. A code registered earlier by another type is
+    /// replaced.
+    pub fn register<E: CustomError>(mut self) -> Self {
+        for error_code in E::ERROR_CODES {
+            self.codes.insert(error_code.code, *error_code);
+        }
+        self
+    }
+
+    pub fn get(&self, code: u32) -> Option<&ErrorCode> {
+        self.codes.get(&code)
+    }
+
+    /// The registered variant `error` holds, if it is a custom error.
+    pub fn lookup(&self, error: &ProgramError) -> Option<&ErrorCode> {
+        match error {
+            ProgramError::Custom(code) => self.get(*code),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CustomError, ErrorCode, ErrorRegistry, ProgramError};
+
+    #[derive(Debug, PartialEq, CustomError)]
+    #[custom_error(offset = 501)]
+    enum TokenError {
+        #[custom_error(msg = "The sender is not owned by the program")]
+        SenderNotOwnedByProgram,
+        OwnerMismatch,
+        #[custom_error(code = 600, msg = "The mint is finished")]
+        MintFinished,
+        InsufficientBalance,
+    }
+
+    #[derive(Debug, PartialEq, CustomError)]
+    enum PoolError {
+        PoolClosed,
+    }
+
+    #[test]
+    fn test_custom_error_codes() {
+        assert_eq!(TokenError::SenderNotOwnedByProgram.code(), 501);
+        assert_eq!(TokenError::OwnerMismatch.code(), 502);
+        assert_eq!(TokenError::MintFinished.code(), 600);
+        assert_eq!(TokenError::InsufficientBalance.code(), 601);
+        assert_eq!(PoolError::PoolClosed.code(), 0);
+
+        for error_code in TokenError::ERROR_CODES {
+            let error = TokenError::from_code(error_code.code).unwrap();
+            assert_eq!(error.code(), error_code.code);
+            assert_eq!(format!("{:?}", error), error_code.name);
+            assert_eq!(error.to_string(), error_code.message);
+        }
+        assert_eq!(TokenError::from_code(503), None);
+    }
+
+    #[test]
+    fn test_custom_error_into_program_error() {
+        let error = ProgramError::from(TokenError::MintFinished);
+        assert_eq!(error, ProgramError::Custom(600));
+        assert_eq!(
+            TokenError::from_program_error(&error),
+            Some(TokenError::MintFinished)
+        );
+        assert_eq!(
+            TokenError::from_program_error(&ProgramError::InvalidArgument),
+            None
+        );
+        assert_eq!(TokenError::OwnerMismatch.to_string(), "OwnerMismatch");
+
+        // Custom errors survive the round trip through the runtime's return code.
+        let returned = ProgramError::from(u64::from(ProgramError::from(PoolError::PoolClosed)));
+        assert_eq!(
+            PoolError::from_program_error(&returned),
+            Some(PoolError::PoolClosed)
+        );
+    }
+
+    #[test]
+    fn test_error_registry() {
+        let registry = ErrorRegistry::new()
+            .register::<PoolError>()
+            .register::<TokenError>();
+
+        assert_eq!(
+            registry.lookup(&ProgramError::Custom(501)),
+            Some(&ErrorCode {
+                code: 501,
+                name: "SenderNotOwnedByProgram",
+                message: "The sender is not owned by the program",
+            })
+        );
+        assert_eq!(registry.get(0).map(|code| code.name), Some("PoolClosed"));
+        assert_eq!(registry.get(503), None);
+        assert_eq!(registry.lookup(&ProgramError::InvalidArgument), None);
+    }
+}
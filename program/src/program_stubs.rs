@@ -1,55 +1,418 @@
 //! Implementations of syscalls used when `arch-program` is built for non-SBF targets.
+//!
+//! Every syscall wrapper in this crate routes through the [`SyscallStubs`]
+//! installed with [`set_syscall_stubs`] when not running on-chain, so tests
+//! can supply Bitcoin transactions, a clock, a block height or a network key.
 
 #![cfg(not(target_os = "solana"))]
 #![allow(dead_code)]
 
-pub const UNIMPLEMENTED: u64 = 0;
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::sync::{Arc, RwLock};
+
 use crate::{
-    account::AccountInfo, entrypoint::ProgramResult, instruction::Instruction, pubkey::Pubkey,
-    utxo::UtxoMeta,
+    account::AccountInfo, clock::Clock, entrypoint::ProgramResult, instruction::Instruction,
+    pubkey::Pubkey, utxo::UtxoMeta,
 };
 
+pub const UNIMPLEMENTED: u64 = 0;
+
+/// The network key the default stubs report: the BIP341 point with no known
+/// discrete logarithm, a valid x-only key that nobody can sign for.
+pub const NATIVE_NETWORK_XONLY_PUBKEY: [u8; 32] = [
+    0x50, 0x92, 0x9b, 0x74, 0xc1, 0xa0, 0x49, 0x54, 0xb7, 0x8b, 0x4b, 0x60, 0x35, 0xe9, 0x7a, 0x5e,
+    0x07, 0x8a, 0x5a, 0x0f, 0x28, 0xec, 0x96, 0xd5, 0x47, 0xbf, 0xee, 0x9a, 0xce, 0x80, 0x3a, 0xc0,
+];
+
+/// The most logs the default stubs keep per thread. Once full, each new log
+/// drops the oldest one, so code that never takes its logs does not grow the
+/// buffer without bound.
+pub const MAX_CAPTURED_LOGS: usize = 10_000;
+
+static SYSCALL_STUBS: RwLock<Option<Arc<dyn SyscallStubs>>> = RwLock::new(None);
+
+thread_local! {
+    static NATIVE_CLOCK: Cell<Clock> = Cell::new(Clock::default());
+    static LOGS: RefCell<VecDeque<CapturedLog>> = const { RefCell::new(VecDeque::new()) };
+    static RETURN_DATA: RefCell<Option<(Pubkey, Vec<u8>)>> = const { RefCell::new(None) };
+}
+
+/// Set the clock returned by the default stubs on the current thread.
+pub fn set_native_clock(clock: Clock) {
+    NATIVE_CLOCK.with(|native_clock| native_clock.set(clock));
+}
+
+/// Move the current thread's native clock forward by `seconds`.
+pub fn advance_native_clock(seconds: u64) {
+    NATIVE_CLOCK.with(|native_clock| {
+        let mut clock = native_clock.get();
+        clock.unix_timestamp = clock.unix_timestamp.saturating_add_unsigned(seconds);
+        native_clock.set(clock);
+    });
+}
+
+/// A log entry captured by the default stubs.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CapturedLog {
+    /// A line logged with `msg!`, `sol_log` or `sol_log_64`.
+    Message(String),
+    /// The fields of a `sol_log_data` call.
+    Data(Vec<Vec<u8>>),
+}
+
+impl CapturedLog {
+    pub fn as_message(&self) -> Option<&str> {
+        match self {
+            CapturedLog::Message(message) => Some(message),
+            CapturedLog::Data(_) => None,
+        }
+    }
+
+    pub fn as_data(&self) -> Option<&[Vec<u8>]> {
+        match self {
+            CapturedLog::Message(_) => None,
+            CapturedLog::Data(fields) => Some(fields),
+        }
+    }
+}
+
+fn capture_log(log: CapturedLog) {
+    LOGS.with(|logs| {
+        let mut logs = logs.borrow_mut();
+        if logs.len() == MAX_CAPTURED_LOGS {
+            logs.pop_front();
+        }
+        logs.push_back(log);
+    });
+}
+
+/// The logs the default stubs captured on the current thread, without
+/// taking them. Only the last [`MAX_CAPTURED_LOGS`] are kept.
+pub fn logs() -> Vec<CapturedLog> {
+    LOGS.with(|logs| logs.borrow().iter().cloned().collect())
+}
+
+/// Take every log the default stubs captured on the current thread.
+pub fn take_logs() -> Vec<CapturedLog> {
+    LOGS.with(|logs| logs.take().into())
+}
+
+/// Take the fields of every `sol_log_data` call the default stubs captured on
+/// the current thread, leaving the messages.
+pub fn take_log_data() -> Vec<Vec<Vec<u8>>> {
+    LOGS.with(|logs| {
+        let (data, messages) = logs
+            .take()
+            .into_iter()
+            .partition::<VecDeque<_>, _>(|log| matches!(log, CapturedLog::Data(_)));
+        *logs.borrow_mut() = messages;
+        data.into_iter()
+            .filter_map(|log| match log {
+                CapturedLog::Data(fields) => Some(fields),
+                CapturedLog::Message(_) => None,
+            })
+            .collect()
+    })
+}
+
+/// Run `f` against an empty log buffer and take what it logged, putting back
+/// the logs captured before.
+pub(crate) fn capture_logs<R>(f: impl FnOnce() -> R) -> (R, Vec<CapturedLog>) {
+    let outer = LOGS.with(|logs| logs.take());
+    let result = f();
+    let captured = LOGS.with(|logs| logs.replace(outer));
+    (result, captured.into())
+}
+
+/// Clear the return data the default stubs hold for the current thread, as
+/// the runtime does before every instruction.
+pub(crate) fn clear_return_data() {
+    RETURN_DATA.with(|return_data| return_data.take());
+}
+
+/// Install the syscall stubs used by native builds, returning the previous ones.
+pub fn set_syscall_stubs(syscall_stubs: Arc<dyn SyscallStubs>) -> Arc<dyn SyscallStubs> {
+    let mut current = SYSCALL_STUBS.write().unwrap();
+    current
+        .replace(syscall_stubs)
+        .unwrap_or_else(|| Arc::new(DefaultSyscallStubs))
+}
+
+/// Run `f` with `syscall_stubs` installed, restoring the previous stubs after.
+///
+/// Tests run in parallel and the stubs are global, so tests that install
+/// stubs take turns through this.
+#[cfg(test)]
+pub(crate) fn with_syscall_stubs<R>(
+    syscall_stubs: Arc<dyn SyscallStubs>,
+    f: impl FnOnce() -> R,
+) -> R {
+    static TEST_STUBS_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+    let _guard = TEST_STUBS_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let previous = set_syscall_stubs(syscall_stubs);
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(f));
+    set_syscall_stubs(previous);
+    result.unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+}
+
+/// The stubs are cloned out of the lock before use so a stub that re-enters
+/// the program, like a CPI, can itself make syscalls.
+fn syscall_stubs() -> Arc<dyn SyscallStubs> {
+    SYSCALL_STUBS
+        .read()
+        .unwrap()
+        .clone()
+        .unwrap_or_else(|| Arc::new(DefaultSyscallStubs))
+}
+
+/// Builtin return values mirror the on-chain syscalls, so the wrappers in
+/// [`crate::program`] behave the same on both targets.
+///
+/// The default logging stubs print to stdout and also capture the last
+/// [`MAX_CAPTURED_LOGS`] lines on the current thread, to be inspected with
+/// [`logs`] or [`take_logs`].
+pub trait SyscallStubs: Sync + Send {
+    fn sol_log(&self, message: &str) {
+        capture_log(CapturedLog::Message(message.to_string()));
+        println!("{message}");
+    }
+    fn sol_log_64(&self, arg1: u64, arg2: u64, arg3: u64, arg4: u64, arg5: u64) {
+        self.sol_log(&format!(
+            "{arg1:#x}, {arg2:#x}, {arg3:#x}, {arg4:#x}, {arg5:#x}"
+        ));
+    }
+    fn sol_log_data(&self, data: &[&[u8]]) {
+        capture_log(CapturedLog::Data(
+            data.iter().map(|field| field.to_vec()).collect(),
+        ));
+        let fields: Vec<String> = data.iter().map(hex::encode).collect();
+        println!("data: {}", fields.join(" "));
+    }
+    fn sol_invoke_signed(
+        &self,
+        instruction: &Instruction,
+        account_infos: &[AccountInfo],
+        signers_seeds: &[&[&[u8]]],
+    ) -> ProgramResult {
+        crate::program_test::invoke(instruction, account_infos, signers_seeds).unwrap_or_else(
+            || {
+                self.sol_log("SyscallStubs: sol_invoke_signed() not available");
+                Ok(())
+            },
+        )
+    }
+    /// Keeps the data for the current thread, attributed to the program a
+    /// [`ProgramTest`](crate::program_test::ProgramTest) is running.
+    fn sol_set_return_data(&self, data: &[u8]) {
+        let program_id = crate::program_test::current_program_id().unwrap_or_default();
+        RETURN_DATA.with(|return_data| {
+            *return_data.borrow_mut() = (!data.is_empty()).then(|| (program_id, data.to_vec()))
+        });
+    }
+    fn sol_get_return_data(&self) -> Option<(Pubkey, Vec<u8>)> {
+        RETURN_DATA.with(|return_data| return_data.borrow().clone())
+    }
+    fn arch_set_transaction_to_sign(&self, _transaction_to_sign: &[u8]) -> u64 {
+        self.sol_log("SyscallStubs: arch_set_transaction_to_sign() not available");
+        UNIMPLEMENTED
+    }
+    /// Copies as much of the transaction as fits into `buf` and returns its
+    /// full length, or 0 if the transaction is unknown.
+    fn arch_get_bitcoin_tx(&self, _buf: &mut [u8], _txid: &[u8; 32]) -> u64 {
+        self.sol_log("SyscallStubs: arch_get_bitcoin_tx() not available");
+        UNIMPLEMENTED
+    }
+    fn arch_get_network_xonly_pubkey(&self) -> [u8; 32] {
+        NATIVE_NETWORK_XONLY_PUBKEY
+    }
+    fn arch_validate_utxo_ownership(&self, _utxo: &UtxoMeta, _owner: &Pubkey) -> bool {
+        false
+    }
+    fn arch_get_account_script_pubkey(&self, _pubkey: &Pubkey) -> [u8; 34] {
+        [0; 34]
+    }
+    fn arch_get_bitcoin_block_height(&self) -> u64 {
+        0
+    }
+    fn arch_get_clock(&self) -> Clock {
+        NATIVE_CLOCK.with(Cell::get)
+    }
+}
+
+struct DefaultSyscallStubs;
+impl SyscallStubs for DefaultSyscallStubs {}
+
 pub(crate) fn sol_log(message: &str) {
-    println!("{message}");
+    syscall_stubs().sol_log(message);
+}
+pub(crate) fn sol_log_64(arg1: u64, arg2: u64, arg3: u64, arg4: u64, arg5: u64) {
+    syscall_stubs().sol_log_64(arg1, arg2, arg3, arg4, arg5);
+}
+pub(crate) fn sol_log_data(data: &[&[u8]]) {
+    syscall_stubs().sol_log_data(data);
 }
-pub(crate) fn sol_log_64_(arg1: u64, arg2: u64, arg3: u64, arg4: u64, arg5: u64) {
-    sol_log(&format!("{arg1:?}, {arg2:?},{arg3:?},{arg4:?},{arg5:?}"))
+pub(crate) fn sol_set_return_data(data: &[u8]) {
+    syscall_stubs().sol_set_return_data(data);
 }
-pub(crate) fn sol_set_return_data(_data: *const u8, _length: u64) {
-    sol_log("UNAVAILABLE");
+pub(crate) fn sol_get_return_data() -> Option<(Pubkey, Vec<u8>)> {
+    syscall_stubs().sol_get_return_data()
 }
-pub(crate) fn sol_log_pubkey(_pubkey_addr: *const u8) {
-    sol_log("UNAVAILABLE");
+pub(crate) fn arch_set_transaction_to_sign(transaction_to_sign: &[u8]) -> u64 {
+    syscall_stubs().arch_set_transaction_to_sign(transaction_to_sign)
 }
-pub(crate) fn sol_log_data(_data: *const u8, _data_len: u64) {
-    sol_log("UNAVAILABLE");
+pub(crate) fn arch_get_bitcoin_tx(buf: &mut [u8], txid: &[u8; 32]) -> u64 {
+    syscall_stubs().arch_get_bitcoin_tx(buf, txid)
 }
-pub(crate) fn sol_get_return_data(_data: *mut u8, _length: u64, _program_id: *mut Pubkey) -> u64 {
-    sol_log("UNAVAILABLE");
-    UNIMPLEMENTED
+pub(crate) fn arch_get_network_xonly_pubkey() -> [u8; 32] {
+    syscall_stubs().arch_get_network_xonly_pubkey()
 }
-pub(crate) fn arch_set_transaction_to_sign(_transaction_to_sign: *const u8, _length: usize) -> u64 {
-    sol_log("UNAVAILABLE");
-    UNIMPLEMENTED
+pub(crate) fn arch_validate_utxo_ownership(utxo: &UtxoMeta, owner: &Pubkey) -> bool {
+    syscall_stubs().arch_validate_utxo_ownership(utxo, owner)
 }
-pub(crate) fn arch_get_bitcoin_tx(_buf: *const u8, _buf_len: usize, _txid: &[u8; 32]) -> u64 {
-    sol_log("UNAVAILABLE");
-    UNIMPLEMENTED
+pub(crate) fn arch_get_account_script_pubkey(pubkey: &Pubkey) -> [u8; 34] {
+    syscall_stubs().arch_get_account_script_pubkey(pubkey)
 }
-pub(crate) fn arch_get_network_xonly_pubkey(_data: *mut u8) -> u64 {
-    sol_log("UNAVAILABLE");
-    UNIMPLEMENTED
+pub(crate) fn arch_get_bitcoin_block_height() -> u64 {
+    syscall_stubs().arch_get_bitcoin_block_height()
 }
-pub(crate) fn arch_validate_utxo_ownership(_utxo: *const UtxoMeta, _owner: *const Pubkey) -> u64 {
-    sol_log("UNAVAILABLE");
-    UNIMPLEMENTED
+pub(crate) fn arch_get_clock() -> Clock {
+    syscall_stubs().arch_get_clock()
 }
-pub(crate) fn arch_get_account_script_pubkey(_buf: &mut [u8; 34], _pubkey: &Pubkey) {}
 
 pub(crate) fn sol_invoke_signed_rust(
-    _instruction_addr: &Instruction,
-    _account_infos: &[AccountInfo],
+    instruction_addr: &Instruction,
+    account_infos: &[AccountInfo],
+    signers_seeds: &[&[&[u8]]],
 ) -> ProgramResult {
-    sol_log("SyscallStubs: sol_invoke_signed() not available");
-    Ok(())
+    syscall_stubs().sol_invoke_signed(instruction_addr, account_infos, signers_seeds)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, sync::Arc};
+
+    use super::{
+        logs, take_log_data, take_logs, with_syscall_stubs, CapturedLog, SyscallStubs,
+        MAX_CAPTURED_LOGS,
+    };
+    use crate::{program, program::BitcoinTxError, MAX_BTC_TX_SIZE};
+
+    struct TestStubs {
+        transactions: HashMap<[u8; 32], Vec<u8>>,
+    }
+
+    impl SyscallStubs for TestStubs {
+        fn arch_get_bitcoin_tx(&self, buf: &mut [u8], txid: &[u8; 32]) -> u64 {
+            match self.transactions.get(txid) {
+                Some(tx) => {
+                    let len = tx.len().min(buf.len());
+                    buf[..len].copy_from_slice(&tx[..len]);
+                    tx.len() as u64
+                }
+                None => 0,
+            }
+        }
+        fn arch_get_network_xonly_pubkey(&self) -> [u8; 32] {
+            [2; 32]
+        }
+        fn arch_get_bitcoin_block_height(&self) -> u64 {
+            840_000
+        }
+    }
+
+    #[test]
+    fn test_set_syscall_stubs() {
+        let stubs = Arc::new(TestStubs {
+            transactions: HashMap::from([([1; 32], vec![0xde, 0xad])]),
+        });
+        let (bitcoin_tx, missing_tx, network_key, block_height) = with_syscall_stubs(stubs, || {
+            (
+                program::get_bitcoin_tx([1; 32]),
+                program::get_bitcoin_tx([3; 32]),
+                program::get_network_xonly_pubkey(),
+                program::get_bitcoin_block_height(),
+            )
+        });
+
+        assert_eq!(bitcoin_tx, Ok(vec![0xde, 0xad]));
+        assert_eq!(missing_tx, Err(BitcoinTxError::NotFound));
+        assert_eq!(network_key, [2; 32]);
+        assert_eq!(block_height, 840_000);
+    }
+
+    #[test]
+    fn test_get_large_bitcoin_tx() {
+        let large_tx: Vec<u8> = (0..3000).map(|i| i as u8).collect();
+        let oversized_tx = vec![7; MAX_BTC_TX_SIZE + 1];
+        let stubs = Arc::new(TestStubs {
+            transactions: HashMap::from([([1; 32], large_tx.clone()), ([2; 32], oversized_tx)]),
+        });
+
+        with_syscall_stubs(stubs, || {
+            assert_eq!(program::get_bitcoin_tx_size(&[1; 32]), Ok(3000));
+            assert_eq!(program::get_bitcoin_tx([1; 32]), Ok(large_tx.clone()));
+
+            let mut buf = [0; 1024];
+            assert_eq!(
+                program::get_bitcoin_tx_into(&[1; 32], &mut buf),
+                Err(BitcoinTxError::TooLarge {
+                    size: 3000,
+                    capacity: 1024,
+                })
+            );
+            assert_eq!(
+                program::get_bitcoin_tx([2; 32]),
+                Err(BitcoinTxError::TooLarge {
+                    size: MAX_BTC_TX_SIZE + 1,
+                    capacity: MAX_BTC_TX_SIZE,
+                })
+            );
+
+            let mut buf = vec![0; MAX_BTC_TX_SIZE + 1];
+            assert_eq!(
+                program::get_bitcoin_tx_into(&[2; 32], &mut buf),
+                Ok(MAX_BTC_TX_SIZE + 1)
+            );
+            assert_eq!(
+                program::get_bitcoin_tx_size(&[3; 32]),
+                Err(BitcoinTxError::NotFound)
+            );
+        });
+    }
+
+    #[test]
+    fn test_logs_are_captured() {
+        take_logs();
+
+        crate::msg!("Insufficient balance");
+        crate::log::sol_log_64(1, 2, 3, 4, 5);
+        crate::log::sol_log_data(&[b"event", &[1, 2]]);
+
+        assert_eq!(logs().len(), 3);
+        assert_eq!(logs()[0].as_message(), Some("Insufficient balance"));
+        assert_eq!(take_log_data(), vec![vec![b"event".to_vec(), vec![1, 2]]]);
+        assert_eq!(
+            take_logs(),
+            vec![
+                CapturedLog::Message("Insufficient balance".to_string()),
+                CapturedLog::Message("0x1, 0x2, 0x3, 0x4, 0x5".to_string()),
+            ]
+        );
+        assert!(logs().is_empty());
+    }
+
+    #[test]
+    fn test_captured_logs_are_capped() {
+        take_logs();
+
+        for i in 0..MAX_CAPTURED_LOGS + 2 {
+            crate::log::sol_log_64(i as u64, 0, 0, 0, 0);
+        }
+
+        let logs = take_logs();
+        assert_eq!(logs.len(), MAX_CAPTURED_LOGS);
+        assert_eq!(logs[0].as_message(), Some("0x2, 0x0, 0x0, 0x0, 0x0"));
+    }
 }
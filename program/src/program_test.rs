@@ -0,0 +1,1380 @@
+//! An in-process runtime for running programs natively under `cargo test`.
+//!
+//! [`ProgramTest`] keeps accounts in memory and executes every instruction of
+//! a [`Message`] against a registered [`ProcessInstruction`] function. Account
+//! parameters are laid out exactly like the on-chain loader lays them out and
+//! are handed to the program through [`deserialize`], so `realloc`,
+//! `set_owner` and `set_utxo` behave as they do on-chain.
+//!
+//! Calls to [`invoke`] made while a message is being processed are routed to
+//! the registered callee, and the callee's changes are written back into the
+//! caller's [`AccountInfo`]s once it returns.
+//!
+//! Each instruction's logs are taken out of the thread's log buffer and kept
+//! alongside its results; see [`ProgramTest::instruction_logs`].
+//!
+//! The system program is built in, so messages can create, assign, resize
+//! and close accounts alongside the programs under test.
+//!
+//! [`invoke`]: crate::program::invoke
+
+#![cfg(not(target_os = "solana"))]
+
+use std::{cell::RefCell, collections::HashMap, mem::size_of, slice::from_raw_parts};
+
+use crate::{
+    account::AccountInfo,
+    entrypoint::{
+        deserialize, ProcessInstruction, ProgramResult, BPF_ALIGN_OF_U128,
+        MAX_PERMITTED_DATA_INCREASE, NON_DUP_MARKER,
+    },
+    instruction::{Instruction, InstructionError},
+    message::Message,
+    program::{get_return_data, next_account_info},
+    program_error::ProgramError,
+    program_stubs::{self, CapturedLog},
+    pubkey::Pubkey,
+    sanitized::{SanitizedInstruction, SanitizedMessage},
+    system_instruction::SystemInstruction,
+    utxo::UtxoMeta,
+};
+
+/// An account held by [`ProgramTest`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TestAccount {
+    pub data: Vec<u8>,
+    pub owner: Pubkey,
+    pub utxo: UtxoMeta,
+    pub is_executable: bool,
+}
+
+impl TestAccount {
+    pub fn new(owner: Pubkey, data: Vec<u8>) -> Self {
+        Self {
+            data,
+            owner,
+            utxo: UtxoMeta::from([0; 32], 0),
+            is_executable: false,
+        }
+    }
+}
+
+/// Runs messages against a set of natively compiled programs.
+pub struct ProgramTest {
+    programs: HashMap<Pubkey, ProcessInstruction>,
+    accounts: HashMap<Pubkey, TestAccount>,
+    return_data: Option<(Pubkey, Vec<u8>)>,
+    instruction_logs: Vec<Vec<CapturedLog>>,
+}
+
+impl Default for ProgramTest {
+    fn default() -> Self {
+        Self {
+            programs: HashMap::from([(
+                Pubkey::system_program(),
+                process_system_instruction as ProcessInstruction,
+            )]),
+            accounts: HashMap::new(),
+            return_data: None,
+            instruction_logs: vec![],
+        }
+    }
+}
+
+impl ProgramTest {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register `process_instruction` as the program living at `program_id`.
+    pub fn add_program(&mut self, program_id: Pubkey, process_instruction: ProcessInstruction) {
+        self.programs.insert(program_id, process_instruction);
+    }
+
+    pub fn add_account(&mut self, pubkey: Pubkey, account: TestAccount) {
+        self.accounts.insert(pubkey, account);
+    }
+
+    pub fn get_account(&self, pubkey: &Pubkey) -> Option<&TestAccount> {
+        self.accounts.get(pubkey)
+    }
+
+    /// The return data left by the last instruction of the last message, and
+    /// the program that set it.
+    pub fn return_data(&self) -> Option<&(Pubkey, Vec<u8>)> {
+        self.return_data.as_ref()
+    }
+
+    /// What each instruction of the last message logged, in order, including
+    /// the instruction that failed if one did. The logs of programs it
+    /// invoked are part of an instruction's logs.
+    pub fn instruction_logs(&self) -> &[Vec<CapturedLog>] {
+        &self.instruction_logs
+    }
+
+    /// Execute every instruction of `message` in order.
+    ///
+    /// The message is [compiled](Message::compile) first, so an account has
+    /// the same merged privileges in every instruction and is passed to a
+    /// program once, later references to it arriving as duplicates.
+    ///
+    /// Account changes are persisted between instructions. If any instruction
+    /// fails, the accounts are restored to their state before the message.
+    pub fn process_message(&mut self, message: &Message) -> Result<(), InstructionError> {
+        self.instruction_logs.clear();
+        let sanitized = message.compile()?;
+        if sanitized.account_keys.len() > MAX_ACCOUNTS {
+            return Err(InstructionError::MaxAccountsExceeded);
+        }
+
+        let snapshot = self.accounts.clone();
+        let _context = InvokeContextGuard::enter(self.programs.clone());
+        program_stubs::clear_return_data();
+
+        let result = sanitized.instructions.iter().try_for_each(|instruction| {
+            program_stubs::clear_return_data();
+            let (result, logs) =
+                program_stubs::capture_logs(|| self.process_instruction(&sanitized, instruction));
+            self.instruction_logs.push(logs);
+            result
+        });
+        self.return_data = get_return_data();
+
+        if result.is_err() {
+            self.accounts = snapshot;
+        }
+        result
+    }
+
+    fn process_instruction(
+        &mut self,
+        message: &SanitizedMessage,
+        instruction: &SanitizedInstruction,
+    ) -> Result<(), InstructionError> {
+        let process_instruction = *self
+            .programs
+            .get(&instruction.program_id)
+            .ok_or(InstructionError::UnsupportedProgramId)?;
+
+        let mut accounts = instruction
+            .accounts
+            .iter()
+            .map(|index| {
+                let meta = message.account_meta(*index as usize);
+                let account = self
+                    .accounts
+                    .get(&meta.pubkey)
+                    .cloned()
+                    .ok_or(InstructionError::MissingAccount)?;
+                Ok(InstructionAccount {
+                    key: meta.pubkey,
+                    is_signer: meta.is_signer,
+                    is_writable: meta.is_writable,
+                    account,
+                })
+            })
+            .collect::<Result<Vec<_>, InstructionError>>()?;
+
+        execute(
+            process_instruction,
+            &instruction.program_id,
+            &mut accounts,
+            &instruction.data,
+        )?;
+
+        for account in accounts {
+            self.accounts.insert(account.key, account.account);
+        }
+
+        Ok(())
+    }
+}
+
+/// The built-in system program. Every instruction takes the account it acts
+/// on as its only account, which must sign and be writable. `MakeExecutable`
+/// is not emulated.
+fn process_system_instruction(
+    _program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    let account = next_account_info(&mut accounts.iter())?;
+    if !account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if !account.is_writable {
+        return Err(ProgramError::Immutable);
+    }
+    if !account.owner.is_system_program() {
+        return Err(ProgramError::IllegalOwner);
+    }
+
+    match SystemInstruction::from_slice(instruction_data)? {
+        SystemInstruction::CreateAccount(utxo) => account.set_utxo(&utxo),
+        SystemInstruction::ExtendBytes(bytes) => {
+            let len = account.data_len();
+            account.realloc(len + bytes.len(), false)?;
+            account.try_borrow_mut_data()?[len..].copy_from_slice(&bytes);
+        }
+        SystemInstruction::MakeExecutable(_) => return Err(ProgramError::InvalidArgument),
+        SystemInstruction::Assign(owner) => account.set_owner(&owner),
+        SystemInstruction::Resize(new_len) => {
+            let new_len = usize::try_from(new_len).map_err(|_| ProgramError::InvalidRealloc)?;
+            account.realloc(new_len, true)?;
+        }
+        SystemInstruction::CloseAccount => {
+            account.realloc(0, false)?;
+            account.set_utxo(&UtxoMeta::from([0; 32], 0));
+        }
+    }
+    Ok(())
+}
+
+/// An account as it is passed to a single instruction.
+#[derive(Clone)]
+struct InstructionAccount {
+    key: Pubkey,
+    is_signer: bool,
+    is_writable: bool,
+    account: TestAccount,
+}
+
+/// The most accounts a message or instruction can pass, as a duplicate is
+/// serialized as the one-byte position of the account it repeats.
+const MAX_ACCOUNTS: usize = u8::MAX as usize + 1;
+
+/// Where each account ended up in a serialized parameter buffer.
+enum SerializedAccount {
+    Account { data_len_offset: usize },
+    Duplicate(usize),
+}
+
+/// Lay out the program parameters the way [`deserialize`] expects to find
+/// them. The buffer is backed by `u64`s so that its start is 8-byte aligned.
+fn serialize_parameters(
+    program_id: &Pubkey,
+    accounts: &[InstructionAccount],
+    instruction_data: &[u8],
+) -> (Vec<u64>, Vec<SerializedAccount>) {
+    let mut buffer: Vec<u8> = vec![];
+    let mut layout = Vec::with_capacity(accounts.len());
+
+    buffer.extend((accounts.len() as u64).to_le_bytes());
+    for (index, account) in accounts.iter().enumerate() {
+        if let Some(position) = accounts[..index].iter().position(|a| a.key == account.key) {
+            buffer.push(position as u8);
+            buffer.extend([0; 7]);
+            layout.push(SerializedAccount::Duplicate(position));
+            continue;
+        }
+
+        buffer.push(NON_DUP_MARKER);
+        buffer.extend([0; 4]);
+        buffer.push(account.is_signer as u8);
+        buffer.push(account.is_writable as u8);
+        buffer.push(account.account.is_executable as u8);
+        buffer.extend(account.key.serialize());
+        buffer.extend((account.account.data.len() as u64).to_le_bytes());
+        layout.push(SerializedAccount::Account {
+            data_len_offset: buffer.len(),
+        });
+        buffer.extend((account.account.data.len() as u64).to_le_bytes());
+        buffer.extend(&account.account.data);
+        buffer.resize(buffer.len() + MAX_PERMITTED_DATA_INCREASE, 0);
+        let padding = (buffer.len() as *const u8).align_offset(BPF_ALIGN_OF_U128);
+        buffer.resize(buffer.len() + padding, 0);
+        buffer.extend(account.account.owner.serialize());
+        buffer.extend(account.account.utxo.serialize());
+        buffer.extend([0; 4]);
+    }
+    buffer.extend((instruction_data.len() as u64).to_le_bytes());
+    buffer.extend(instruction_data);
+    buffer.extend(program_id.serialize());
+
+    let mut aligned = vec![0u64; buffer.len().div_ceil(size_of::<u64>())];
+    // SAFETY: `aligned` spans at least `buffer.len()` bytes.
+    unsafe {
+        std::ptr::copy_nonoverlapping(
+            buffer.as_ptr(),
+            aligned.as_mut_ptr() as *mut u8,
+            buffer.len(),
+        )
+    };
+    (aligned, layout)
+}
+
+/// Read the accounts back out of a parameter buffer after the program ran.
+fn deserialize_accounts(
+    buffer: &[u64],
+    layout: &[SerializedAccount],
+    accounts: &mut [InstructionAccount],
+) {
+    // SAFETY: a `u64` buffer can always be viewed as bytes.
+    let buffer =
+        unsafe { from_raw_parts(buffer.as_ptr() as *const u8, std::mem::size_of_val(buffer)) };
+    let read_u64 =
+        |offset: usize| u64::from_le_bytes(buffer[offset..offset + 8].try_into().unwrap()) as usize;
+
+    for (index, serialized) in layout.iter().enumerate() {
+        match *serialized {
+            SerializedAccount::Account { data_len_offset } => {
+                let data_len = read_u64(data_len_offset);
+                let data_offset = data_len_offset + size_of::<u64>();
+                let original_data_len = read_u64(data_len_offset - size_of::<u64>());
+
+                let mut owner_offset =
+                    data_offset + original_data_len + MAX_PERMITTED_DATA_INCREASE;
+                owner_offset += (owner_offset as *const u8).align_offset(BPF_ALIGN_OF_U128);
+                let utxo_offset = owner_offset + size_of::<Pubkey>();
+
+                // The executable flag sits just before the key and the
+                // original data length.
+                let is_executable_offset =
+                    data_len_offset - size_of::<u64>() - size_of::<Pubkey>() - 1;
+
+                let account = &mut accounts[index].account;
+                account.is_executable = buffer[is_executable_offset] != 0;
+                account.data = buffer[data_offset..data_offset + data_len].to_vec();
+                account.owner = Pubkey::from_slice(&buffer[owner_offset..utxo_offset]).unwrap();
+                account.utxo = UtxoMeta::from_slice(&buffer[utxo_offset..]).unwrap();
+            }
+            SerializedAccount::Duplicate(position) => {
+                accounts[index].account = accounts[position].account.clone();
+            }
+        }
+    }
+}
+
+/// Run `process_instruction` over `accounts`, updating them in place if the
+/// program succeeds and only changed what it was allowed to change.
+fn execute(
+    process_instruction: ProcessInstruction,
+    program_id: &Pubkey,
+    accounts: &mut [InstructionAccount],
+    instruction_data: &[u8],
+) -> Result<(), InstructionError> {
+    if accounts.len() > MAX_ACCOUNTS {
+        return Err(InstructionError::MaxAccountsExceeded);
+    }
+    let (mut buffer, layout) = serialize_parameters(program_id, accounts, instruction_data);
+
+    with_invoke_context(|context| {
+        context.frames.push(Frame {
+            program_id: *program_id,
+            pre_accounts: accounts
+                .iter()
+                .map(|account| (account.key, account.account.clone()))
+                .collect(),
+        })
+    });
+    let result = {
+        // SAFETY: the buffer was laid out by `serialize_parameters` and
+        // outlives every reference handed out to the program.
+        let (program_id, account_infos, instruction_data) =
+            unsafe { deserialize(buffer.as_mut_ptr() as *mut u8) };
+        process_instruction(program_id, &account_infos, instruction_data)
+    };
+    let frame = with_invoke_context(|context| context.frames.pop())
+        .flatten()
+        .expect("frame pushed above");
+
+    result.map_err(InstructionError::ProgramError)?;
+    deserialize_accounts(&buffer, &layout, accounts);
+
+    for (index, account) in accounts.iter().enumerate() {
+        if accounts[..index].iter().any(|a| a.key == account.key) {
+            continue;
+        }
+        verify_account_change(program_id, &frame.pre_accounts[&account.key], account)?;
+    }
+    Ok(())
+}
+
+/// Check that `program_id` was allowed to make the changes it made to an
+/// account.
+fn verify_account_change(
+    program_id: &Pubkey,
+    pre: &TestAccount,
+    post: &InstructionAccount,
+) -> Result<(), InstructionError> {
+    let owner_changed = pre.owner != post.account.owner;
+    let data_changed = pre.data != post.account.data;
+    let utxo_changed = pre.utxo != post.account.utxo;
+    let executable_changed = pre.is_executable != post.account.is_executable;
+    if !owner_changed && !data_changed && !utxo_changed && !executable_changed {
+        return Ok(());
+    }
+
+    if !post.is_writable {
+        return Err(InstructionError::ReadonlyDataModified(format!(
+            "{:x}",
+            post.key
+        )));
+    }
+    if pre.owner != *program_id {
+        if owner_changed {
+            return Err(InstructionError::ModifiedProgramId);
+        }
+        if executable_changed {
+            return Err(InstructionError::ExecutableModified);
+        }
+        return Err(InstructionError::ExternalAccountDataModified(
+            format!("{:x}", post.key),
+            format!("{:x}", program_id),
+        ));
+    }
+
+    Ok(())
+}
+
+/// State shared with [`invoke`] while a message is being processed.
+///
+/// [`invoke`]: crate::program::invoke
+struct InvokeContext {
+    programs: HashMap<Pubkey, ProcessInstruction>,
+    frames: Vec<Frame>,
+}
+
+/// A program on the invocation stack, along with the account states its
+/// changes are checked against.
+struct Frame {
+    program_id: Pubkey,
+    pre_accounts: HashMap<Pubkey, TestAccount>,
+}
+
+thread_local! {
+    static INVOKE_CONTEXT: RefCell<Option<InvokeContext>> = const { RefCell::new(None) };
+}
+
+fn with_invoke_context<R>(f: impl FnOnce(&mut InvokeContext) -> R) -> Option<R> {
+    INVOKE_CONTEXT.with(|context| context.borrow_mut().as_mut().map(f))
+}
+
+/// Installs an [`InvokeContext`] for the current thread and removes it again
+/// when dropped.
+struct InvokeContextGuard;
+
+impl InvokeContextGuard {
+    fn enter(programs: HashMap<Pubkey, ProcessInstruction>) -> Self {
+        INVOKE_CONTEXT.with(|context| {
+            *context.borrow_mut() = Some(InvokeContext {
+                programs,
+                frames: vec![],
+            })
+        });
+        Self
+    }
+}
+
+impl Drop for InvokeContextGuard {
+    fn drop(&mut self) {
+        INVOKE_CONTEXT.with(|context| *context.borrow_mut() = None);
+    }
+}
+
+/// The program a [`ProgramTest`] is running on this thread, if any.
+pub(crate) fn current_program_id() -> Option<Pubkey> {
+    with_invoke_context(|context| context.frames.last().map(|frame| frame.program_id)).flatten()
+}
+
+/// Route a cross-program invocation to its registered callee.
+///
+/// Returns `None` when no [`ProgramTest`] is processing a message on this
+/// thread.
+pub(crate) fn invoke(
+    instruction: &Instruction,
+    account_infos: &[AccountInfo],
+    signers_seeds: &[&[&[u8]]],
+) -> Option<ProgramResult> {
+    let (process_instruction, caller_program_id) = with_invoke_context(|context| {
+        (
+            context.programs.get(&instruction.program_id).copied(),
+            context.frames.last().map(|frame| frame.program_id),
+        )
+    })?;
+
+    Some(match (process_instruction, caller_program_id) {
+        (Some(process_instruction), Some(caller_program_id)) => invoke_callee(
+            process_instruction,
+            instruction,
+            account_infos,
+            &caller_program_id,
+            signers_seeds,
+        ),
+        _ => Err(ProgramError::IncorrectProgramId),
+    })
+}
+
+/// The program addresses `signers_seeds` sign for, derived from the caller's
+/// id. Without the `experimental-pda` feature nothing can pass seeds.
+fn program_signers(
+    signers_seeds: &[&[&[u8]]],
+    caller_program_id: &Pubkey,
+) -> Result<Vec<Pubkey>, ProgramError> {
+    #[cfg(any(test, feature = "experimental-pda"))]
+    {
+        signers_seeds
+            .iter()
+            .map(|seeds| Pubkey::create_program_address(seeds, caller_program_id))
+            .collect()
+    }
+
+    #[cfg(not(any(test, feature = "experimental-pda")))]
+    {
+        let _ = caller_program_id;
+        match signers_seeds {
+            [] => Ok(vec![]),
+            _ => Err(ProgramError::InvalidSeeds),
+        }
+    }
+}
+
+fn invoke_callee(
+    process_instruction: ProcessInstruction,
+    instruction: &Instruction,
+    account_infos: &[AccountInfo],
+    caller_program_id: &Pubkey,
+    signers_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    let signers = program_signers(signers_seeds, caller_program_id)?;
+
+    let mut caller_infos = Vec::with_capacity(instruction.accounts.len());
+    let mut accounts = Vec::with_capacity(instruction.accounts.len());
+    for meta in instruction.accounts.iter() {
+        let account_info = account_infos
+            .iter()
+            .find(|account_info| *account_info.key == meta.pubkey)
+            .ok_or(ProgramError::NotEnoughAccountKeys)?;
+
+        if meta.is_signer && !account_info.is_signer && !signers.contains(&meta.pubkey) {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+        if meta.is_writable && !account_info.is_writable {
+            return Err(ProgramError::Immutable);
+        }
+
+        let account = InstructionAccount {
+            key: meta.pubkey,
+            is_signer: meta.is_signer,
+            is_writable: meta.is_writable,
+            account: TestAccount {
+                data: account_info.try_borrow_data()?.to_vec(),
+                owner: *account_info.owner,
+                utxo: account_info.utxo.clone(),
+                is_executable: account_info.is_executable,
+            },
+        };
+        // Settle what the caller changed so far before the callee sees it.
+        sync_caller_account(
+            &InstructionAccount {
+                is_writable: account_info.is_writable,
+                ..account.clone()
+            },
+            true,
+        )?;
+
+        accounts.push(account);
+        caller_infos.push(account_info);
+    }
+
+    program_stubs::clear_return_data();
+    execute(
+        process_instruction,
+        &instruction.program_id,
+        &mut accounts,
+        &instruction.data,
+    )
+    .map_err(to_program_error)?;
+
+    for (account_info, post) in caller_infos.into_iter().zip(accounts) {
+        if !post.is_writable {
+            continue;
+        }
+
+        if post.account.data.len() != account_info.data_len() {
+            account_info.realloc(post.account.data.len(), false)?;
+        }
+        account_info
+            .try_borrow_mut_data()?
+            .copy_from_slice(&post.account.data);
+        if post.account.owner != *account_info.owner {
+            account_info.set_owner(&post.account.owner);
+        }
+        if post.account.utxo != *account_info.utxo {
+            account_info.set_utxo(&post.account.utxo);
+        }
+        sync_caller_account(&post, false)?;
+    }
+
+    Ok(())
+}
+
+/// Record `account` as the caller's new baseline, first checking the
+/// caller's own changes to it if `verify` is set.
+fn sync_caller_account(account: &InstructionAccount, verify: bool) -> ProgramResult {
+    with_invoke_context(|context| {
+        let Some(frame) = context.frames.last_mut() else {
+            return Ok(());
+        };
+        if verify {
+            if let Some(pre) = frame.pre_accounts.get(&account.key) {
+                verify_account_change(&frame.program_id, pre, account).map_err(to_program_error)?;
+            }
+        }
+        frame
+            .pre_accounts
+            .insert(account.key, account.account.clone());
+        Ok(())
+    })
+    .unwrap_or(Ok(()))
+}
+
+/// A failed cross-program invocation surfaces to the caller as a
+/// [`ProgramError`].
+fn to_program_error(error: InstructionError) -> ProgramError {
+    match error {
+        InstructionError::ProgramError(error) => error,
+        InstructionError::ReadonlyDataModified(_) => ProgramError::Immutable,
+        InstructionError::ModifiedProgramId => ProgramError::IllegalOwner,
+        _ => ProgramError::InvalidAccountOwner,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ProgramTest, TestAccount};
+    use crate::program_stubs::{self, CapturedLog};
+    use crate::{
+        account::{AccountInfo, AccountMeta},
+        entrypoint::ProgramResult,
+        instruction::{Instruction, InstructionError},
+        message::Message,
+        msg,
+        program::{
+            get_return_data_borsh, invoke, invoke_signed, next_account_info, set_return_data_borsh,
+        },
+        program_error::ProgramError,
+        pubkey::Pubkey,
+        system_instruction::SystemInstruction,
+        utxo::UtxoMeta,
+    };
+
+    fn token_program_id() -> Pubkey {
+        Pubkey::from([7; 32])
+    }
+
+    fn pool_program_id() -> Pubkey {
+        Pubkey::from([8; 32])
+    }
+
+    /// Moves `amount` from the first balance account to the second one; the
+    /// owner must sign. Balances are little-endian `u64`s.
+    fn process_token_instruction(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        instruction_data: &[u8],
+    ) -> ProgramResult {
+        let account_iter = &mut accounts.iter();
+        let owner_account = next_account_info(account_iter)?;
+        let sender_account = next_account_info(account_iter)?;
+        let receiver_account = next_account_info(account_iter)?;
+
+        if !owner_account.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+        if sender_account.owner != program_id || receiver_account.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+
+        let amount = u64::from_le_bytes(
+            instruction_data
+                .try_into()
+                .map_err(|_| ProgramError::InvalidInstructionData)?,
+        );
+        let balance = |account: &AccountInfo| -> Result<u64, ProgramError> {
+            Ok(u64::from_le_bytes(
+                account.try_borrow_data()?[..]
+                    .try_into()
+                    .map_err(|_| ProgramError::InvalidAccountData)?,
+            ))
+        };
+        let sender_balance = balance(sender_account)?
+            .checked_sub(amount)
+            .ok_or_else(|| {
+                msg!("Insufficient balance");
+                ProgramError::InsufficientFunds
+            })?;
+        msg!("Transfer {}", amount);
+        let receiver_balance = balance(receiver_account)? + amount;
+
+        sender_account
+            .try_borrow_mut_data()?
+            .copy_from_slice(&sender_balance.to_le_bytes());
+        receiver_account
+            .try_borrow_mut_data()?
+            .copy_from_slice(&receiver_balance.to_le_bytes());
+        Ok(())
+    }
+
+    /// Transfers tokens from the user into the pool and appends the amount to
+    /// the pool's deposit record, growing it by 8 bytes each time.
+    fn process_pool_instruction(
+        _program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        instruction_data: &[u8],
+    ) -> ProgramResult {
+        let account_iter = &mut accounts.iter();
+        let pool_account = next_account_info(account_iter)?;
+        let user_account = next_account_info(account_iter)?;
+        let token_program = next_account_info(account_iter)?;
+        let user_token_account = next_account_info(account_iter)?;
+        let pool_token_account = next_account_info(account_iter)?;
+
+        invoke(
+            &Instruction {
+                program_id: *token_program.key,
+                accounts: vec![
+                    AccountMeta {
+                        pubkey: *user_account.key,
+                        is_signer: true,
+                        is_writable: false,
+                    },
+                    AccountMeta {
+                        pubkey: *user_token_account.key,
+                        is_signer: false,
+                        is_writable: true,
+                    },
+                    AccountMeta {
+                        pubkey: *pool_token_account.key,
+                        is_signer: false,
+                        is_writable: true,
+                    },
+                ],
+                data: instruction_data.to_vec(),
+            },
+            accounts,
+        )?;
+
+        let len = pool_account.data_len();
+        pool_account.realloc(len + 8, true)?;
+        pool_account.try_borrow_mut_data()?[len..].copy_from_slice(instruction_data);
+        Ok(())
+    }
+
+    /// Pays `amount` out of the vault's token account, signing for the
+    /// vault's program address with the seeds passed as instruction data.
+    fn process_vault_instruction(
+        _program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        instruction_data: &[u8],
+    ) -> ProgramResult {
+        let account_iter = &mut accounts.iter();
+        let vault_authority = next_account_info(account_iter)?;
+        let token_program = next_account_info(account_iter)?;
+        let vault_token_account = next_account_info(account_iter)?;
+        let user_token_account = next_account_info(account_iter)?;
+
+        let (amount, bump_seed) = instruction_data.split_at(8);
+        invoke_signed(
+            &Instruction {
+                program_id: *token_program.key,
+                accounts: vec![
+                    AccountMeta {
+                        pubkey: *vault_authority.key,
+                        is_signer: true,
+                        is_writable: false,
+                    },
+                    AccountMeta {
+                        pubkey: *vault_token_account.key,
+                        is_signer: false,
+                        is_writable: true,
+                    },
+                    AccountMeta {
+                        pubkey: *user_token_account.key,
+                        is_signer: false,
+                        is_writable: true,
+                    },
+                ],
+                data: amount.to_vec(),
+            },
+            accounts,
+            &[&[b"vault", bump_seed]],
+        )
+    }
+
+    struct Setup {
+        program_test: ProgramTest,
+        pool: Pubkey,
+        user: Pubkey,
+        user_tokens: Pubkey,
+        pool_tokens: Pubkey,
+    }
+
+    fn setup() -> Setup {
+        let mut program_test = ProgramTest::new();
+        program_test.add_program(token_program_id(), process_token_instruction);
+        program_test.add_program(pool_program_id(), process_pool_instruction);
+
+        let (pool, user, user_tokens, pool_tokens) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        program_test.add_account(pool, TestAccount::new(pool_program_id(), vec![]));
+        program_test.add_account(user, TestAccount::new(Pubkey::system_program(), vec![]));
+        program_test.add_account(
+            user_tokens,
+            TestAccount::new(token_program_id(), 100u64.to_le_bytes().to_vec()),
+        );
+        program_test.add_account(
+            pool_tokens,
+            TestAccount::new(token_program_id(), 0u64.to_le_bytes().to_vec()),
+        );
+        program_test.add_account(
+            token_program_id(),
+            TestAccount {
+                is_executable: true,
+                ..TestAccount::new(Pubkey::system_program(), vec![])
+            },
+        );
+
+        Setup {
+            program_test,
+            pool,
+            user,
+            user_tokens,
+            pool_tokens,
+        }
+    }
+
+    fn deposit_message(setup: &Setup, amount: u64) -> Message {
+        let meta = |pubkey, is_signer, is_writable| AccountMeta {
+            pubkey,
+            is_signer,
+            is_writable,
+        };
+        Message {
+            signers: vec![setup.user],
+            instructions: vec![Instruction {
+                program_id: pool_program_id(),
+                accounts: vec![
+                    meta(setup.pool, false, true),
+                    meta(setup.user, true, false),
+                    meta(token_program_id(), false, false),
+                    meta(setup.user_tokens, false, true),
+                    meta(setup.pool_tokens, false, true),
+                ],
+                data: amount.to_le_bytes().to_vec(),
+            }],
+        }
+    }
+
+    fn balance(setup: &Setup, pubkey: &Pubkey) -> u64 {
+        let data = &setup.program_test.get_account(pubkey).unwrap().data;
+        u64::from_le_bytes(data[..].try_into().unwrap())
+    }
+
+    #[test]
+    fn test_deposit_invokes_token_program() {
+        let mut setup = setup();
+
+        let message = deposit_message(&setup, 30);
+        setup.program_test.process_message(&message).unwrap();
+        let message = deposit_message(&setup, 20);
+        setup.program_test.process_message(&message).unwrap();
+
+        assert_eq!(balance(&setup, &setup.user_tokens), 50);
+        assert_eq!(balance(&setup, &setup.pool_tokens), 50);
+
+        let pool_data = &setup.program_test.get_account(&setup.pool).unwrap().data;
+        assert_eq!(pool_data.len(), 16);
+        assert_eq!(pool_data[..8], 30u64.to_le_bytes());
+        assert_eq!(pool_data[8..], 20u64.to_le_bytes());
+    }
+
+    #[test]
+    fn test_instruction_logs_leave_the_buffer() {
+        let mut setup = setup();
+        msg!("Before");
+
+        for amount in [30, 20] {
+            let message = deposit_message(&setup, amount);
+            setup.program_test.process_message(&message).unwrap();
+            assert!(setup.program_test.instruction_logs()[0]
+                .contains(&CapturedLog::Message(format!("Transfer {}", amount))));
+            assert!(program_stubs::take_log_data().is_empty());
+        }
+
+        assert_eq!(
+            program_stubs::take_logs(),
+            vec![CapturedLog::Message("Before".to_string())]
+        );
+    }
+
+    fn withdraw_message(setup: &Setup, vault_authority: Pubkey, bump_seed: u8) -> Message {
+        let meta = |pubkey, is_writable| AccountMeta {
+            pubkey,
+            is_signer: false,
+            is_writable,
+        };
+        let mut data = 40u64.to_le_bytes().to_vec();
+        data.push(bump_seed);
+        Message {
+            signers: vec![],
+            instructions: vec![Instruction {
+                program_id: vault_program_id(),
+                accounts: vec![
+                    meta(vault_authority, false),
+                    meta(token_program_id(), false),
+                    meta(setup.user_tokens, true),
+                    meta(setup.pool_tokens, true),
+                ],
+                data,
+            }],
+        }
+    }
+
+    fn vault_program_id() -> Pubkey {
+        Pubkey::from([9; 32])
+    }
+
+    #[test]
+    fn test_invoke_signed_with_program_address() {
+        let mut setup = setup();
+        setup
+            .program_test
+            .add_program(vault_program_id(), process_vault_instruction);
+        let (vault_authority, bump_seed) =
+            Pubkey::find_program_address(&[b"vault"], &vault_program_id());
+        setup.program_test.add_account(
+            vault_authority,
+            TestAccount::new(Pubkey::system_program(), vec![]),
+        );
+
+        let message = withdraw_message(&setup, vault_authority, bump_seed.wrapping_sub(1));
+        assert!(setup.program_test.process_message(&message).is_err());
+
+        let message = withdraw_message(&setup, vault_authority, bump_seed);
+        setup.program_test.process_message(&message).unwrap();
+        assert_eq!(balance(&setup, &setup.user_tokens), 60);
+        assert_eq!(balance(&setup, &setup.pool_tokens), 40);
+    }
+
+    #[test]
+    fn test_failed_message_is_rolled_back() {
+        let mut setup = setup();
+
+        let mut message = deposit_message(&setup, 60);
+        message
+            .instructions
+            .extend(deposit_message(&setup, 60).instructions);
+
+        assert_eq!(
+            setup.program_test.process_message(&message),
+            Err(InstructionError::ProgramError(
+                ProgramError::InsufficientFunds
+            ))
+        );
+        assert_eq!(balance(&setup, &setup.user_tokens), 100);
+        assert_eq!(balance(&setup, &setup.pool_tokens), 0);
+        let logs = setup.program_test.instruction_logs();
+        assert_eq!(logs.len(), 2);
+        assert!(logs[0].contains(&CapturedLog::Message("Transfer 60".to_string())));
+        assert!(logs[1].contains(&CapturedLog::Message("Insufficient balance".to_string())));
+        assert!(!logs[1].contains(&CapturedLog::Message("Transfer 60".to_string())));
+        assert!(setup
+            .program_test
+            .get_account(&setup.pool)
+            .unwrap()
+            .data
+            .is_empty());
+    }
+
+    #[test]
+    fn test_missing_signer() {
+        let mut setup = setup();
+
+        let mut message = deposit_message(&setup, 10);
+        message.signers.clear();
+
+        assert_eq!(
+            setup.program_test.process_message(&message),
+            Err(InstructionError::MissingRequiredSignature)
+        );
+    }
+
+    #[test]
+    fn test_readonly_account_data_modified() {
+        let mut setup = setup();
+
+        // The sender balance is passed read-only, so the token program may not
+        // debit it.
+        let message = Message {
+            signers: vec![setup.user],
+            instructions: vec![Instruction {
+                program_id: token_program_id(),
+                accounts: vec![
+                    AccountMeta {
+                        pubkey: setup.user,
+                        is_signer: true,
+                        is_writable: false,
+                    },
+                    AccountMeta {
+                        pubkey: setup.user_tokens,
+                        is_signer: false,
+                        is_writable: false,
+                    },
+                    AccountMeta {
+                        pubkey: setup.pool_tokens,
+                        is_signer: false,
+                        is_writable: true,
+                    },
+                ],
+                data: 5u64.to_le_bytes().to_vec(),
+            }],
+        };
+
+        assert!(matches!(
+            setup.program_test.process_message(&message),
+            Err(InstructionError::ReadonlyDataModified(_))
+        ));
+        assert_eq!(balance(&setup, &setup.user_tokens), 100);
+    }
+
+    /// Moves the account it is given to a new UTXO.
+    fn process_reanchor_instruction(
+        _program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        _instruction_data: &[u8],
+    ) -> ProgramResult {
+        next_account_info(&mut accounts.iter())?.set_utxo(&UtxoMeta::from([1; 32], 0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_external_account_utxo_modified() {
+        let mut setup = setup();
+        let reanchor_program_id = Pubkey::from([9; 32]);
+        setup
+            .program_test
+            .add_program(reanchor_program_id, process_reanchor_instruction);
+        let reanchor = |pubkey| Message {
+            signers: vec![],
+            instructions: vec![Instruction {
+                program_id: reanchor_program_id,
+                accounts: vec![AccountMeta {
+                    pubkey,
+                    is_signer: false,
+                    is_writable: true,
+                }],
+                data: vec![],
+            }],
+        };
+
+        assert_eq!(
+            setup
+                .program_test
+                .process_message(&reanchor(setup.user_tokens)),
+            Err(InstructionError::ExternalAccountDataModified(
+                format!("{:x}", setup.user_tokens),
+                format!("{:x}", reanchor_program_id),
+            ))
+        );
+        assert_eq!(
+            setup
+                .program_test
+                .get_account(&setup.user_tokens)
+                .unwrap()
+                .utxo,
+            UtxoMeta::from([0; 32], 0)
+        );
+
+        let owned = Pubkey::new_unique();
+        setup
+            .program_test
+            .add_account(owned, TestAccount::new(reanchor_program_id, vec![]));
+        assert_eq!(setup.program_test.process_message(&reanchor(owned)), Ok(()));
+        assert_eq!(
+            setup.program_test.get_account(&owned).unwrap().utxo,
+            UtxoMeta::from([1; 32], 0)
+        );
+    }
+
+    #[test]
+    fn test_too_many_accounts() {
+        let mut setup = setup();
+        let mut accounts: Vec<AccountMeta> = (0..=u8::MAX as u16 + 1)
+            .map(|i| {
+                let mut key = [3; 32];
+                key[..2].copy_from_slice(&i.to_le_bytes());
+                AccountMeta {
+                    pubkey: Pubkey::from(key),
+                    is_signer: false,
+                    is_writable: false,
+                }
+            })
+            .collect();
+        let message = |accounts| Message {
+            signers: vec![],
+            instructions: vec![Instruction {
+                program_id: pool_program_id(),
+                accounts,
+                data: vec![],
+            }],
+        };
+
+        assert_eq!(
+            setup
+                .program_test
+                .process_message(&message(accounts.clone())),
+            Err(InstructionError::MaxAccountsExceeded)
+        );
+        // 256 accounts can be passed; these ones just do not exist.
+        accounts.pop();
+        assert_eq!(
+            setup.program_test.process_message(&message(accounts)),
+            Err(InstructionError::MissingAccount)
+        );
+    }
+
+    fn system_message(account: Pubkey, instructions: Vec<Instruction>) -> Message {
+        Message {
+            signers: vec![account],
+            instructions,
+        }
+    }
+
+    #[test]
+    fn test_system_program_account_lifecycle() {
+        let mut program_test = ProgramTest::new();
+        program_test.add_program(token_program_id(), process_token_instruction);
+        let account = Pubkey::new_unique();
+        program_test.add_account(account, TestAccount::new(Pubkey::system_program(), vec![]));
+
+        let message = system_message(
+            account,
+            vec![
+                SystemInstruction::new_create_account_instruction([1; 32], 2, account),
+                SystemInstruction::new_extend_bytes_instruction(vec![1, 2, 3], account),
+                SystemInstruction::new_resize_instruction(5, account),
+            ],
+        );
+        program_test.process_message(&message).unwrap();
+        let state = program_test.get_account(&account).unwrap();
+        assert_eq!(state.data, vec![1, 2, 3, 0, 0]);
+        assert_eq!(state.utxo, UtxoMeta::from([1; 32], 2));
+
+        let message = system_message(
+            account,
+            vec![
+                SystemInstruction::new_resize_instruction(2, account),
+                SystemInstruction::new_close_account_instruction(account),
+            ],
+        );
+        program_test.process_message(&message).unwrap();
+        let state = program_test.get_account(&account).unwrap();
+        assert!(state.data.is_empty());
+        assert_eq!(state.utxo, UtxoMeta::from([0; 32], 0));
+        assert_eq!(state.owner, Pubkey::system_program());
+
+        let message = system_message(
+            account,
+            vec![SystemInstruction::new_assign_instruction(
+                token_program_id(),
+                account,
+            )],
+        );
+        program_test.process_message(&message).unwrap();
+        assert_eq!(
+            program_test.get_account(&account).unwrap().owner,
+            token_program_id()
+        );
+
+        // Once assigned, the system program no longer controls the account.
+        let message = system_message(
+            account,
+            vec![SystemInstruction::new_close_account_instruction(account)],
+        );
+        assert_eq!(
+            program_test.process_message(&message),
+            Err(InstructionError::ProgramError(ProgramError::IllegalOwner))
+        );
+    }
+
+    #[test]
+    fn test_system_program_requires_signature() {
+        let mut program_test = ProgramTest::new();
+        let account = Pubkey::new_unique();
+        program_test.add_account(account, TestAccount::new(Pubkey::system_program(), vec![]));
+
+        let mut instruction = SystemInstruction::new_resize_instruction(8, account);
+        instruction.accounts[0].is_signer = false;
+        let message = Message {
+            signers: vec![],
+            instructions: vec![instruction],
+        };
+        assert_eq!(
+            program_test.process_message(&message),
+            Err(InstructionError::ProgramError(
+                ProgramError::MissingRequiredSignature
+            ))
+        );
+        assert!(program_test.get_account(&account).unwrap().data.is_empty());
+    }
+
+    /// Returns the balance held by its only account.
+    fn process_balance_instruction(
+        _program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        _instruction_data: &[u8],
+    ) -> ProgramResult {
+        let account = next_account_info(&mut accounts.iter())?;
+        let balance = u64::from_le_bytes(account.try_borrow_data()?[..].try_into().unwrap());
+        set_return_data_borsh(&balance)
+    }
+
+    /// Asks the balance program for the balance of the second account and
+    /// stores it, doubled, in the first.
+    fn process_doubler_instruction(
+        _program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        _instruction_data: &[u8],
+    ) -> ProgramResult {
+        let account_iter = &mut accounts.iter();
+        let result_account = next_account_info(account_iter)?;
+        let balance_account = next_account_info(account_iter)?;
+
+        invoke(
+            &Instruction {
+                program_id: vault_program_id(),
+                accounts: vec![AccountMeta {
+                    pubkey: *balance_account.key,
+                    is_signer: false,
+                    is_writable: false,
+                }],
+                data: vec![],
+            },
+            accounts,
+        )?;
+        let (program_id, balance) = get_return_data_borsh::<u64>()?.unwrap();
+        assert_eq!(program_id, vault_program_id());
+
+        result_account.realloc(8, false)?;
+        result_account
+            .try_borrow_mut_data()?
+            .copy_from_slice(&(balance * 2).to_le_bytes());
+        Ok(())
+    }
+
+    #[test]
+    fn test_return_data() {
+        let mut setup = setup();
+        let program_test = &mut setup.program_test;
+        program_test.add_program(vault_program_id(), process_balance_instruction);
+        let view = |pubkey| Message {
+            signers: vec![],
+            instructions: vec![Instruction {
+                program_id: vault_program_id(),
+                accounts: vec![AccountMeta {
+                    pubkey,
+                    is_signer: false,
+                    is_writable: false,
+                }],
+                data: vec![],
+            }],
+        };
+
+        program_test
+            .process_message(&view(setup.user_tokens))
+            .unwrap();
+        assert_eq!(
+            program_test.return_data(),
+            Some(&(vault_program_id(), 100u64.to_le_bytes().to_vec()))
+        );
+
+        program_test.add_program(pool_program_id(), process_doubler_instruction);
+        let message = Message {
+            signers: vec![],
+            instructions: vec![Instruction {
+                program_id: pool_program_id(),
+                accounts: vec![
+                    AccountMeta {
+                        pubkey: setup.pool,
+                        is_signer: false,
+                        is_writable: true,
+                    },
+                    AccountMeta {
+                        pubkey: setup.user_tokens,
+                        is_signer: false,
+                        is_writable: false,
+                    },
+                ],
+                data: vec![],
+            }],
+        };
+        program_test.process_message(&message).unwrap();
+        assert_eq!(
+            program_test.get_account(&setup.pool).unwrap().data,
+            200u64.to_le_bytes()
+        );
+        // Return data is left as the callee set it.
+        assert_eq!(program_test.return_data().unwrap().0, vault_program_id());
+    }
+
+    /// Increments the first account's counter and checks the second account
+    /// sees the change.
+    fn process_duplicate_instruction(
+        _program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        _instruction_data: &[u8],
+    ) -> ProgramResult {
+        let account_iter = &mut accounts.iter();
+        let first = next_account_info(account_iter)?;
+        let second = next_account_info(account_iter)?;
+
+        first.try_borrow_mut_data()?[0] += 1;
+        if second.try_borrow_data()?[0] != first.try_borrow_data()?[0] || !second.is_writable {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_repeated_account_is_passed_as_duplicate() {
+        let mut program_test = ProgramTest::new();
+        program_test.add_program(pool_program_id(), process_duplicate_instruction);
+        let account = Pubkey::new_unique();
+        program_test.add_account(account, TestAccount::new(pool_program_id(), vec![0]));
+
+        // The second reference is read-only, but the message makes the
+        // account writable everywhere.
+        let message = Message {
+            signers: vec![],
+            instructions: vec![Instruction {
+                program_id: pool_program_id(),
+                accounts: vec![
+                    AccountMeta {
+                        pubkey: account,
+                        is_signer: false,
+                        is_writable: true,
+                    },
+                    AccountMeta {
+                        pubkey: account,
+                        is_signer: false,
+                        is_writable: false,
+                    },
+                ],
+                data: vec![],
+            }],
+        };
+        assert_eq!(
+            message.compile().unwrap().instructions[0].accounts,
+            vec![0, 0]
+        );
+
+        program_test.process_message(&message).unwrap();
+        program_test.process_message(&message).unwrap();
+        assert_eq!(program_test.get_account(&account).unwrap().data, vec![2]);
+    }
+}
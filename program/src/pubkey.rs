@@ -1,6 +1,24 @@
+#[cfg(all(not(target_os = "solana"), any(test, feature = "experimental-pda")))]
+use bitcoin::hashes::{sha256, Hash, HashEngine};
+use bitcoin::secp256k1::XOnlyPublicKey;
 use borsh::{BorshDeserialize, BorshSerialize};
 use serde::{Deserialize, Serialize};
 
+use crate::codec::{self, DecodeError, ParseHexError, Reader};
+#[cfg(all(not(target_os = "solana"), any(test, feature = "experimental-pda")))]
+use crate::program_error::ProgramError;
+
+/// Maximum length of a single seed used to derive a program address.
+#[cfg(all(not(target_os = "solana"), any(test, feature = "experimental-pda")))]
+pub const MAX_SEED_LEN: usize = 32;
+
+/// Maximum number of seeds used to derive a program address.
+#[cfg(all(not(target_os = "solana"), any(test, feature = "experimental-pda")))]
+pub const MAX_SEEDS: usize = 16;
+
+#[cfg(all(not(target_os = "solana"), any(test, feature = "experimental-pda")))]
+const PDA_MARKER: &[u8; 21] = b"ProgramDerivedAddress";
+
 #[repr(C)]
 #[derive(
     Clone,
@@ -24,10 +42,15 @@ impl Pubkey {
         self.0
     }
 
-    pub fn from_slice(data: &[u8]) -> Self {
-        let mut tmp = [0u8; 32];
-        tmp[..data.len()].copy_from_slice(data);
-        Self(tmp)
+    /// Reads a key from the first 32 bytes of `data`, failing if there are
+    /// fewer. Use `Pubkey::from([u8; 32])` when the bytes are already an
+    /// array.
+    pub fn from_slice(data: &[u8]) -> Result<Self, DecodeError> {
+        Self::decode(&mut Reader::new(data))
+    }
+
+    pub fn decode(reader: &mut Reader) -> Result<Self, DecodeError> {
+        Ok(Self(reader.read_array("pubkey")?))
     }
 
     pub fn system_program() -> Self {
@@ -55,9 +78,88 @@ impl Pubkey {
         Self::from(b)
     }
 
+    /// Whether this key is a valid x-only secp256k1 public key, i.e. whether
+    /// someone could hold a private key for it.
+    pub fn is_on_curve(&self) -> bool {
+        XOnlyPublicKey::from_slice(&self.0).is_ok()
+    }
+
     /// Log a `Pubkey` from a program
     pub fn log(&self) {
-        unsafe { crate::syscalls::sol_log_pubkey(self.as_ref() as *const _ as *const u8) };
+        #[cfg(target_os = "solana")]
+        unsafe {
+            crate::syscalls::sol_log_pubkey(self.as_ref() as *const _ as *const u8)
+        };
+        #[cfg(not(target_os = "solana"))]
+        crate::program_stubs::sol_log(&format!("{:x}", self));
+    }
+}
+
+/// Experimental program derived addresses, native only.
+///
+/// The derivation (seeds, program id and a fixed marker hashed with SHA-256,
+/// kept only if the result is not a valid x-only key) is not known to be the
+/// Arch runtime's, and the runtime's `sol_invoke_signed_rust` takes no signer
+/// seeds. Only [`ProgramTest`](crate::program_test::ProgramTest) honours
+/// these addresses, so they are behind the `experimental-pda` feature.
+#[cfg(all(not(target_os = "solana"), any(test, feature = "experimental-pda")))]
+impl Pubkey {
+    /// Derive a program address from `seeds` and `program_id`.
+    ///
+    /// Program addresses are never valid public keys, so nobody can sign for
+    /// them; only `program_id` can, through
+    /// [`invoke_signed`](crate::program::invoke_signed). Returns
+    /// [`ProgramError::InvalidSeeds`] if the derived address happens to be a
+    /// valid public key; [`Pubkey::find_program_address`] searches for a bump
+    /// seed that avoids this.
+    pub fn create_program_address(
+        seeds: &[&[u8]],
+        program_id: &Pubkey,
+    ) -> Result<Pubkey, ProgramError> {
+        if seeds.len() > MAX_SEEDS || seeds.iter().any(|seed| seed.len() > MAX_SEED_LEN) {
+            return Err(ProgramError::MaxSeedLengthExceeded);
+        }
+
+        let mut engine = sha256::Hash::engine();
+        for seed in seeds {
+            engine.input(seed);
+        }
+        engine.input(program_id.as_ref());
+        engine.input(PDA_MARKER);
+        let address = Pubkey(sha256::Hash::from_engine(engine).to_byte_array());
+
+        if address.is_on_curve() {
+            return Err(ProgramError::InvalidSeeds);
+        }
+        Ok(address)
+    }
+
+    /// Find a program address and the bump seed that, appended to `seeds`,
+    /// makes it a valid program address.
+    ///
+    /// Bump seeds are tried from 255 downwards, so the result is
+    /// deterministic. Returns `None` if no bump seed works, which is
+    /// astronomically unlikely, or if `seeds` is too long.
+    pub fn try_find_program_address(seeds: &[&[u8]], program_id: &Pubkey) -> Option<(Pubkey, u8)> {
+        let mut bump_seed = [u8::MAX];
+        for _ in 0..u8::MAX {
+            let mut seeds_with_bump = seeds.to_vec();
+            seeds_with_bump.push(&bump_seed);
+            match Self::create_program_address(&seeds_with_bump, program_id) {
+                Ok(address) => return Some((address, bump_seed[0])),
+                Err(ProgramError::InvalidSeeds) => (),
+                Err(_) => break,
+            }
+            bump_seed[0] -= 1;
+        }
+        None
+    }
+
+    /// Like [`Pubkey::try_find_program_address`], but panics if no program
+    /// address can be found.
+    pub fn find_program_address(seeds: &[&[u8]], program_id: &Pubkey) -> (Pubkey, u8) {
+        Self::try_find_program_address(seeds, program_id)
+            .unwrap_or_else(|| panic!("Unable to find a viable program address bump seed"))
     }
 }
 
@@ -72,12 +174,20 @@ impl std::fmt::LowerHex for Pubkey {
 }
 
 use core::fmt;
+use core::str::FromStr;
 
-/// TODO:
-///  Change this in future according to the correct base implementation
+/// The key as 64 lowercase hex characters, the same as `{:x}`.
 impl fmt::Display for Pubkey {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{:?}", self.0)
+        fmt::LowerHex::fmt(self, f)
+    }
+}
+
+impl FromStr for Pubkey {
+    type Err = ParseHexError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        codec::parse_hex32(s).map(Self)
     }
 }
 
@@ -101,16 +211,80 @@ impl From<[u8; 32]> for Pubkey {
 
 #[cfg(test)]
 mod tests {
-    use crate::pubkey::Pubkey;
+    use crate::{
+        codec::ParseHexError,
+        program_error::ProgramError,
+        pubkey::{Pubkey, MAX_SEED_LEN},
+    };
     use proptest::prelude::*;
 
+    #[test]
+    fn test_find_program_address() {
+        let program_id = Pubkey::new_unique();
+        let (address, bump_seed) = Pubkey::find_program_address(&[b"pool", &[1]], &program_id);
+
+        assert_eq!(
+            (address, bump_seed),
+            Pubkey::find_program_address(&[b"pool", &[1]], &program_id)
+        );
+        assert_eq!(
+            Pubkey::create_program_address(&[b"pool", &[1], &[bump_seed]], &program_id),
+            Ok(address)
+        );
+        assert!(!address.is_on_curve());
+        assert_ne!(
+            address,
+            Pubkey::find_program_address(&[b"pool", &[2]], &program_id).0
+        );
+    }
+
+    #[test]
+    fn test_create_program_address_max_seed_length() {
+        let program_id = Pubkey::new_unique();
+        let long_seed = [0u8; MAX_SEED_LEN + 1];
+
+        assert_eq!(
+            Pubkey::create_program_address(&[&long_seed], &program_id),
+            Err(ProgramError::MaxSeedLengthExceeded)
+        );
+        assert_eq!(
+            Pubkey::try_find_program_address(&[&long_seed], &program_id),
+            None
+        );
+        assert_eq!(
+            Pubkey::create_program_address(&[&[0u8][..]; 17], &program_id),
+            Err(ProgramError::MaxSeedLengthExceeded)
+        );
+    }
+
+    #[test]
+    fn test_parse_pubkey() {
+        assert_eq!(
+            Pubkey::system_program().to_string(),
+            format!("{}01", "0".repeat(62))
+        );
+        assert_eq!("01".parse::<Pubkey>(), Err(ParseHexError::InvalidLength(2)));
+        assert_eq!(
+            "g".repeat(64).parse::<Pubkey>(),
+            Err(ParseHexError::InvalidHex)
+        );
+    }
+
     proptest! {
         #[test]
         fn fuzz_serialize_deserialize_pubkey(data in any::<[u8; 32]>()) {
             let pubkey = Pubkey::from(data);
             let serialized = pubkey.serialize();
-            let deserialized = Pubkey::from_slice(&serialized);
+            let deserialized = Pubkey::from_slice(&serialized).unwrap();
             assert_eq!(pubkey, deserialized);
+            prop_assert!(Pubkey::from_slice(&serialized[1..]).is_err());
+        }
+
+        #[test]
+        fn fuzz_pubkey_string_roundtrip(data in any::<[u8; 32]>()) {
+            let pubkey = Pubkey::from(data);
+            prop_assert_eq!(pubkey.to_string().parse::<Pubkey>(), Ok(pubkey));
+            prop_assert_eq!(pubkey.to_string(), format!("{:x}", pubkey));
         }
     }
 }
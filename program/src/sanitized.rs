@@ -1,9 +1,76 @@
+//! Messages compiled into a shared account table.
+//!
+//! A [`SanitizedMessage`] lists every account the message touches once, in
+//! [`account_keys`](SanitizedMessage::account_keys), and its instructions
+//! refer to accounts by their index in that table. An account's privileges
+//! are merged across the message: it is a signer if it signs anywhere and
+//! writable if any instruction writes to it.
+//!
+//! The table is ordered writable signers, read-only signers, writable
+//! non-signers, then read-only non-signers, each group in order of first
+//! appearance, so the [`MessageHeader`] counts are enough to recover every
+//! account's privileges from its index.
+//!
+//! [`SanitizedMessage::serialize`] encodes the compiled form with compact
+//! integers for every count and index, so it has no limit below what an
+//! index can address; this is the message layout of version 1 transactions.
+
+use thiserror::Error;
+
+use crate::account::AccountMeta;
+use crate::codec::{self, DecodeError, Reader};
+use crate::hash::Hash;
+use crate::instruction::{Instruction, InstructionError};
+use crate::message::Message;
 use crate::pubkey::Pubkey;
 
-#[derive(Debug, Clone)]
+/// Reasons a message could not be compiled or a compiled one is malformed
+#[derive(Clone, Debug, Eq, Error, PartialEq)]
+pub enum SanitizeError {
+    #[error("account {0:x} must sign but is not one of the message signers")]
+    MissingSigner(Pubkey),
+    #[error("message references {0} accounts, more than an index can address")]
+    TooManyAccounts(usize),
+    #[error("account {0:x} appears more than once in the account table")]
+    DuplicateAccount(Pubkey),
+    #[error("header counts do not fit an account table of {0} keys")]
+    InvalidHeader(usize),
+    #[error(
+        "instruction {instruction} references account {index}, past the end of the account table"
+    )]
+    AccountIndexOutOfRange { instruction: usize, index: u16 },
+}
+
+impl From<SanitizeError> for InstructionError {
+    fn from(error: SanitizeError) -> Self {
+        match error {
+            SanitizeError::MissingSigner(_) => InstructionError::MissingRequiredSignature,
+            _ => InstructionError::InvalidArgument,
+        }
+    }
+}
+
+/// How the account table of a [`SanitizedMessage`] splits into privilege
+/// groups.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct MessageHeader {
+    /// The leading accounts that sign the message.
+    pub num_required_signatures: u16,
+    /// The read-only accounts at the end of the signers.
+    pub num_readonly_signed_accounts: u16,
+    /// The read-only accounts at the end of the table.
+    pub num_readonly_unsigned_accounts: u16,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct SanitizedMessage {
-    pub signers: Vec<Pubkey>,
+    pub header: MessageHeader,
+    pub account_keys: Vec<Pubkey>,
     pub instructions: Vec<SanitizedInstruction>,
+    /// The block hash a version 1 transaction carries; see
+    /// `RuntimeTransaction::recent_blockhash` in `common`. Compiling a
+    /// [`Message`] leaves it unset.
+    pub recent_blockhash: Option<[u8; 32]>,
 }
 
 #[derive(Clone, Debug, Eq, PartialEq)]
@@ -12,3 +79,503 @@ pub struct SanitizedInstruction {
     pub accounts: Vec<u16>,
     pub data: Vec<u8>,
 }
+
+impl SanitizedMessage {
+    /// Build the account table of `message` and index its instructions into
+    /// it.
+    ///
+    /// Every account an instruction requires to sign must be one of the
+    /// message's `signers`.
+    pub fn compile(message: &Message) -> Result<Self, SanitizeError> {
+        // Accounts in order of first appearance, with merged privileges.
+        let mut accounts: Vec<AccountMeta> = vec![];
+        let mut merge = |pubkey: &Pubkey, is_signer: bool, is_writable: bool| match accounts
+            .iter_mut()
+            .find(|meta| meta.pubkey == *pubkey)
+        {
+            Some(meta) => {
+                meta.is_signer |= is_signer;
+                meta.is_writable |= is_writable;
+            }
+            None => accounts.push(AccountMeta {
+                pubkey: *pubkey,
+                is_signer,
+                is_writable,
+            }),
+        };
+        for signer in message.signers.iter() {
+            merge(signer, true, false);
+        }
+        for instruction in message.instructions.iter() {
+            for meta in instruction.accounts.iter() {
+                if meta.is_signer && !message.signers.contains(&meta.pubkey) {
+                    return Err(SanitizeError::MissingSigner(meta.pubkey));
+                }
+                merge(&meta.pubkey, meta.is_signer, meta.is_writable);
+            }
+        }
+        if accounts.len() > u16::MAX as usize {
+            return Err(SanitizeError::TooManyAccounts(accounts.len()));
+        }
+
+        let group = |is_signer: bool, is_writable: bool| {
+            accounts
+                .iter()
+                .filter(move |meta| meta.is_signer == is_signer && meta.is_writable == is_writable)
+                .map(|meta| meta.pubkey)
+        };
+        let account_keys: Vec<Pubkey> = group(true, true)
+            .chain(group(true, false))
+            .chain(group(false, true))
+            .chain(group(false, false))
+            .collect();
+        let header = MessageHeader {
+            num_required_signatures: group(true, true).chain(group(true, false)).count() as u16,
+            num_readonly_signed_accounts: group(true, false).count() as u16,
+            num_readonly_unsigned_accounts: group(false, false).count() as u16,
+        };
+
+        let index_of = |pubkey: &Pubkey| {
+            account_keys
+                .iter()
+                .position(|key| key == pubkey)
+                .expect("every account was added to the table") as u16
+        };
+        let instructions = message
+            .instructions
+            .iter()
+            .map(|instruction| SanitizedInstruction {
+                program_id: instruction.program_id,
+                accounts: instruction
+                    .accounts
+                    .iter()
+                    .map(|meta| index_of(&meta.pubkey))
+                    .collect(),
+                data: instruction.data.clone(),
+            })
+            .collect();
+
+        let sanitized = Self {
+            header,
+            account_keys,
+            instructions,
+            recent_blockhash: None,
+        };
+        sanitized.sanitize()?;
+        Ok(sanitized)
+    }
+
+    /// Check that the header fits the account table, that no account is
+    /// listed twice and that every instruction only references accounts in
+    /// the table.
+    pub fn sanitize(&self) -> Result<(), SanitizeError> {
+        let len = self.account_keys.len();
+        if len > u16::MAX as usize {
+            return Err(SanitizeError::TooManyAccounts(len));
+        }
+        let header = &self.header;
+        if header.num_readonly_signed_accounts > header.num_required_signatures
+            || header.num_required_signatures as usize
+                + header.num_readonly_unsigned_accounts as usize
+                > len
+        {
+            return Err(SanitizeError::InvalidHeader(len));
+        }
+
+        for (index, key) in self.account_keys.iter().enumerate() {
+            if self.account_keys[..index].contains(key) {
+                return Err(SanitizeError::DuplicateAccount(*key));
+            }
+        }
+
+        for (instruction_index, instruction) in self.instructions.iter().enumerate() {
+            if let Some(index) = instruction
+                .accounts
+                .iter()
+                .find(|index| **index as usize >= len)
+            {
+                return Err(SanitizeError::AccountIndexOutOfRange {
+                    instruction: instruction_index,
+                    index: *index,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// The accounts that sign the message.
+    pub fn signers(&self) -> &[Pubkey] {
+        &self.account_keys[..self.header.num_required_signatures as usize]
+    }
+
+    pub fn is_signer(&self, index: usize) -> bool {
+        index < self.header.num_required_signatures as usize
+    }
+
+    pub fn is_writable(&self, index: usize) -> bool {
+        let header = &self.header;
+        let num_signers = header.num_required_signatures as usize;
+        if index < num_signers {
+            index < num_signers - header.num_readonly_signed_accounts as usize
+        } else {
+            index < self.account_keys.len() - header.num_readonly_unsigned_accounts as usize
+        }
+    }
+
+    /// The account at `index` in the table, with its merged privileges.
+    pub fn account_meta(&self, index: usize) -> AccountMeta {
+        AccountMeta {
+            pubkey: self.account_keys[index],
+            is_signer: self.is_signer(index),
+            is_writable: self.is_writable(index),
+        }
+    }
+
+    /// Expand the message back into full instructions. Accounts carry their
+    /// merged privileges, so they may be more privileged than in the message
+    /// this was compiled from.
+    pub fn decompile(&self) -> Message {
+        Message {
+            signers: self.signers().to_vec(),
+            instructions: self
+                .instructions
+                .iter()
+                .map(|instruction| Instruction {
+                    program_id: instruction.program_id,
+                    accounts: instruction
+                        .accounts
+                        .iter()
+                        .map(|index| self.account_meta(*index as usize))
+                        .collect(),
+                    data: instruction.data.clone(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Double SHA-256 of the [encoded](Self::serialize) compiled message,
+    /// which version 1 transactions are signed over.
+    pub fn hash(&self) -> Hash {
+        Hash::digest(&self.serialize())
+    }
+
+    /// Encode the compiled message: the optional block hash behind a
+    /// presence byte, the header counts, the account table and the
+    /// instructions, every count and index a compact integer.
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut serialized = vec![];
+
+        match &self.recent_blockhash {
+            Some(recent_blockhash) => {
+                serialized.push(1);
+                serialized.extend(recent_blockhash);
+            }
+            None => serialized.push(0),
+        }
+        for count in [
+            self.header.num_required_signatures,
+            self.header.num_readonly_signed_accounts,
+            self.header.num_readonly_unsigned_accounts,
+        ] {
+            codec::write_compact(&mut serialized, count.into());
+        }
+        codec::write_compact_len(&mut serialized, self.account_keys.len());
+        for key in self.account_keys.iter() {
+            serialized.extend(&key.serialize());
+        }
+        codec::write_compact_len(&mut serialized, self.instructions.len());
+        for instruction in self.instructions.iter() {
+            serialized.extend(&instruction.program_id.serialize());
+            codec::write_compact_len(&mut serialized, instruction.accounts.len());
+            for index in instruction.accounts.iter() {
+                codec::write_compact(&mut serialized, (*index).into());
+            }
+            codec::write_compact_len(&mut serialized, instruction.data.len());
+            serialized.extend(&instruction.data);
+        }
+
+        serialized
+    }
+
+    pub fn from_slice(data: &[u8]) -> Result<Self, DecodeError> {
+        Self::decode(&mut Reader::new(data))
+    }
+
+    /// Decodes a compiled message from the front of `reader`, leaving it
+    /// positioned just past the last instruction. The result is not
+    /// [sanitized](Self::sanitize).
+    pub fn decode(reader: &mut Reader) -> Result<Self, DecodeError> {
+        let recent_blockhash = match reader.read_u8("sanitized_message.recent_blockhash")? {
+            0 => None,
+            1 => Some(reader.read_array("sanitized_message.recent_blockhash")?),
+            tag => {
+                return Err(DecodeError::InvalidTag {
+                    field: "sanitized_message.recent_blockhash",
+                    offset: reader.offset() - 1,
+                    tag,
+                })
+            }
+        };
+        let header = MessageHeader {
+            num_required_signatures: reader
+                .read_compact_u16("sanitized_message.num_required_signatures")?,
+            num_readonly_signed_accounts: reader
+                .read_compact_u16("sanitized_message.num_readonly_signed_accounts")?,
+            num_readonly_unsigned_accounts: reader
+                .read_compact_u16("sanitized_message.num_readonly_unsigned_accounts")?,
+        };
+
+        let keys_len = reader.read_compact_len("sanitized_message.account_keys.len")?;
+        let mut account_keys = Vec::with_capacity(reader.capacity_for(keys_len, 32));
+        for _ in 0..keys_len {
+            account_keys.push(Pubkey::from(
+                reader.read_array("sanitized_message.account_keys")?,
+            ));
+        }
+
+        let instructions_len = reader.read_compact_len("sanitized_message.instructions.len")?;
+        let mut instructions = Vec::with_capacity(reader.capacity_for(instructions_len, 35));
+        for _ in 0..instructions_len {
+            let program_id = Pubkey::from(reader.read_array("sanitized_instruction.program_id")?);
+            let accounts_len = reader.read_compact_len("sanitized_instruction.accounts.len")?;
+            let mut accounts = Vec::with_capacity(reader.capacity_for(accounts_len, 1));
+            for _ in 0..accounts_len {
+                accounts.push(reader.read_compact_u16("sanitized_instruction.accounts")?);
+            }
+            let data_len = reader.read_compact_len("sanitized_instruction.data.len")?;
+            let data = reader
+                .read_bytes("sanitized_instruction.data", data_len)?
+                .to_vec();
+            instructions.push(SanitizedInstruction {
+                program_id,
+                accounts,
+                data,
+            });
+        }
+
+        Ok(Self {
+            header,
+            account_keys,
+            instructions,
+            recent_blockhash,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MessageHeader, SanitizeError, SanitizedInstruction, SanitizedMessage};
+    use crate::{account::AccountMeta, instruction::Instruction, message::Message, pubkey::Pubkey};
+
+    use proptest::prelude::*;
+
+    fn meta(pubkey: Pubkey, is_signer: bool, is_writable: bool) -> AccountMeta {
+        AccountMeta {
+            pubkey,
+            is_signer,
+            is_writable,
+        }
+    }
+
+    #[test]
+    fn test_compile_orders_and_merges_accounts() {
+        let (payer, user, pool, oracle) = (
+            Pubkey::from([1; 32]),
+            Pubkey::from([2; 32]),
+            Pubkey::from([3; 32]),
+            Pubkey::from([4; 32]),
+        );
+        let program_id = Pubkey::from([9; 32]);
+        let message = Message {
+            signers: vec![payer, user],
+            instructions: vec![
+                Instruction {
+                    program_id,
+                    accounts: vec![
+                        meta(oracle, false, false),
+                        meta(user, true, false),
+                        meta(pool, false, false),
+                    ],
+                    data: vec![1],
+                },
+                Instruction {
+                    program_id,
+                    accounts: vec![meta(pool, false, true), meta(pool, false, true)],
+                    data: vec![2],
+                },
+            ],
+        };
+
+        let sanitized = message.compile().unwrap();
+        assert_eq!(sanitized.account_keys, vec![payer, user, pool, oracle]);
+        assert_eq!(
+            sanitized.header,
+            MessageHeader {
+                num_required_signatures: 2,
+                num_readonly_signed_accounts: 2,
+                num_readonly_unsigned_accounts: 1,
+            }
+        );
+        assert_eq!(sanitized.signers(), &[payer, user]);
+        assert_eq!(
+            sanitized.instructions,
+            vec![
+                SanitizedInstruction {
+                    program_id,
+                    accounts: vec![3, 1, 2],
+                    data: vec![1],
+                },
+                SanitizedInstruction {
+                    program_id,
+                    accounts: vec![2, 2],
+                    data: vec![2],
+                },
+            ]
+        );
+        assert_eq!(
+            sanitized.decompile().instructions[0].accounts,
+            vec![
+                meta(oracle, false, false),
+                meta(user, true, false),
+                meta(pool, false, true),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_compile_rejects_missing_signer() {
+        let user = Pubkey::from([2; 32]);
+        let message = Message {
+            signers: vec![],
+            instructions: vec![Instruction {
+                program_id: Pubkey::system_program(),
+                accounts: vec![meta(user, true, true)],
+                data: vec![],
+            }],
+        };
+
+        assert_eq!(message.compile(), Err(SanitizeError::MissingSigner(user)));
+    }
+
+    #[test]
+    fn test_sanitize_rejects_malformed_messages() {
+        let valid = SanitizedMessage {
+            header: MessageHeader {
+                num_required_signatures: 1,
+                num_readonly_signed_accounts: 0,
+                num_readonly_unsigned_accounts: 1,
+            },
+            account_keys: vec![Pubkey::from([1; 32]), Pubkey::from([2; 32])],
+            instructions: vec![SanitizedInstruction {
+                program_id: Pubkey::system_program(),
+                accounts: vec![0, 1],
+                data: vec![],
+            }],
+            recent_blockhash: None,
+        };
+        assert_eq!(valid.sanitize(), Ok(()));
+
+        let mut message = valid.clone();
+        message.instructions[0].accounts.push(2);
+        assert_eq!(
+            message.sanitize(),
+            Err(SanitizeError::AccountIndexOutOfRange {
+                instruction: 0,
+                index: 2,
+            })
+        );
+
+        let mut message = valid.clone();
+        message.header.num_readonly_unsigned_accounts = 2;
+        assert_eq!(message.sanitize(), Err(SanitizeError::InvalidHeader(2)));
+
+        let mut message = valid;
+        message.account_keys[1] = message.account_keys[0];
+        assert_eq!(
+            message.sanitize(),
+            Err(SanitizeError::DuplicateAccount(Pubkey::from([1; 32])))
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn fuzz_compile_message(
+            keys in prop::collection::vec(0..6u8, 0..24),
+            is_signer_flags in prop::collection::vec(any::<bool>(), 24),
+            is_writable_flags in prop::collection::vec(any::<bool>(), 24),
+            instruction_lens in prop::collection::vec(0..4usize, 0..6),
+            recent_blockhash in prop::option::of(any::<[u8; 32]>()),
+        ) {
+            let metas: Vec<AccountMeta> = keys
+                .iter()
+                .zip(is_signer_flags.iter().zip(is_writable_flags.iter()))
+                .map(|(key, (is_signer, is_writable))| {
+                    meta(Pubkey::from([*key; 32]), *is_signer, *is_writable)
+                })
+                .collect();
+            let mut remaining = &metas[..];
+            let instructions: Vec<Instruction> = instruction_lens
+                .iter()
+                .map(|len| {
+                    let (accounts, rest) = remaining.split_at((*len).min(remaining.len()));
+                    remaining = rest;
+                    Instruction {
+                        program_id: Pubkey::system_program(),
+                        accounts: accounts.to_vec(),
+                        data: vec![*len as u8],
+                    }
+                })
+                .collect();
+            let mut signers: Vec<Pubkey> = vec![];
+            for meta in instructions.iter().flat_map(|ix| ix.accounts.iter()) {
+                if meta.is_signer && !signers.contains(&meta.pubkey) {
+                    signers.push(meta.pubkey);
+                }
+            }
+            let message = Message {
+                signers,
+                instructions,
+            };
+
+            let mut sanitized = message.compile().unwrap();
+            prop_assert_eq!(sanitized.recent_blockhash, None);
+            sanitized.recent_blockhash = recent_blockhash;
+            prop_assert_eq!(sanitized.sanitize(), Ok(()));
+
+            let serialized = sanitized.serialize();
+            prop_assert_eq!(SanitizedMessage::from_slice(&serialized), Ok(sanitized.clone()));
+            for len in 0..serialized.len() {
+                prop_assert!(SanitizedMessage::from_slice(&serialized[..len]).is_err());
+            }
+
+            let decompiled = sanitized.decompile();
+            prop_assert_eq!(decompiled.instructions.len(), message.instructions.len());
+            for (original, decompiled) in message.instructions.iter().zip(decompiled.instructions.iter()) {
+                prop_assert_eq!(&original.data, &decompiled.data);
+                prop_assert_eq!(original.accounts.len(), decompiled.accounts.len());
+                for (original, decompiled) in original.accounts.iter().zip(decompiled.accounts.iter()) {
+                    prop_assert_eq!(original.pubkey, decompiled.pubkey);
+                    prop_assert!(decompiled.is_signer || !original.is_signer);
+                    prop_assert!(decompiled.is_writable || !original.is_writable);
+                }
+            }
+            for signer in message.signers.iter() {
+                prop_assert!(sanitized.signers().contains(signer));
+            }
+
+            // A decompiled message is already in table form.
+            prop_assert_eq!(
+                decompiled.compile(),
+                Ok(SanitizedMessage {
+                    recent_blockhash: None,
+                    ..sanitized
+                })
+            );
+        }
+
+        #[test]
+        fn fuzz_deserialize_arbitrary_sanitized_message(data in prop::collection::vec(any::<u8>(), 0..1024)) {
+            let _ = SanitizedMessage::from_slice(&data);
+        }
+    }
+}
@@ -0,0 +1,135 @@
+//! Serde adapters that encode keys, hashes and signatures as their hex
+//! strings in human-readable formats such as JSON.
+//!
+//! [`Pubkey`](crate::pubkey::Pubkey), [`Hash`](crate::hash::Hash) and
+//! [`UtxoMeta`](crate::utxo::UtxoMeta) serialize as their bytes by default,
+//! which is what the node's JSON-RPC expects. Annotate a field to get the
+//! hex form instead; binary formats are unaffected.
+//!
+//! ```
+//! use arch_program::pubkey::Pubkey;
+//! use serde::{Deserialize, Serialize};
+//!
+//! #[derive(Serialize, Deserialize)]
+//! struct Owner {
+//!     #[serde(with = "arch_program::serde_hex")]
+//!     owner: Pubkey,
+//!     #[serde(with = "arch_program::serde_hex::vec")]
+//!     signers: Vec<Pubkey>,
+//! }
+//! ```
+//!
+//! Any type whose `Display` and `FromStr` round-trip works with these
+//! adapters, which is the case for all three.
+
+use core::fmt::Display;
+use core::str::FromStr;
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+pub fn serialize<T, S>(value: &T, serializer: S) -> Result<S::Ok, S::Error>
+where
+    T: Display + Serialize,
+    S: Serializer,
+{
+    if serializer.is_human_readable() {
+        serializer.collect_str(value)
+    } else {
+        value.serialize(serializer)
+    }
+}
+
+pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+where
+    T: FromStr + Deserialize<'de>,
+    T::Err: Display,
+    D: Deserializer<'de>,
+{
+    if deserializer.is_human_readable() {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(de::Error::custom)
+    } else {
+        T::deserialize(deserializer)
+    }
+}
+
+/// The same encoding for each element of a `Vec`.
+pub mod vec {
+    use core::fmt::Display;
+    use core::str::FromStr;
+
+    use serde::ser::SerializeSeq;
+    use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<T, S>(values: &[T], serializer: S) -> Result<S::Ok, S::Error>
+    where
+        T: Display + Serialize,
+        S: Serializer,
+    {
+        if serializer.is_human_readable() {
+            let mut seq = serializer.serialize_seq(Some(values.len()))?;
+            for value in values {
+                seq.serialize_element(&value.to_string())?;
+            }
+            seq.end()
+        } else {
+            values.serialize(serializer)
+        }
+    }
+
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<Vec<T>, D::Error>
+    where
+        T: FromStr + Deserialize<'de>,
+        T::Err: Display,
+        D: Deserializer<'de>,
+    {
+        if deserializer.is_human_readable() {
+            Vec::<String>::deserialize(deserializer)?
+                .iter()
+                .map(|s| s.parse().map_err(de::Error::custom))
+                .collect()
+        } else {
+            Vec::<T>::deserialize(deserializer)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pubkey::Pubkey;
+
+    use proptest::prelude::*;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Owner {
+        #[serde(with = "crate::serde_hex")]
+        owner: Pubkey,
+        #[serde(with = "crate::serde_hex::vec")]
+        signers: Vec<Pubkey>,
+        plain: Pubkey,
+    }
+
+    proptest! {
+        #[test]
+        fn fuzz_serde_hex(
+            owner in any::<[u8; 32]>(),
+            signers in prop::collection::vec(any::<[u8; 32]>(), 0..4),
+        ) {
+            let value = Owner {
+                owner: Pubkey::from(owner),
+                signers: signers.into_iter().map(Pubkey::from).collect(),
+                plain: Pubkey::from(owner),
+            };
+
+            let json = serde_json::to_value(&value).unwrap();
+            prop_assert_eq!(&json["owner"], &serde_json::json!(value.owner.to_string()));
+            prop_assert_eq!(
+                &json["signers"],
+                &serde_json::json!(value.signers.iter().map(Pubkey::to_string).collect::<Vec<_>>())
+            );
+            prop_assert_eq!(&json["plain"], &serde_json::json!(owner));
+            prop_assert_eq!(serde_json::from_value::<Owner>(json).unwrap(), value);
+        }
+    }
+}
@@ -1,4 +1,5 @@
 use crate::account::AccountMeta;
+use crate::codec::{DecodeError, Reader};
 use crate::instruction::Instruction;
 use crate::pubkey::Pubkey;
 use crate::utxo::UtxoMeta;
@@ -8,6 +9,14 @@ pub enum SystemInstruction {
     CreateAccount(UtxoMeta),
     ExtendBytes(Vec<u8>),
     MakeExecutable(Vec<u8>),
+    /// Hand a system-owned account over to a program.
+    Assign(Pubkey),
+    /// Set the length of a system-owned account's data, truncating it or
+    /// zero-filling the new bytes. It can grow by at most
+    /// `MAX_PERMITTED_DATA_INCREASE` bytes at a time.
+    Resize(u64),
+    /// Clear a system-owned account's data and release the UTXO anchoring it.
+    CloseAccount,
 }
 
 impl SystemInstruction {
@@ -27,19 +36,44 @@ impl SystemInstruction {
                 serialized.push(2);
                 serialized.extend(bytes);
             }
+            Self::Assign(owner) => {
+                serialized.push(3);
+                serialized.extend(owner.serialize());
+            }
+            Self::Resize(new_len) => {
+                serialized.push(4);
+                serialized.extend(new_len.to_le_bytes());
+            }
+            Self::CloseAccount => serialized.push(5),
         }
 
         serialized
     }
 
-    pub fn from_slice(data: &[u8]) -> Self {
-        match data[0] {
-            0 => Self::CreateAccount(UtxoMeta::from_slice(&data[1..])),
-            1 => Self::ExtendBytes(data[1..].to_vec()),
-            2 => Self::MakeExecutable(data[1..].to_vec()),
-            _ => {
-                unreachable!("error deserializing system instruction")
-            }
+    pub fn from_slice(data: &[u8]) -> Result<Self, DecodeError> {
+        let mut reader = Reader::new(data);
+        match reader.read_u8("system_instruction.tag")? {
+            0 => Ok(Self::CreateAccount(UtxoMeta::decode(&mut reader)?)),
+            1 => Ok(Self::ExtendBytes(
+                reader
+                    .read_bytes("extend_bytes.data", reader.remaining())?
+                    .to_vec(),
+            )),
+            2 => Ok(Self::MakeExecutable(
+                reader
+                    .read_bytes("make_executable.data", reader.remaining())?
+                    .to_vec(),
+            )),
+            3 => Ok(Self::Assign(Pubkey::from(
+                reader.read_array::<32>("assign.owner")?,
+            ))),
+            4 => Ok(Self::Resize(reader.read_u64("resize.new_len")?)),
+            5 => Ok(Self::CloseAccount),
+            tag => Err(DecodeError::InvalidTag {
+                field: "system_instruction.tag",
+                offset: 0,
+                tag,
+            }),
         }
     }
 
@@ -82,14 +116,70 @@ impl SystemInstruction {
             data: SystemInstruction::MakeExecutable(data).serialise(),
         }
     }
+
+    pub fn new_assign_instruction(owner: Pubkey, pubkey: Pubkey) -> Instruction {
+        Instruction {
+            program_id: Pubkey::system_program(),
+            accounts: vec![AccountMeta {
+                pubkey,
+                is_signer: true,
+                is_writable: true,
+            }],
+            data: SystemInstruction::Assign(owner).serialise(),
+        }
+    }
+
+    pub fn new_resize_instruction(new_len: u64, pubkey: Pubkey) -> Instruction {
+        Instruction {
+            program_id: Pubkey::system_program(),
+            accounts: vec![AccountMeta {
+                pubkey,
+                is_signer: true,
+                is_writable: true,
+            }],
+            data: SystemInstruction::Resize(new_len).serialise(),
+        }
+    }
+
+    pub fn new_close_account_instruction(pubkey: Pubkey) -> Instruction {
+        Instruction {
+            program_id: Pubkey::system_program(),
+            accounts: vec![AccountMeta {
+                pubkey,
+                is_signer: true,
+                is_writable: true,
+            }],
+            data: SystemInstruction::CloseAccount.serialise(),
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::SystemInstruction;
-    use crate::utxo::UtxoMeta;
+    use crate::{codec::DecodeError, pubkey::Pubkey, utxo::UtxoMeta};
     use proptest::prelude::*;
 
+    #[test]
+    fn test_from_slice_rejects_unknown_tag() {
+        assert_eq!(
+            SystemInstruction::from_slice(&[6, 0, 0]),
+            Err(DecodeError::InvalidTag {
+                field: "system_instruction.tag",
+                offset: 0,
+                tag: 6,
+            })
+        );
+        assert!(SystemInstruction::from_slice(&[]).is_err());
+        assert!(SystemInstruction::from_slice(&[0; 36]).is_err());
+        assert!(SystemInstruction::from_slice(&[3; 32]).is_err());
+        assert!(SystemInstruction::from_slice(&[4, 0, 0, 0]).is_err());
+        assert_eq!(
+            SystemInstruction::from_slice(&[5]),
+            Ok(SystemInstruction::CloseAccount)
+        );
+    }
+
     proptest! {
         #[test]
         fn fuzz_serialize_deserialize_system_instruction_create_account(
@@ -100,7 +190,7 @@ mod tests {
             let instruction = SystemInstruction::CreateAccount(UtxoMeta::from(txid, vout));
 
             let serialized = instruction.serialise();
-            let deserialized = SystemInstruction::from_slice(&serialized);
+            let deserialized = SystemInstruction::from_slice(&serialized).unwrap();
 
             assert_eq!(instruction, deserialized);
         }
@@ -114,7 +204,31 @@ mod tests {
             let instruction = SystemInstruction::ExtendBytes(random_bytes.clone());
 
             let serialized = instruction.serialise();
-            let deserialized = SystemInstruction::from_slice(&serialized);
+            let deserialized = SystemInstruction::from_slice(&serialized).unwrap();
+
+            assert_eq!(instruction, deserialized);
+        }
+
+        #[test]
+        fn fuzz_serialize_deserialize_system_instruction_assign(
+            owner in any::<[u8; 32]>(),
+        ) {
+            let instruction = SystemInstruction::Assign(Pubkey::from(owner));
+
+            let serialized = instruction.serialise();
+            let deserialized = SystemInstruction::from_slice(&serialized).unwrap();
+
+            assert_eq!(instruction, deserialized);
+        }
+
+        #[test]
+        fn fuzz_serialize_deserialize_system_instruction_resize(
+            new_len in any::<u64>(),
+        ) {
+            let instruction = SystemInstruction::Resize(new_len);
+
+            let serialized = instruction.serialise();
+            let deserialized = SystemInstruction::from_slice(&serialized).unwrap();
 
             assert_eq!(instruction, deserialized);
         }
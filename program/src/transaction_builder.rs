@@ -0,0 +1,453 @@
+//! Composition of the Bitcoin transaction a program asks the network to sign.
+//!
+//! A [`TransactionBuilder`] lays the transaction out the way
+//! [`set_transaction_to_sign`](crate::program::set_transaction_to_sign)
+//! expects: each account's state transition spends the account's UTXO at
+//! input `i` and recreates it at output `i`, so the account can be moved to
+//! the new UTXO once the transaction is signed. Fee inputs come after the
+//! state transitions, followed by any payout outputs and the change.
+
+use std::collections::BTreeMap;
+
+use bitcoin::{
+    absolute::LockTime, transaction::Version, Amount, ScriptBuf, Sequence, Transaction, TxIn,
+    TxOut, Witness,
+};
+
+use crate::{
+    account::AccountInfo,
+    input_to_sign::InputToSign,
+    program::{get_account_script_pubkey, get_bitcoin_tx},
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    transaction_to_sign::TransactionToSign,
+    utxo::UtxoMeta,
+};
+
+/// Builds a state-transition transaction and the [`TransactionToSign`] for it.
+///
+/// ```ignore
+/// let mut builder = TransactionBuilder::new();
+/// builder.add_state_transition(pool_account)?;
+/// builder.add_fee_input(&fee_utxo, *pool_account.key)?;
+/// builder.add_output(payout, user_script_pubkey);
+/// builder.set_change(change_script_pubkey, fee);
+/// set_transaction_to_sign(accounts, builder.transaction_to_sign()?)?;
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct TransactionBuilder {
+    transitions: Vec<Spend>,
+    transition_outputs: Vec<TxOut>,
+    fee_inputs: Vec<Spend>,
+    outputs: Vec<TxOut>,
+    change: Option<(ScriptBuf, Amount)>,
+    previous_transactions: BTreeMap<[u8; 32], Transaction>,
+    tx_bytes: Vec<u8>,
+    inputs_to_sign: Vec<InputToSign>,
+}
+
+#[derive(Clone, Debug)]
+struct Spend {
+    utxo: UtxoMeta,
+    value: Amount,
+    signer: Pubkey,
+}
+
+impl TransactionBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Spend `account`'s UTXO and recreate it, with the same value, at the
+    /// account's script pubkey.
+    ///
+    /// Fails with [`ProgramError::Immutable`] if the account is not writable
+    /// and with [`ProgramError::InvalidArgument`] if its UTXO is already spent
+    /// by this transaction.
+    pub fn add_state_transition(&mut self, account: &AccountInfo) -> Result<(), ProgramError> {
+        if !account.is_writable {
+            return Err(ProgramError::Immutable);
+        }
+        let value = self.spend_value(account.utxo)?;
+        self.transitions.push(Spend {
+            utxo: account.utxo.clone(),
+            value,
+            signer: *account.key,
+        });
+        self.transition_outputs.push(TxOut {
+            value,
+            script_pubkey: ScriptBuf::from_bytes(get_account_script_pubkey(account.key).to_vec()),
+        });
+        Ok(())
+    }
+
+    /// Add a state transition for every writable account in `accounts`.
+    pub fn add_state_transitions(&mut self, accounts: &[AccountInfo]) -> Result<(), ProgramError> {
+        for account in accounts.iter().filter(|account| account.is_writable) {
+            self.add_state_transition(account)?;
+        }
+        Ok(())
+    }
+
+    /// Spend `utxo` to pay for the payout outputs, the change and the fee.
+    ///
+    /// `signer` signs the input. It should not be an account with a state
+    /// transition in the same transaction.
+    pub fn add_fee_input(&mut self, utxo: &UtxoMeta, signer: Pubkey) -> Result<(), ProgramError> {
+        let value = self.spend_value(utxo)?;
+        self.fee_inputs.push(Spend {
+            utxo: utxo.clone(),
+            value,
+            signer,
+        });
+        Ok(())
+    }
+
+    /// Pay `value` to `script_pubkey`.
+    pub fn add_output(&mut self, value: Amount, script_pubkey: ScriptBuf) {
+        self.outputs.push(TxOut {
+            value,
+            script_pubkey,
+        });
+    }
+
+    /// Return what is left of the fee inputs after the payouts and `fee` to
+    /// `script_pubkey`. Change below the dust limit is left to the fee.
+    pub fn set_change(&mut self, script_pubkey: ScriptBuf, fee: Amount) {
+        self.change = Some((script_pubkey, fee));
+    }
+
+    /// Assemble the transaction.
+    ///
+    /// Fails with [`ProgramError::InsufficientFunds`] if the fee inputs do not
+    /// cover the payouts and fee.
+    pub fn build(&self) -> Result<Transaction, ProgramError> {
+        let mut output = self.transition_outputs.clone();
+        output.extend(self.outputs.iter().cloned());
+
+        let fee_input_value = self
+            .fee_inputs
+            .iter()
+            .try_fold(Amount::ZERO, |total, spend| {
+                total
+                    .checked_add(spend.value)
+                    .ok_or(ProgramError::ArithmeticOverflow)
+            })?;
+        let payout_value = self
+            .outputs
+            .iter()
+            .try_fold(Amount::ZERO, |total, output| {
+                total
+                    .checked_add(output.value)
+                    .ok_or(ProgramError::ArithmeticOverflow)
+            })?;
+        let fee = self.change.as_ref().map_or(Amount::ZERO, |(_, fee)| *fee);
+        let change = payout_value
+            .checked_add(fee)
+            .and_then(|spent| fee_input_value.checked_sub(spent))
+            .ok_or(ProgramError::InsufficientFunds)?;
+        if let Some((script_pubkey, _)) = &self.change {
+            if change >= script_pubkey.minimal_non_dust() {
+                output.push(TxOut {
+                    value: change,
+                    script_pubkey: script_pubkey.clone(),
+                });
+            }
+        }
+
+        Ok(Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: self
+                .transitions
+                .iter()
+                .chain(self.fee_inputs.iter())
+                .map(|spend| TxIn {
+                    previous_output: spend.utxo.to_outpoint(),
+                    script_sig: ScriptBuf::new(),
+                    sequence: Sequence::MAX,
+                    witness: Witness::new(),
+                })
+                .collect(),
+            output,
+        })
+    }
+
+    /// Assemble the transaction and the inputs each signer must sign, ready
+    /// for [`set_transaction_to_sign`](crate::program::set_transaction_to_sign).
+    pub fn transaction_to_sign(&mut self) -> Result<TransactionToSign<'_>, ProgramError> {
+        self.tx_bytes = bitcoin::consensus::serialize(&self.build()?);
+        self.inputs_to_sign = self
+            .transitions
+            .iter()
+            .chain(self.fee_inputs.iter())
+            .enumerate()
+            .map(|(index, spend)| {
+                Ok(InputToSign {
+                    index: u32::try_from(index).map_err(|_| ProgramError::ArithmeticOverflow)?,
+                    signer: spend.signer,
+                })
+            })
+            .collect::<Result<_, ProgramError>>()?;
+
+        Ok(TransactionToSign {
+            tx_bytes: &self.tx_bytes,
+            inputs_to_sign: &self.inputs_to_sign,
+        })
+    }
+
+    /// The value of the output `utxo` refers to, fetching each previous
+    /// transaction only once.
+    fn spend_value(&mut self, utxo: &UtxoMeta) -> Result<Amount, ProgramError> {
+        if self
+            .transitions
+            .iter()
+            .chain(self.fee_inputs.iter())
+            .any(|spend| spend.utxo == *utxo)
+        {
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        let txid: [u8; 32] = utxo
+            .txid()
+            .try_into()
+            .map_err(|_| ProgramError::InvalidAccountData)?;
+        let transaction = match self.previous_transactions.get(&txid) {
+            Some(transaction) => transaction,
+            None => {
+                let tx_bytes =
+                    get_bitcoin_tx(txid).map_err(|_| ProgramError::InvalidAccountData)?;
+                let transaction: Transaction = bitcoin::consensus::deserialize(&tx_bytes)
+                    .map_err(|_| ProgramError::InvalidAccountData)?;
+                self.previous_transactions
+                    .entry(txid)
+                    .or_insert(transaction)
+            }
+        };
+        transaction
+            .output
+            .get(utxo.vout() as usize)
+            .map(|output| output.value)
+            .ok_or(ProgramError::InvalidAccountData)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+    };
+
+    use bitcoin::{Amount, ScriptBuf, Transaction, TxOut};
+
+    use super::TransactionBuilder;
+    use crate::{
+        account::AccountInfo,
+        program_error::ProgramError,
+        program_stubs::{with_syscall_stubs, SyscallStubs},
+        pubkey::Pubkey,
+        utxo::UtxoMeta,
+    };
+
+    struct BitcoinStubs {
+        transactions: HashMap<[u8; 32], Vec<u8>>,
+        fetches: AtomicUsize,
+    }
+
+    impl SyscallStubs for BitcoinStubs {
+        fn arch_get_bitcoin_tx(&self, buf: &mut [u8], txid: &[u8; 32]) -> u64 {
+            self.fetches.fetch_add(1, Ordering::SeqCst);
+            match self.transactions.get(txid) {
+                Some(tx) => {
+                    let len = tx.len().min(buf.len());
+                    buf[..len].copy_from_slice(&tx[..len]);
+                    tx.len() as u64
+                }
+                None => 0,
+            }
+        }
+        fn arch_get_account_script_pubkey(&self, pubkey: &Pubkey) -> [u8; 34] {
+            let mut script_pubkey = [0; 34];
+            script_pubkey[0] = 0x51;
+            script_pubkey[1] = 0x20;
+            script_pubkey[2..].copy_from_slice(&pubkey.serialize());
+            script_pubkey
+        }
+    }
+
+    fn previous_transaction(values: &[u64]) -> Transaction {
+        Transaction {
+            version: bitcoin::transaction::Version::TWO,
+            lock_time: bitcoin::absolute::LockTime::ZERO,
+            input: vec![],
+            output: values
+                .iter()
+                .map(|value| TxOut {
+                    value: Amount::from_sat(*value),
+                    script_pubkey: ScriptBuf::new(),
+                })
+                .collect(),
+        }
+    }
+
+    fn txid_bytes(transaction: &Transaction) -> [u8; 32] {
+        hex::decode(transaction.compute_txid().to_string())
+            .unwrap()
+            .try_into()
+            .unwrap()
+    }
+
+    fn stubs(transactions: &[&Transaction]) -> Arc<BitcoinStubs> {
+        Arc::new(BitcoinStubs {
+            transactions: transactions
+                .iter()
+                .map(|tx| (txid_bytes(tx), bitcoin::consensus::serialize(*tx)))
+                .collect(),
+            fetches: AtomicUsize::new(0),
+        })
+    }
+
+    #[test]
+    fn test_state_transitions_with_payout_and_change() {
+        let accounts_tx = previous_transaction(&[1_000, 2_000, 3_000]);
+        let fee_tx = previous_transaction(&[50_000]);
+        let stubs = stubs(&[&accounts_tx, &fee_tx]);
+
+        let keys = [Pubkey::from([1; 32]), Pubkey::from([2; 32])];
+        let utxos = [
+            UtxoMeta::from(txid_bytes(&accounts_tx), 2),
+            UtxoMeta::from(txid_bytes(&accounts_tx), 0),
+        ];
+        let fee_utxo = UtxoMeta::from(txid_bytes(&fee_tx), 0);
+        let fee_signer = Pubkey::from([9; 32]);
+        let owner = Pubkey::system_program();
+        let mut data = [[0u8; 0]; 3];
+        let [data0, data1, data2] = &mut data;
+        let accounts = [
+            AccountInfo::new(&keys[0], data0, &owner, &utxos[0], false, true, false),
+            AccountInfo::new(&keys[1], data1, &owner, &utxos[1], false, true, false),
+            AccountInfo::new(&fee_signer, data2, &owner, &fee_utxo, true, false, false),
+        ];
+        let payout_script = ScriptBuf::from_bytes(vec![0x51]);
+        let change_script = ScriptBuf::from_bytes([&[0x00, 0x14][..], &[7; 20]].concat());
+
+        let (transaction, inputs_to_sign, tx_bytes) = with_syscall_stubs(stubs.clone(), || {
+            let mut builder = TransactionBuilder::new();
+            builder.set_change(change_script.clone(), Amount::from_sat(1_000));
+            builder.add_output(Amount::from_sat(10_000), payout_script.clone());
+            builder.add_fee_input(&fee_utxo, fee_signer).unwrap();
+            builder.add_state_transitions(&accounts).unwrap();
+
+            let transaction = builder.build().unwrap();
+            let transaction_to_sign = builder.transaction_to_sign().unwrap();
+            (
+                transaction,
+                transaction_to_sign.inputs_to_sign.to_vec(),
+                transaction_to_sign.tx_bytes.to_vec(),
+            )
+        });
+
+        // Both accounts share a previous transaction, fetched once, with a
+        // single syscall per fetch.
+        assert_eq!(stubs.fetches.load(Ordering::SeqCst), 2);
+
+        let previous_outputs: Vec<_> = transaction
+            .input
+            .iter()
+            .map(|input| input.previous_output)
+            .collect();
+        assert_eq!(
+            previous_outputs,
+            vec![
+                utxos[0].to_outpoint(),
+                utxos[1].to_outpoint(),
+                fee_utxo.to_outpoint()
+            ]
+        );
+
+        assert_eq!(transaction.output.len(), 4);
+        assert_eq!(transaction.output[0].value, Amount::from_sat(3_000));
+        assert_eq!(
+            &transaction.output[0].script_pubkey.as_bytes()[2..],
+            &keys[0].serialize()
+        );
+        assert_eq!(transaction.output[1].value, Amount::from_sat(1_000));
+        assert_eq!(
+            &transaction.output[1].script_pubkey.as_bytes()[2..],
+            &keys[1].serialize()
+        );
+        assert_eq!(
+            transaction.output[2],
+            TxOut {
+                value: Amount::from_sat(10_000),
+                script_pubkey: payout_script,
+            }
+        );
+        assert_eq!(
+            transaction.output[3],
+            TxOut {
+                value: Amount::from_sat(39_000),
+                script_pubkey: change_script,
+            }
+        );
+
+        let signers: Vec<_> = inputs_to_sign
+            .iter()
+            .map(|input| (input.index, input.signer))
+            .collect();
+        assert_eq!(signers, vec![(0, keys[0]), (1, keys[1]), (2, fee_signer)]);
+        assert_eq!(tx_bytes, bitcoin::consensus::serialize(&transaction));
+    }
+
+    #[test]
+    fn test_builder_errors() {
+        let accounts_tx = previous_transaction(&[1_000]);
+        let fee_tx = previous_transaction(&[5_000]);
+        let stubs = stubs(&[&accounts_tx, &fee_tx]);
+
+        let key = Pubkey::from([1; 32]);
+        let owner = Pubkey::system_program();
+        let utxo = UtxoMeta::from(txid_bytes(&accounts_tx), 0);
+        let missing_vout = UtxoMeta::from(txid_bytes(&accounts_tx), 1);
+        let unknown_tx = UtxoMeta::from([3; 32], 0);
+        let fee_utxo = UtxoMeta::from(txid_bytes(&fee_tx), 0);
+        let mut data = [[0u8; 0]; 2];
+        let [data0, data1] = &mut data;
+        let writable = AccountInfo::new(&key, data0, &owner, &utxo, false, true, false);
+        let read_only = AccountInfo::new(&key, data1, &owner, &utxo, false, false, false);
+
+        with_syscall_stubs(stubs, || {
+            let mut builder = TransactionBuilder::new();
+            assert_eq!(
+                builder.add_state_transition(&read_only),
+                Err(ProgramError::Immutable)
+            );
+            builder.add_state_transition(&writable).unwrap();
+            assert_eq!(
+                builder.add_state_transition(&writable),
+                Err(ProgramError::InvalidArgument)
+            );
+            assert_eq!(
+                builder.add_fee_input(&missing_vout, key),
+                Err(ProgramError::InvalidAccountData)
+            );
+            assert_eq!(
+                builder.add_fee_input(&unknown_tx, key),
+                Err(ProgramError::InvalidAccountData)
+            );
+
+            builder.add_fee_input(&fee_utxo, key).unwrap();
+            builder.add_output(Amount::from_sat(4_500), ScriptBuf::new());
+            builder.set_change(ScriptBuf::new(), Amount::from_sat(600));
+            assert_eq!(builder.build(), Err(ProgramError::InsufficientFunds));
+
+            // Change below the dust limit goes to the fee.
+            builder.set_change(ScriptBuf::new(), Amount::from_sat(400));
+            assert_eq!(builder.build().unwrap().output.len(), 2);
+        });
+    }
+}
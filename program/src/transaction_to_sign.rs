@@ -1,6 +1,10 @@
+use crate::codec::{self, DecodeError, EncodeError, Reader};
 use crate::input_to_sign::InputToSign;
 use crate::pubkey::Pubkey;
 
+/// Encoded size of a single [`InputToSign`]: a u32 index followed by the signer.
+const INPUT_TO_SIGN_SIZE: usize = 4 + 32;
+
 #[repr(C)]
 #[derive(Clone, Debug, Default, Eq, PartialEq)]
 pub struct TransactionToSign<'a> {
@@ -9,49 +13,50 @@ pub struct TransactionToSign<'a> {
 }
 
 impl<'a> TransactionToSign<'a> {
-    pub fn serialise(&self) -> Vec<u8> {
+    pub fn serialise(&self) -> Result<Vec<u8>, EncodeError> {
         let mut serialized = vec![];
 
-        serialized.extend_from_slice(&(self.tx_bytes.len() as u32).to_le_bytes());
+        serialized.extend_from_slice(
+            &codec::u32_len("transaction_to_sign.tx_bytes", self.tx_bytes.len())?.to_le_bytes(),
+        );
         serialized.extend_from_slice(self.tx_bytes);
-        serialized.extend_from_slice(&(self.inputs_to_sign.len() as u32).to_le_bytes());
+        serialized.extend_from_slice(
+            &codec::u32_len(
+                "transaction_to_sign.inputs_to_sign",
+                self.inputs_to_sign.len(),
+            )?
+            .to_le_bytes(),
+        );
         for input_to_sign in self.inputs_to_sign.iter() {
             serialized.extend_from_slice(&input_to_sign.index.to_le_bytes());
             serialized.extend_from_slice(&input_to_sign.signer.serialize());
         }
 
-        serialized
+        Ok(serialized)
     }
 
-    pub fn from_slice(data: &'a [u8]) -> Self {
-        let mut size = 0;
-
-        let tx_bytes_len = u32::from_le_bytes(data[size..size + 4].try_into().unwrap()) as usize;
-        size += 4;
+    pub fn from_slice(data: &'a [u8]) -> Result<Self, DecodeError> {
+        let mut reader = Reader::new(data);
 
-        let tx_bytes = &data[size..(size + tx_bytes_len)];
-        size += tx_bytes_len;
+        let tx_bytes_len = reader.read_u32("transaction_to_sign.tx_bytes.len")? as usize;
+        let tx_bytes = reader.read_bytes("transaction_to_sign.tx_bytes", tx_bytes_len)?;
 
         let inputs_to_sign_len =
-            u32::from_le_bytes(data[size..size + 4].try_into().unwrap()) as usize;
-        size += 4;
-
-        let mut inputs_to_sign = Vec::with_capacity(inputs_to_sign_len);
+            reader.read_u32("transaction_to_sign.inputs_to_sign.len")? as usize;
+        let mut inputs_to_sign =
+            Vec::with_capacity(reader.capacity_for(inputs_to_sign_len, INPUT_TO_SIGN_SIZE));
 
         for _ in 0..inputs_to_sign_len {
-            let index = u32::from_le_bytes(data[size..size + 4].try_into().unwrap());
-            size += 4;
-
-            let signer = Pubkey::from_slice(&data[size..size + 32]);
-            size += 32;
+            let index = reader.read_u32("input_to_sign.index")?;
+            let signer = Pubkey::from(reader.read_array("input_to_sign.signer")?);
 
             inputs_to_sign.push(InputToSign { index, signer });
         }
 
-        TransactionToSign {
+        Ok(TransactionToSign {
             tx_bytes,
             inputs_to_sign: inputs_to_sign.leak(),
-        }
+        })
     }
 }
 
@@ -62,6 +67,15 @@ mod tests {
     };
     use proptest::prelude::*;
 
+    #[test]
+    fn test_from_slice_rejects_oversized_length_prefix() {
+        let mut data = vec![];
+        data.extend(0u32.to_le_bytes());
+        data.extend(u32::MAX.to_le_bytes());
+
+        assert!(TransactionToSign::from_slice(&data).is_err());
+    }
+
     proptest! {
         #[test]
         fn fuzz_serialize_deserialize_transaction_to_sign(
@@ -84,8 +98,8 @@ mod tests {
                 inputs_to_sign: &inputs_to_sign,
             };
 
-            let serialized = transaction.serialise();
-            let deserialized = TransactionToSign::from_slice(&serialized);
+            let serialized = transaction.serialise().unwrap();
+            let deserialized = TransactionToSign::from_slice(&serialized).unwrap();
 
             assert_eq!(transaction.tx_bytes, deserialized.tx_bytes);
             assert_eq!(transaction.inputs_to_sign, deserialized.inputs_to_sign);
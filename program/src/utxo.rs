@@ -1,10 +1,37 @@
 use borsh::{BorshDeserialize, BorshSerialize};
 
+use crate::codec::{DecodeError, Reader};
+
+/// A Bitcoin output an account is anchored to: a txid followed by the
+/// little-endian output index.
+///
+/// The txid is held in display byte order, the order in which block explorers
+/// and `Txid`'s `Display` print it, which is the reverse of the order a
+/// transaction hashes to. [`UtxoMeta::from`] and [`UtxoMeta::txid`] take and
+/// return bytes in this order; [`UtxoMeta::from_outpoint`] and
+/// [`UtxoMeta::to_outpoint`] convert from and to `bitcoin` types.
+///
+/// Displayed and parsed as `<txid hex>:<vout>`. It serializes as the pair
+/// of its txid bytes and vout; use [`serde_hex`](crate::serde_hex) for the
+/// string form. Borsh carries the 36 bytes.
 #[derive(Clone, Debug, PartialEq, Eq)]
 #[repr(C)]
 pub struct UtxoMeta([u8; 36]);
 
+/// Reasons a string is not a `<txid>:<vout>` [`UtxoMeta`]
+#[derive(Clone, Debug, Eq, Error, PartialEq)]
+pub enum ParseUtxoMetaError {
+    #[error("utxo is missing the `:<vout>` suffix")]
+    MissingVout,
+    #[error("utxo txid is not 64 hex characters")]
+    InvalidTxid,
+    #[error("utxo vout is not a u32")]
+    InvalidVout,
+}
+
 impl UtxoMeta {
+    /// The output `vout` of the transaction whose txid, in display byte
+    /// order, is `txid`.
     pub fn from(txid: [u8; 32], vout: u32) -> Self {
         let mut data: [u8; 36] = [0; 36];
         data[..32].copy_from_slice(&txid);
@@ -13,28 +40,33 @@ impl UtxoMeta {
     }
 
     pub fn from_outpoint(txid: Txid, vout: u32) -> Self {
-        let mut data: [u8; 36] = [0; 36];
-        data[..32].copy_from_slice(
-            &bitcoin::consensus::serialize(&txid)
-                .into_iter()
-                .rev()
-                .collect::<Vec<u8>>(),
-        );
-        data[32..].copy_from_slice(&vout.to_le_bytes());
-        Self(data)
+        let mut txid = txid.to_byte_array();
+        txid.reverse();
+        Self::from(txid, vout)
+    }
+
+    pub fn to_txid(&self) -> Txid {
+        let mut txid: [u8; 32] = self.txid().try_into().expect("utxo meta unreachable");
+        txid.reverse();
+        Txid::from_byte_array(txid)
     }
 
     pub fn to_outpoint(&self) -> OutPoint {
         OutPoint {
-            txid: Txid::from_str(&hex::encode(self.txid())).unwrap(),
+            txid: self.to_txid(),
             vout: self.vout(),
         }
     }
 
-    pub fn from_slice(data: &[u8]) -> Self {
-        Self(data[..36].try_into().expect("utxo meta is 36 bytes long"))
+    pub fn from_slice(data: &[u8]) -> Result<Self, DecodeError> {
+        Self::decode(&mut Reader::new(data))
+    }
+
+    pub fn decode(reader: &mut Reader) -> Result<Self, DecodeError> {
+        Ok(Self(reader.read_array("utxo_meta")?))
     }
 
+    /// The txid in display byte order.
     pub fn txid(&self) -> &[u8] {
         &self.0[..32]
     }
@@ -76,17 +108,53 @@ fn test_outpoint() {
 }
 
 use core::fmt;
-use std::io::{Read, Result, Write};
+use std::io::{self, Read, Write};
 use std::str::FromStr;
 
+use bitcoin::hashes::Hash;
 use bitcoin::OutPoint;
 use bitcoin::Txid;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use thiserror::Error;
 
-/// TODO:
-///  Change this in future according to the correct base implementation
 impl fmt::Display for UtxoMeta {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{:?}", self.0)
+        for byte in self.txid() {
+            write!(f, "{:02x}", byte)?;
+        }
+        write!(f, ":{}", self.vout())
+    }
+}
+
+impl FromStr for UtxoMeta {
+    type Err = ParseUtxoMetaError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (txid, vout) = s.split_once(':').ok_or(ParseUtxoMetaError::MissingVout)?;
+        let mut txid_bytes = [0; 32];
+        hex::decode_to_slice(txid, &mut txid_bytes).map_err(|_| ParseUtxoMetaError::InvalidTxid)?;
+        let vout = vout.parse().map_err(|_| ParseUtxoMetaError::InvalidVout)?;
+        Ok(Self::from(txid_bytes, vout))
+    }
+}
+
+impl From<OutPoint> for UtxoMeta {
+    fn from(outpoint: OutPoint) -> Self {
+        Self::from_outpoint(outpoint.txid, outpoint.vout)
+    }
+}
+
+impl Serialize for UtxoMeta {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let txid: [u8; 32] = self.txid().try_into().expect("utxo meta unreachable");
+        Serialize::serialize(&(txid, self.vout()), serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for UtxoMeta {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let (txid, vout) = <([u8; 32], u32) as Deserialize>::deserialize(deserializer)?;
+        Ok(Self::from(txid, vout))
     }
 }
 
@@ -110,31 +178,47 @@ impl From<[u8; 36]> for UtxoMeta {
 
 impl BorshSerialize for UtxoMeta {
     #[inline]
-    fn serialize<W: Write>(&self, writer: &mut W) -> Result<()> {
+    fn serialize<W: Write>(&self, writer: &mut W) -> io::Result<()> {
         self.0.serialize(writer)
     }
 }
 
 impl BorshDeserialize for UtxoMeta {
     #[inline]
-    fn deserialize_reader<R: Read>(reader: &mut R) -> Result<Self> {
-        if let Some(vec_bytes) = u8::vec_from_reader(36, reader)? {
-            Ok(UtxoMeta::from_slice(&vec_bytes))
-        } else {
-            // TODO(16): return capacity allocation when we can safely do that.
-            let mut result = Vec::with_capacity(36);
-            for _ in 0..36 {
-                result.push(u8::deserialize_reader(reader)?);
-            }
-            Ok(UtxoMeta::from_slice(&result))
-        }
+    fn deserialize_reader<R: Read>(reader: &mut R) -> io::Result<Self> {
+        <[u8; 36]>::deserialize_reader(reader).map(UtxoMeta)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::utxo::UtxoMeta;
+    use crate::utxo::{ParseUtxoMetaError, UtxoMeta};
+    use bitcoin::{OutPoint, Txid};
     use proptest::prelude::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_txid_byte_order() {
+        let txid = "c5cc9251192330191366016c8dab0f67dc345bd024a206c313dbf26db0a66bb1";
+        let utxo = UtxoMeta::from_outpoint(Txid::from_str(txid).unwrap(), 3);
+
+        assert_eq!(utxo.txid(), hex::decode(txid).unwrap());
+        assert_eq!(utxo.to_string(), format!("{}:3", txid));
+        assert_eq!(utxo.to_outpoint().to_string(), format!("{}:3", txid));
+
+        assert_eq!(
+            "00:1".parse::<UtxoMeta>(),
+            Err(ParseUtxoMetaError::InvalidTxid)
+        );
+        assert_eq!(
+            txid.parse::<UtxoMeta>(),
+            Err(ParseUtxoMetaError::MissingVout)
+        );
+        assert_eq!(
+            format!("{}:-1", txid).parse::<UtxoMeta>(),
+            Err(ParseUtxoMetaError::InvalidVout)
+        );
+    }
 
     proptest! {
         #[test]
@@ -144,5 +228,21 @@ mod tests {
             let deserialized: UtxoMeta = borsh::from_slice(&serialized).unwrap();
             assert_eq!(original, deserialized);
         }
+
+        #[test]
+        fn fuzz_utxo_meta_encodings(txid in any::<[u8; 32]>(), vout in any::<u32>()) {
+            let utxo = UtxoMeta::from(txid, vout);
+            prop_assert_eq!(utxo.to_string().parse::<UtxoMeta>(), Ok(utxo.clone()));
+            prop_assert_eq!(utxo.to_outpoint().to_string(), utxo.to_string());
+            let outpoint: OutPoint = utxo.to_outpoint();
+            prop_assert_eq!(UtxoMeta::from_outpoint(outpoint.txid, outpoint.vout), utxo.clone());
+            let converted: UtxoMeta = outpoint.into();
+            prop_assert_eq!(converted, utxo.clone());
+
+            let json = serde_json::to_value(&utxo).unwrap();
+            prop_assert_eq!(&json, &serde_json::json!((txid, vout)));
+            prop_assert_eq!(serde_json::from_value::<UtxoMeta>(json).unwrap(), utxo.clone());
+            prop_assert_eq!(borsh::to_vec(&utxo).unwrap(), utxo.serialize().to_vec());
+        }
     }
 }
@@ -0,0 +1,26 @@
+use arch_program::instruction::ProgramInstruction;
+
+use crate::{DepositParam, PoolParam, QueryParam};
+
+/// Instructions accepted by the archyield program, tagged by their first byte.
+/// The view instructions log their result.
+#[derive(Debug, ProgramInstruction)]
+pub enum PoolInstruction {
+    /// Accounts: `[writable] pool`, `[signer] owner`, `[writable] pool list`
+    CreatePool(PoolParam),
+    /// Accounts: `[writable] pool`, `[] pool list`, `[signer, writable] user`,
+    /// `[] token program`, `[writable] user tokens`, `[writable] pool tokens`,
+    /// `[] mint`, `[] pool` again
+    Deposit(DepositParam),
+    /// Accounts: `[writable] pool`, `[signer, writable] user`, `[] token program`,
+    /// `[writable] pool tokens`, `[writable] user tokens`, `[] mint`
+    Withdraw(QueryParam),
+    /// Accounts: `[] user`
+    GetUserDeposit(QueryParam),
+    /// Accounts: `[] pool list`, then every pool
+    GetAllPools,
+    /// Accounts: `[] pool list`, `[] pool`
+    GetPoolById(u64),
+    /// Accounts: `[] pool list`, `[] pool`
+    GetPoolTvl(u64),
+}
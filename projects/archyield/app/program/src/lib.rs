@@ -1,14 +1,20 @@
 use arch_program::{
     account::{AccountInfo, AccountMeta},
-    clock::Clock,
-    entrypoint,
-    instruction::Instruction,
+    account_data::{Account, AccountData},
+    accounts::Accounts,
+    clock::{Clock, SECONDS_PER_DAY},
+    emit, entrypoint,
+    instruction::{Instruction, ProgramInstruction},
+    log::Event,
+    math::{compound_interest, simple_interest, Rounding, Wad},
     msg,
-    program::{invoke, next_account_info},
+    program::invoke,
     program_error::ProgramError,
     pubkey::Pubkey,
 };
 use borsh::{BorshDeserialize, BorshSerialize};
+use instruction::PoolInstruction;
+pub mod instruction;
 
 #[derive(BorshSerialize, BorshDeserialize, Debug)]
 pub struct Pool {
@@ -52,13 +58,13 @@ impl TransferInput {
 
 #[derive(BorshSerialize, BorshDeserialize, Debug, PartialEq, Eq)]
 pub struct DepositParam {
-    pool_id: u64,
-    amount: u64,
+    pub pool_id: u64,
+    pub amount: u64,
 }
 
 #[derive(BorshSerialize, BorshDeserialize, Debug, PartialEq, Eq)]
 pub struct QueryParam {
-    pool_id: u64,
+    pub pool_id: u64,
 }
 
 #[derive(BorshSerialize, BorshDeserialize, Debug, PartialEq, Eq)]
@@ -123,75 +129,102 @@ pub struct PoolList {
     pub admin_list: Vec<Pubkey>,
 }
 
+impl AccountData for PoolList {
+    const DISCRIMINATOR: [u8; 8] = *b"poollist";
+}
+
 #[derive(BorshSerialize, BorshDeserialize)]
 pub struct UserDepositList {
     pub deposits: Vec<Deposits>,
 }
 
+impl AccountData for UserDepositList {
+    const DISCRIMINATOR: [u8; 8] = *b"deposits";
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct DepositEvent {
+    pub pool_id: u64,
+    pub user_pubkey: Pubkey,
+    pub amount: u64,
+    pub tvl: u64,
+}
+
+impl Event for DepositEvent {
+    const DISCRIMINATOR: [u8; 8] = *b"deposit_";
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct WithdrawEvent {
+    pub pool_id: u64,
+    pub user_pubkey: Pubkey,
+    pub amount: u64,
+    pub tvl: u64,
+}
+
+impl Event for WithdrawEvent {
+    const DISCRIMINATOR: [u8; 8] = *b"withdraw";
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct RewardWithdrawEvent {
+    pub pool_id: u64,
+    pub user_pubkey: Pubkey,
+    pub amount: u64,
+}
+
+impl Event for RewardWithdrawEvent {
+    const DISCRIMINATOR: [u8; 8] = *b"reward__";
+}
+
 entrypoint!(process_instruction);
 fn process_instruction(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
     instruction_data: &[u8],
 ) -> Result<(), ProgramError> {
-    if instruction_data.is_empty() {
-        return Err(ProgramError::InvalidInstructionData);
-    }
-
-    match instruction_data[0] {
-        0 => create_pool(accounts, &instruction_data[1..]),
-        1 => deposit(program_id, accounts, &instruction_data[1..]),
-        2 => withdraw(program_id, accounts, &instruction_data[1..]),
-        3 => get_user_deposit(accounts, &instruction_data[1..]),
-        4 => get_all_pools(program_id, accounts),
-        5 => get_pool_by_id(accounts, &instruction_data[1..]),
-        6 => get_pool_tvl(accounts, &instruction_data[1..]),
-        _ => Err(ProgramError::InvalidInstructionData),
+    match PoolInstruction::unpack(instruction_data)? {
+        PoolInstruction::CreatePool(pool_param) => create_pool(program_id, accounts, pool_param),
+        PoolInstruction::Deposit(deposit_param) => deposit(program_id, accounts, deposit_param),
+        PoolInstruction::Withdraw(query_param) => withdraw(program_id, accounts, query_param),
+        PoolInstruction::GetUserDeposit(query_param) => {
+            get_user_deposit(program_id, accounts, query_param)
+        }
+        PoolInstruction::GetAllPools => get_all_pools(program_id, accounts),
+        PoolInstruction::GetPoolById(pool_id) => get_pool_by_id(program_id, accounts, pool_id),
+        PoolInstruction::GetPoolTvl(pool_id) => get_pool_tvl(program_id, accounts, pool_id),
     }
 }
 
-pub fn create_pool(accounts: &[AccountInfo], instruction_data: &[u8]) -> Result<(), ProgramError> {
-    let account_iter = &mut accounts.iter();
-
-    let pool_account = next_account_info(account_iter)?;
-    let owner_account = next_account_info(account_iter)?;
-    let pool_list_account = next_account_info(account_iter)?;
-
-    let mut pool_list: PoolList = match PoolList::try_from_slice(&pool_list_account.data.borrow()) {
-        Ok(list) => list,
-        Err(_) => PoolList {
-            pools: Vec::new(),
-            pool_id_to_pubkey: Vec::new(),
-            admin_list: vec![],
-        },
-    };
+/// Accounts for [`create_pool`]. The pool and the pool list are written, so
+/// both must belong to the program.
+#[derive(Accounts)]
+pub struct CreatePool<'a, 'b> {
+    #[account(mut, owner = program_id)]
+    pub pool_account: &'a AccountInfo<'b>,
+    #[account(signer)]
+    pub owner_account: &'a AccountInfo<'b>,
+    #[account(mut)]
+    pub pool_list_account: Account<'a, 'b, PoolList>,
+}
 
-    let pool_param = match PoolParam::try_from_slice(instruction_data) {
-        Ok(list) => list,
-        Err(_) => return Err(ProgramError::InvalidInstructionData),
-    };
+pub fn create_pool(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    pool_param: PoolParam,
+) -> Result<(), ProgramError> {
+    let CreatePool {
+        pool_account,
+        owner_account,
+        pool_list_account,
+    } = CreatePool::try_accounts(program_id, accounts)?;
 
-    // if pool_account.owner != program_id {
-    //     return Err(ProgramError::IncorrectProgramId);
-    // }
+    let mut pool_list = pool_list_account.load_mut()?;
 
     if !pool_list.admin_list.contains(owner_account.key) {
         return Err(ProgramError::MissingRequiredSignature);
     }
 
-    if !owner_account.is_signer {
-        return Err(ProgramError::MissingRequiredSignature);
-    }
-
-    if instruction_data.len() < 16 {
-        return Err(ProgramError::InvalidInstructionData);
-    }
-
-    let pool_name_len = instruction_data[0] as usize;
-    if instruction_data.len() < 25 + pool_name_len {
-        return Err(ProgramError::InvalidInstructionData);
-    }
-
     let asset_type = AssetType::from_u8(pool_param.asset_type)?;
     let risk_type = RiskType::from_u8(pool_param.risk_type)?;
     let pool_id = pool_list.pools.len() as u64 + 1;
@@ -219,9 +252,7 @@ pub fn create_pool(accounts: &[AccountInfo], instruction_data: &[u8]) -> Result<
         .pool_id_to_pubkey
         .push((pool_id, *pool_account.key));
 
-    pool_list
-        .serialize(&mut &mut pool_list_account.data.borrow_mut()[..])
-        .map_err(|e| ProgramError::BorshIoError(e.to_string()))?;
+    pool_list_account.store(&pool_list)?;
     pool.serialize(&mut &mut pool_account.data.borrow_mut()[..])
         .map_err(|e| ProgramError::BorshIoError(e.to_string()))?;
 
@@ -234,51 +265,54 @@ pub fn create_pool(accounts: &[AccountInfo], instruction_data: &[u8]) -> Result<
     Ok(())
 }
 
+/// Accounts for [`deposit`]. The user's deposits are written, so the user
+/// account must belong to the program as well as sign.
+#[derive(Accounts)]
+pub struct Deposit<'a, 'b> {
+    #[account(mut, owner = program_id)]
+    pub pool_account: &'a AccountInfo<'b>,
+    pub pool_list_account: Account<'a, 'b, PoolList>,
+    #[account(mut, signer)]
+    pub user_account: Account<'a, 'b, UserDepositList>,
+    pub token_program: &'a AccountInfo<'b>,
+    #[account(mut)]
+    pub user_token_account: &'a AccountInfo<'b>,
+    #[account(mut)]
+    pub pool_token_account: &'a AccountInfo<'b>,
+    pub token_mint: &'a AccountInfo<'b>,
+    /// The pool listed under the deposit's pool id.
+    pub listed_pool_account: &'a AccountInfo<'b>,
+}
+
 pub fn deposit(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
-    instruction_data: &[u8],
+    deposit_param: DepositParam,
 ) -> Result<(), ProgramError> {
-    let account_iter = &mut accounts.iter();
-
-    let pool_account = next_account_info(account_iter)?;
-    let pool_list_account = next_account_info(account_iter)?;
-    let user_account = next_account_info(account_iter)?;
-    let token_program = next_account_info(account_iter)?;
-    let user_token_account = next_account_info(account_iter)?;
-    let pool_token_account = next_account_info(account_iter)?;
-    let token_mint = next_account_info(account_iter)?;
-
-    if pool_account.owner != program_id {
-        return Err(ProgramError::IncorrectProgramId);
-    }
-
-    let deposit_param = match DepositParam::try_from_slice(instruction_data) {
-        Ok(list) => list,
-        Err(_) => return Err(ProgramError::InvalidInstructionData),
-    };
-
-    if !user_account.is_signer {
-        return Err(ProgramError::MissingRequiredSignature);
-    }
-
-    let mut user_deposit_list = match UserDepositList::try_from_slice(&user_account.data.borrow()) {
-        Ok(list) => list,
-        Err(_) => UserDepositList {
+    let Deposit {
+        pool_account,
+        pool_list_account,
+        user_account,
+        token_program,
+        user_token_account,
+        pool_token_account,
+        token_mint,
+        listed_pool_account,
+    } = Deposit::try_accounts(program_id, accounts)?;
+
+    let mut user_deposit_list = if user_account.is_initialized()? {
+        user_account.load_mut()?
+    } else {
+        UserDepositList {
             deposits: Vec::new(),
-        },
+        }
     };
 
-    if instruction_data.len() < 8 {
-        return Err(ProgramError::InvalidInstructionData);
-    }
-
-    let pool_list = PoolList::try_from_slice(&pool_list_account.data.borrow())
-        .map_err(|_| ProgramError::InvalidAccountData)?;
+    let pool_list = pool_list_account.load()?;
     let deposit_amount = deposit_param.amount;
 
     let transfer_accounts = &[
-        user_account.clone(),
+        user_account.info().clone(),
         token_mint.clone(),
         user_token_account.clone(),
         pool_token_account.clone(),
@@ -291,14 +325,12 @@ pub fn deposit(
         .map(|(_, pubkey)| pubkey);
 
     let pool = if let Some(pool_pubkey) = pool_pubkey {
-        let pool_account = account_iter
-            .find(|account| account.key == pool_pubkey)
-            .ok_or(ProgramError::InvalidAccountData)?;
-
-        let pool = Pool::try_from_slice(&pool_account.data.borrow())
-            .map_err(|_| ProgramError::InvalidAccountData)?;
+        if listed_pool_account.key != pool_pubkey {
+            return Err(ProgramError::InvalidAccountData);
+        }
 
-        pool
+        Pool::try_from_slice(&listed_pool_account.data.borrow())
+            .map_err(|_| ProgramError::InvalidAccountData)?
     } else {
         return Err(ProgramError::InvalidArgument);
     };
@@ -315,7 +347,7 @@ pub fn deposit(
         program_id: *token_program.key,
         accounts: vec![
             AccountMeta {
-                pubkey: *user_account.key,
+                pubkey: *user_account.key(),
                 is_signer: true,   // This account needs to sign the transaction
                 is_writable: true, // This account's data will be modified
             },
@@ -343,45 +375,63 @@ pub fn deposit(
     let mut pool: Pool = Pool::try_from_slice(&pool_account.data.borrow())
         .map_err(|e| ProgramError::BorshIoError(e.to_string()))?;
 
-    let apy = pool.apy;
-    let days_in_year = 365;
-    let daily_payout = (deposit_amount * apy / 100) / days_in_year;
+    let apy = Wad::from_percent(pool.apy);
+    let daily_payout = simple_interest(deposit_amount, apy, SECONDS_PER_DAY, Rounding::Down)?;
 
     if let Some(deposit) = user_deposit_list
         .deposits
         .iter_mut()
         .find(|d| d.pool_id == deposit_param.pool_id)
     {
+        // Settle the whole days the old amount earned before the top-up
+        // changes the payout. The new period starts where those days end, so
+        // a partial day still counts towards the next payout.
+        let clock = Clock::get()?;
+        let settled_since = if deposit.last_reward_claim_time == 0 {
+            deposit.start_date
+        } else {
+            deposit.last_reward_claim_time
+        };
+        let elapsed_days = clock.elapsed_days(settled_since)?;
+        let settled_seconds = elapsed_days
+            .checked_mul(SECONDS_PER_DAY)
+            .ok_or(ProgramError::ArithmeticOverflow)?;
+        let base_reward = deposit
+            .daily_payout
+            .checked_mul(elapsed_days)
+            .ok_or(ProgramError::ArithmeticOverflow)?;
+        let compound_reward = compound_interest(deposit.reward, apy, settled_seconds)?;
+        deposit.reward = deposit
+            .reward
+            .checked_add(base_reward)
+            .and_then(|reward| reward.checked_add(compound_reward))
+            .ok_or(ProgramError::ArithmeticOverflow)?;
+
         deposit.deposited_amount = deposit
             .deposited_amount
             .checked_add(deposit_amount)
             .ok_or(ProgramError::InvalidAccountData)?;
-        deposit.daily_payout = (deposit.deposited_amount * apy / 100) / days_in_year;
-        deposit.start_date = Clock::default().unix_timestamp as u64;
-        let elapsed_days: u64;
-        if deposit.last_reward_claim_time == 0 {
-            let current_time = Clock::default().unix_timestamp as u64;
-            elapsed_days = (current_time - deposit.start_date) / (24 * 60 * 60);
-        } else {
-            let current_time = Clock::default().unix_timestamp as u64;
-            elapsed_days = (current_time - deposit.last_reward_claim_time) / (24 * 60 * 60);
+        deposit.daily_payout = simple_interest(
+            deposit.deposited_amount,
+            apy,
+            SECONDS_PER_DAY,
+            Rounding::Down,
+        )?;
+        deposit.start_date = settled_since
+            .checked_add(settled_seconds)
+            .ok_or(ProgramError::ArithmeticOverflow)?;
+        if deposit.last_reward_claim_time != 0 {
+            deposit.last_reward_claim_time = deposit.start_date;
         }
-        let base_reward = deposit.daily_payout * elapsed_days;
-        let compound_reward = if deposit.reward > 0 {
-            ((deposit.reward * apy / 100) / days_in_year) * elapsed_days
-        } else {
-            0
-        };
-        deposit.reward = deposit.reward + base_reward + compound_reward;
     } else {
         user_deposit_list.deposits.push(Deposits {
             pool_id: deposit_param.pool_id,
-            user_pubkey: *user_account.key,
+            user_pubkey: *user_account.key(),
             pool_pubkey: *pool_account.key,
             deposited_amount: deposit_amount,
             status: DepositStatus::Active,
             daily_payout,
-            start_date: Clock::default().unix_timestamp as u64,
+            start_date: Clock::get()?.unix_timestamp as u64,
             last_reward_claim_time: 0,
             reward: 0,
         })
@@ -399,50 +449,50 @@ pub fn deposit(
 
     pool.serialize(&mut &mut pool_account.data.borrow_mut()[..])
         .map_err(|e| ProgramError::BorshIoError(e.to_string()))?;
-
-    msg!(
-        "Deposit successful. Amount: {}, New TVL: {}",
-        deposit_amount,
-        pool.tvl
-    );
+    user_account.store(&user_deposit_list)?;
+
+    emit!(DepositEvent {
+        pool_id: deposit_param.pool_id,
+        user_pubkey: *user_account.key(),
+        amount: deposit_amount,
+        tvl: pool.tvl,
+    });
     Ok(())
 }
 
+/// Accounts for [`withdraw`] and [`withdraw_rewards`].
+#[derive(Accounts)]
+pub struct Withdraw<'a, 'b> {
+    #[account(mut, owner = program_id)]
+    pub pool_account: &'a AccountInfo<'b>,
+    #[account(mut, signer)]
+    pub user_account: Account<'a, 'b, UserDepositList>,
+    pub token_program: &'a AccountInfo<'b>,
+    #[account(mut)]
+    pub pool_token_account: &'a AccountInfo<'b>,
+    #[account(mut)]
+    pub user_token_account: &'a AccountInfo<'b>,
+    pub token_mint: &'a AccountInfo<'b>,
+}
+
 pub fn withdraw(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
-    instruction_data: &[u8],
+    withdraw_param: QueryParam,
 ) -> Result<(), ProgramError> {
-    let account_iter = &mut accounts.iter();
-
-    let pool_account = next_account_info(account_iter)?;
-    let user_account = next_account_info(account_iter)?;
-    let token_program = next_account_info(account_iter)?;
-    let pool_token_account = next_account_info(account_iter)?;
-    let user_token_account = next_account_info(account_iter)?;
-    let token_mint = next_account_info(account_iter)?;
-
-    if pool_account.owner != program_id {
-        return Err(ProgramError::IncorrectProgramId);
-    }
+    let Withdraw {
+        pool_account,
+        user_account,
+        token_program,
+        pool_token_account,
+        user_token_account,
+        token_mint,
+    } = Withdraw::try_accounts(program_id, accounts)?;
 
-    if !user_account.is_signer {
-        return Err(ProgramError::MissingRequiredSignature);
-    }
-
-    let withdraw_param = match QueryParam::try_from_slice(instruction_data) {
-        Ok(list) => list,
-        Err(_) => return Err(ProgramError::InvalidInstructionData),
-    };
     let mut pool: Pool = Pool::try_from_slice(&pool_account.data.borrow())
         .map_err(|e| ProgramError::BorshIoError(e.to_string()))?;
 
-    let mut user_deposit_list = match UserDepositList::try_from_slice(&user_account.data.borrow()) {
-        Ok(list) => list,
-        Err(_) => UserDepositList {
-            deposits: Vec::new(),
-        },
-    };
+    let mut user_deposit_list = user_account.load_mut()?;
 
     let user_deposit = if let Some(deposit) = user_deposit_list
         .deposits
@@ -454,16 +504,11 @@ pub fn withdraw(
         return Err(ProgramError::InvalidAccountData);
     };
 
-    let clock = Clock::default();
-    let current_time = clock.unix_timestamp as u64;
-    if current_time < user_deposit.start_date + pool.min_period {
+    let clock = Clock::get()?;
+    if clock.elapsed_seconds(user_deposit.start_date)? < pool.min_period {
         return Err(ProgramError::Custom(1));
     }
 
-    if instruction_data.len() < 8 {
-        return Err(ProgramError::InvalidInstructionData);
-    }
-
     let withdraw_amount = user_deposit.deposited_amount;
 
     if withdraw_amount > pool.tvl {
@@ -531,40 +576,34 @@ pub fn withdraw(
 
     pool.serialize(&mut &mut pool_account.data.borrow_mut()[..])
         .map_err(|e| ProgramError::BorshIoError(e.to_string()))?;
-
-    msg!(
-        "Withdraw successful. Amount: {}, Remaining TVL: {}",
-        withdraw_amount,
-        pool.tvl
-    );
+    user_account.store(&user_deposit_list)?;
+
+    emit!(WithdrawEvent {
+        pool_id: withdraw_param.pool_id,
+        user_pubkey: *user_account.key(),
+        amount: withdraw_amount,
+        tvl: pool.tvl,
+    });
     Ok(())
 }
 
 pub fn withdraw_rewards(
+    program_id: &Pubkey,
     accounts: &[AccountInfo],
-    instruction_data: &[u8],
+    query_param: QueryParam,
 ) -> Result<(), ProgramError> {
-    let account_iter = &mut accounts.iter();
-
-    let pool_account = next_account_info(account_iter)?;
-    let user_account = next_account_info(account_iter)?;
-    let token_program = next_account_info(account_iter)?;
-    let pool_token_account = next_account_info(account_iter)?;
-    let user_token_account = next_account_info(account_iter)?;
-    let token_mint = next_account_info(account_iter)?;
+    let Withdraw {
+        pool_account,
+        user_account,
+        token_program,
+        pool_token_account,
+        user_token_account,
+        token_mint,
+    } = Withdraw::try_accounts(program_id, accounts)?;
 
     let pool = Pool::try_from_slice(&pool_account.data.borrow())
         .map_err(|e| ProgramError::BorshIoError(e.to_string()))?;
-    let query_param = match QueryParam::try_from_slice(instruction_data) {
-        Ok(list) => list,
-        Err(_) => return Err(ProgramError::InvalidInstructionData),
-    };
-    let mut user_deposit_list = match UserDepositList::try_from_slice(&user_account.data.borrow()) {
-        Ok(list) => list,
-        Err(_) => UserDepositList {
-            deposits: Vec::new(),
-        },
-    };
+    let mut user_deposit_list = user_account.load_mut()?;
 
     let user_deposit = if let Some(deposit) = user_deposit_list
         .deposits
@@ -576,28 +615,32 @@ pub fn withdraw_rewards(
         return Err(ProgramError::InvalidAccountData);
     };
 
-    // if pool_account.owner != program_id {
-    //     return Err(ProgramError::IncorrectProgramId);
-    // }
-
     if user_deposit.reward == 0 {
         return Err(ProgramError::Custom(0)); // No rewards to withdraw
     }
 
-    let days_in_year = 365;
-    let elapsed_days: u64;
-    if user_deposit.last_reward_claim_time == 0 {
-        let current_time = Clock::default().unix_timestamp as u64;
-        elapsed_days = (current_time - user_deposit.start_date) / (24 * 60 * 60);
+    let clock = Clock::get()?;
+    let elapsed_days = if user_deposit.last_reward_claim_time == 0 {
+        clock.elapsed_days(user_deposit.start_date)?
     } else {
-        let current_time = Clock::default().unix_timestamp as u64;
-        elapsed_days = (current_time - user_deposit.last_reward_claim_time) / (24 * 60 * 60);
-    }
-    user_deposit.reward = user_deposit.daily_payout * elapsed_days;
-    let reward_amount = (((user_deposit.reward * pool.apy / 100) / days_in_year) * elapsed_days)
-        + user_deposit.reward;
+        clock.elapsed_days(user_deposit.last_reward_claim_time)?
+    };
+    user_deposit.reward = user_deposit
+        .daily_payout
+        .checked_mul(elapsed_days)
+        .ok_or(ProgramError::ArithmeticOverflow)?;
+    let reward_amount = compound_interest(
+        user_deposit.reward,
+        Wad::from_percent(pool.apy),
+        elapsed_days
+            .checked_mul(SECONDS_PER_DAY)
+            .ok_or(ProgramError::ArithmeticOverflow)?,
+    )?
+    .checked_add(user_deposit.reward)
+    .ok_or(ProgramError::ArithmeticOverflow)?;
     user_deposit.reward = 0;
-    user_deposit.last_reward_claim_time = Clock::default().unix_timestamp as u64;
+    user_deposit.last_reward_claim_time = clock.unix_timestamp as u64;
+    user_account.store(&user_deposit_list)?;
 
     let mut transfer_ix_data = vec![3];
     transfer_ix_data.extend_from_slice(
@@ -644,28 +687,28 @@ pub fn withdraw_rewards(
         ],
     )?;
 
-    msg!("Reward withdrawal successful: {}", reward_amount);
+    emit!(RewardWithdrawEvent {
+        pool_id: query_param.pool_id,
+        user_pubkey: *user_account.key(),
+        amount: reward_amount,
+    });
     Ok(())
 }
 
+/// Accounts for [`get_user_deposit`].
+#[derive(Accounts)]
+pub struct GetUserDeposit<'a, 'b> {
+    pub user_account: Account<'a, 'b, UserDepositList>,
+}
+
 pub fn get_user_deposit(
+    program_id: &Pubkey,
     accounts: &[AccountInfo],
-    instruction_data: &[u8],
+    query_param: QueryParam,
 ) -> Result<(), ProgramError> {
-    let account_iter = &mut accounts.iter();
-    let user_account = next_account_info(account_iter)?;
+    let GetUserDeposit { user_account } = GetUserDeposit::try_accounts(program_id, accounts)?;
 
-    let query_param = match QueryParam::try_from_slice(instruction_data) {
-        Ok(list) => list,
-        Err(_) => return Err(ProgramError::InvalidInstructionData),
-    };
-
-    let user_deposit_list = match UserDepositList::try_from_slice(&user_account.data.borrow()) {
-        Ok(list) => list,
-        Err(_) => UserDepositList {
-            deposits: Vec::new(),
-        },
-    };
+    let user_deposit_list = user_account.load()?;
 
     let user_deposit = if let Some(deposit) = user_deposit_list
         .deposits
@@ -682,17 +725,15 @@ pub fn get_user_deposit(
     Ok(())
 }
 
-pub fn get_all_pools(program_id: &Pubkey, accounts: &[AccountInfo]) -> Result<(), ProgramError> {
-    let account_iter = &mut accounts.iter();
-
-    let pool_list_account = next_account_info(account_iter)?;
-
-    if pool_list_account.owner != program_id {
-        return Err(ProgramError::IncorrectProgramId);
-    }
+/// Accounts for [`get_all_pools`]. The listed pools follow the pool list.
+#[derive(Accounts)]
+pub struct GetAllPools<'a, 'b> {
+    pub pool_list_account: Account<'a, 'b, PoolList>,
+}
 
-    let pool_list: PoolList = PoolList::try_from_slice(&pool_list_account.data.borrow())
-        .map_err(|e| ProgramError::BorshIoError(e.to_string()))?;
+pub fn get_all_pools(program_id: &Pubkey, accounts: &[AccountInfo]) -> Result<(), ProgramError> {
+    let GetAllPools { pool_list_account } = GetAllPools::try_accounts(program_id, accounts)?;
+    let pool_list = pool_list_account.load()?;
 
     let mut pools: Vec<Pool> = Vec::new();
 
@@ -722,20 +763,23 @@ pub fn get_all_pools(program_id: &Pubkey, accounts: &[AccountInfo]) -> Result<()
     Ok(())
 }
 
+/// Accounts for [`get_pool_by_id`] and [`get_pool_tvl`].
+#[derive(Accounts)]
+pub struct GetPool<'a, 'b> {
+    pub pool_list_account: Account<'a, 'b, PoolList>,
+    pub pool_account: &'a AccountInfo<'b>,
+}
+
 pub fn get_pool_by_id(
+    program_id: &Pubkey,
     accounts: &[AccountInfo],
-    instruction_data: &[u8],
+    pool_id: u64,
 ) -> Result<(), ProgramError> {
-    let account_iter = &mut accounts.iter();
-
-    let pool_id = match u64::try_from_slice(instruction_data) {
-        Ok(list) => list,
-        Err(_) => return Err(ProgramError::InvalidInstructionData),
-    };
-
-    let pool_list_account = next_account_info(account_iter)?;
-    let pool_list = PoolList::try_from_slice(&pool_list_account.data.borrow())
-        .map_err(|_| ProgramError::InvalidAccountData)?;
+    let GetPool {
+        pool_list_account,
+        pool_account,
+    } = GetPool::try_accounts(program_id, accounts)?;
+    let pool_list = pool_list_account.load()?;
 
     let pool_pubkey = pool_list
         .pool_id_to_pubkey
@@ -744,9 +788,9 @@ pub fn get_pool_by_id(
         .map(|(_, pubkey)| pubkey);
 
     if let Some(pool_pubkey) = pool_pubkey {
-        let pool_account = account_iter
-            .find(|account| account.key == pool_pubkey)
-            .ok_or(ProgramError::InvalidAccountData)?;
+        if pool_account.key != pool_pubkey {
+            return Err(ProgramError::InvalidAccountData);
+        }
 
         let pool = Pool::try_from_slice(&pool_account.data.borrow())
             .map_err(|_| ProgramError::InvalidAccountData)?;
@@ -758,16 +802,16 @@ pub fn get_pool_by_id(
     }
 }
 
-pub fn get_pool_tvl(accounts: &[AccountInfo], instruction_data: &[u8]) -> Result<(), ProgramError> {
-    let account_iter = &mut accounts.iter();
-    let pool_list_account = next_account_info(account_iter)?;
-    let pool_list = PoolList::try_from_slice(&pool_list_account.data.borrow())
-        .map_err(|_| ProgramError::InvalidAccountData)?;
-
-    let pool_id = match u64::try_from_slice(instruction_data) {
-        Ok(list) => list,
-        Err(_) => return Err(ProgramError::InvalidInstructionData),
-    };
+pub fn get_pool_tvl(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    pool_id: u64,
+) -> Result<(), ProgramError> {
+    let GetPool {
+        pool_list_account,
+        pool_account,
+    } = GetPool::try_accounts(program_id, accounts)?;
+    let pool_list = pool_list_account.load()?;
 
     let pool_pubkey = pool_list
         .pool_id_to_pubkey
@@ -776,9 +820,9 @@ pub fn get_pool_tvl(accounts: &[AccountInfo], instruction_data: &[u8]) -> Result
         .map(|(_, pubkey)| pubkey);
 
     if let Some(pool_pubkey) = pool_pubkey {
-        let pool_account = account_iter
-            .find(|account| account.key == pool_pubkey)
-            .ok_or(ProgramError::InvalidAccountData)?;
+        if pool_account.key != pool_pubkey {
+            return Err(ProgramError::InvalidAccountData);
+        }
 
         let pool = Pool::try_from_slice(&pool_account.data.borrow())
             .map_err(|_| ProgramError::InvalidAccountData)?;
@@ -789,3 +833,173 @@ pub fn get_pool_tvl(accounts: &[AccountInfo], instruction_data: &[u8]) -> Result
         Err(ProgramError::InvalidArgument)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arch_program::{
+        account_data::DISCRIMINATOR_LEN,
+        entrypoint::ProgramResult,
+        message::Message,
+        program_stubs,
+        program_test::{ProgramTest, TestAccount},
+    };
+
+    /// Stands in for the token program; deposits only need the transfer to
+    /// succeed.
+    fn process_token_instruction(
+        _program_id: &Pubkey,
+        _accounts: &[AccountInfo],
+        _instruction_data: &[u8],
+    ) -> ProgramResult {
+        Ok(())
+    }
+
+    struct Setup {
+        program_test: ProgramTest,
+        program_id: Pubkey,
+        token_program_id: Pubkey,
+        pool: Pubkey,
+        pool_list: Pubkey,
+        user: Pubkey,
+        user_tokens: Pubkey,
+        pool_tokens: Pubkey,
+        mint: Pubkey,
+    }
+
+    fn setup(apy: u64) -> Setup {
+        let (program_id, token_program_id) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (pool, pool_list, user) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let (user_tokens, pool_tokens, mint) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+
+        let mut program_test = ProgramTest::new();
+        program_test.add_program(program_id, process_instruction);
+        program_test.add_program(token_program_id, process_token_instruction);
+
+        let pool_data = borsh::to_vec(&Pool {
+            pool_id: 1,
+            pool_pubkey: pool,
+            pool_name: "Pool".to_string(),
+            risk_type: RiskType::Low,
+            apy,
+            min_period: 0,
+            total_unit: 0,
+            tvl: 0,
+            base_value: 0,
+            investment_arm: 0,
+            cover_units: 0,
+            tcp: 0,
+            is_active: true,
+            asset_pubkey: mint,
+            asset_type: AssetType::BTC,
+        })
+        .unwrap();
+        let mut pool_list_data = PoolList::DISCRIMINATOR.to_vec();
+        PoolList {
+            pools: vec![1],
+            pool_id_to_pubkey: vec![(1, pool)],
+            admin_list: vec![],
+        }
+        .serialize(&mut pool_list_data)
+        .unwrap();
+        program_test.add_account(pool, TestAccount::new(program_id, pool_data));
+        program_test.add_account(pool_list, TestAccount::new(program_id, pool_list_data));
+        program_test.add_account(user, TestAccount::new(program_id, vec![]));
+        for pubkey in [user_tokens, pool_tokens, mint] {
+            program_test.add_account(pubkey, TestAccount::new(token_program_id, vec![]));
+        }
+        program_test.add_account(
+            token_program_id,
+            TestAccount {
+                is_executable: true,
+                ..TestAccount::new(Pubkey::system_program(), vec![])
+            },
+        );
+
+        Setup {
+            program_test,
+            program_id,
+            token_program_id,
+            pool,
+            pool_list,
+            user,
+            user_tokens,
+            pool_tokens,
+            mint,
+        }
+    }
+
+    fn deposit_message(setup: &Setup, amount: u64) -> Message {
+        let meta = |pubkey, is_signer, is_writable| AccountMeta {
+            pubkey,
+            is_signer,
+            is_writable,
+        };
+        let instruction = PoolInstruction::Deposit(DepositParam { pool_id: 1, amount })
+            .instruction(
+                setup.program_id,
+                vec![
+                    meta(setup.pool, false, true),
+                    meta(setup.pool_list, false, false),
+                    meta(setup.user, true, true),
+                    meta(setup.token_program_id, false, false),
+                    meta(setup.user_tokens, false, true),
+                    meta(setup.pool_tokens, false, true),
+                    meta(setup.mint, false, false),
+                    meta(setup.pool, false, true),
+                ],
+            );
+        Message {
+            signers: vec![setup.user],
+            instructions: vec![instruction],
+        }
+    }
+
+    fn user_deposit(setup: &Setup) -> Deposits {
+        let data = &setup.program_test.get_account(&setup.user).unwrap().data;
+        UserDepositList::try_from_slice(&data[DISCRIMINATOR_LEN..])
+            .unwrap()
+            .deposits[0]
+            .clone()
+    }
+
+    #[test]
+    fn test_top_up_settles_rewards_at_the_old_payout() {
+        let mut setup = setup(10);
+        let start = 1_700_000_000;
+        program_stubs::set_native_clock(Clock {
+            unix_timestamp: start,
+            ..Clock::default()
+        });
+
+        let message = deposit_message(&setup, 1_000_000);
+        setup.program_test.process_message(&message).unwrap();
+        let deposit = user_deposit(&setup);
+        assert_eq!(deposit.daily_payout, 273);
+        assert_eq!(deposit.start_date, start as u64);
+
+        program_stubs::advance_native_clock(3 * SECONDS_PER_DAY + SECONDS_PER_DAY / 2);
+        let message = deposit_message(&setup, 1_000_000);
+        setup.program_test.process_message(&message).unwrap();
+
+        // Three whole days at the payout of the first 1_000_000, then a new
+        // period at the payout of the whole 2_000_000 that keeps the half day
+        // already elapsed.
+        let deposit = user_deposit(&setup);
+        assert_eq!(deposit.deposited_amount, 2_000_000);
+        assert_eq!(deposit.reward, 3 * 273);
+        assert_eq!(deposit.daily_payout, 547);
+        assert_eq!(deposit.start_date, start as u64 + 3 * SECONDS_PER_DAY);
+
+        let pool_data = &setup.program_test.get_account(&setup.pool).unwrap().data;
+        assert_eq!(Pool::try_from_slice(pool_data).unwrap().tvl, 2_000_000);
+    }
+}
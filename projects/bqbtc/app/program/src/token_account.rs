@@ -1,4 +1,6 @@
-use arch_program::{account::AccountInfo, msg, program_error::ProgramError, pubkey::Pubkey};
+use arch_program::{
+    account::AccountInfo, accounts::Accounts, msg, program_error::ProgramError, pubkey::Pubkey,
+};
 use borsh::{BorshDeserialize, BorshSerialize};
 
 use crate::mint::TokenMintDetails;
@@ -66,19 +68,26 @@ impl TokenBalance {
     }
 }
 
+/// Accounts for [`initialize_balance_account`].
+#[derive(Accounts)]
+pub struct InitializeBalance<'a, 'b> {
+    #[account(signer)]
+    pub owner_account: &'a AccountInfo<'b>,
+    #[account(mut)]
+    pub mint_account: &'a AccountInfo<'b>,
+    #[account(mut)]
+    pub balance_account: &'a AccountInfo<'b>,
+}
+
 pub fn initialize_balance_account(
-    owner_account: &AccountInfo<'_>,
-    mint_account: &AccountInfo<'_>,
-    balance_account: &AccountInfo<'_>,
     program_id: &Pubkey,
+    accounts: &[AccountInfo],
 ) -> Result<(), ProgramError> {
-    if !owner_account.is_signer {
-        return Err(ProgramError::MissingRequiredSignature);
-    }
-
-    if !mint_account.is_writable {
-        return Err(ProgramError::Immutable);
-    }
+    let InitializeBalance {
+        owner_account,
+        mint_account,
+        balance_account,
+    } = InitializeBalance::try_accounts(program_id, accounts)?;
 
     if mint_account.owner != program_id {
         return Err(ProgramError::IllegalOwner);
@@ -1,7 +1,11 @@
-use arch_program::{account::AccountInfo, program_error::ProgramError, pubkey::Pubkey};
+use arch_program::{
+    account::AccountInfo, accounts::Accounts, program_error::ProgramError, pubkey::Pubkey,
+};
 use borsh::{BorshDeserialize, BorshSerialize};
 
-use crate::{mint::TokenMintDetails, token_account::TokenBalance};
+use crate::{
+    errors::FungibleTokenError, mint::TokenMintDetails, token_account::TokenBalance,
+};
 
 #[derive(Debug, Clone, BorshSerialize, BorshDeserialize)]
 pub struct TransferInput {
@@ -13,14 +17,30 @@ impl TransferInput {
     }
 }
 
+/// Accounts for [`transfer_tokens`].
+#[derive(Accounts)]
+pub struct TransferTokens<'a, 'b> {
+    #[account(signer)]
+    pub owner_account: &'a AccountInfo<'b>,
+    pub mint_account: &'a AccountInfo<'b>,
+    #[account(mut)]
+    pub sender_account: &'a AccountInfo<'b>,
+    #[account(mut)]
+    pub receiver_account: &'a AccountInfo<'b>,
+}
+
 pub fn transfer_tokens(
-    owner_account: &AccountInfo<'_>,
-    mint_account: &AccountInfo<'_>,
-    sender_account: &AccountInfo<'_>,
-    receiver_account: &AccountInfo<'_>,
     program_id: &Pubkey,
+    accounts: &[AccountInfo],
     transfer_input: TransferInput,
 ) -> Result<(), ProgramError> {
+    let TransferTokens {
+        owner_account,
+        mint_account,
+        sender_account,
+        receiver_account,
+    } = TransferTokens::try_accounts(program_id, accounts)?;
+
     let mut sender_token_balance_data = sender_account
         .data
         .try_borrow_mut()
@@ -30,15 +50,15 @@ pub fn transfer_tokens(
         .map_err(|_| ProgramError::InvalidAccountData)?;
 
     if sender_account.owner != program_id {
-        return Err(ProgramError::Custom(501));
+        return Err(FungibleTokenError::BalanceNotOwnedByProgram.into());
     };
 
     if sender_token_balance.mint_account != mint_account.key.serialize() {
-        return Err(ProgramError::Custom(503));
+        return Err(FungibleTokenError::BalanceMintMismatch.into());
     }
 
     if sender_token_balance.owner != owner_account.key.serialize() {
-        return Err(ProgramError::Custom(502));
+        return Err(FungibleTokenError::OwnerMismatch.into());
     }
 
     let mut receiver_token_balance_data = receiver_account
@@ -51,11 +71,11 @@ pub fn transfer_tokens(
             .map_err(|_| ProgramError::InvalidAccountData)?;
 
     if receiver_account.owner != program_id {
-        return Err(ProgramError::Custom(505));
+        return Err(FungibleTokenError::ReceiverNotOwnedByProgram.into());
     };
 
     if receiver_token_balance.mint_account != mint_account.key.serialize() {
-        return Err(ProgramError::Custom(506));
+        return Err(FungibleTokenError::ReceiverMintMismatch.into());
     }
 
     let mint_data = mint_account
@@ -67,11 +87,7 @@ pub fn transfer_tokens(
         .map_err(|_| ProgramError::InvalidAccountData)?;
 
     if mint_account.owner != program_id {
-        return Err(ProgramError::Custom(504));
-    }
-    
-    if !owner_account.is_signer {
-        return Err(ProgramError::MissingRequiredSignature);
+        return Err(FungibleTokenError::MintNotOwnedByProgram.into());
     }
 
     sender_token_balance.decrease_balance(transfer_input.amount, &mint_details)?;
@@ -1,4 +1,4 @@
-use arch_program::{account::AccountInfo, program::next_account_info, program_error::ProgramError, pubkey::Pubkey};
+use arch_program::{account::AccountInfo, accounts::Accounts, program_error::ProgramError, pubkey::Pubkey};
 use borsh::{BorshDeserialize, BorshSerialize};
 
 use crate::{initialize_mint, mint::InitializeMintInput};
@@ -23,19 +23,25 @@ pub struct AgentParam {
     decimals: u8,
 }
 
+/// Accounts for [`create_agent`].
+#[derive(Accounts)]
+pub struct CreateAgent<'a, 'b> {
+    #[account(mut)]
+    pub agent_account: &'a AccountInfo<'b>,
+    #[account(mut)]
+    pub agent_mint_account: &'a AccountInfo<'b>,
+}
+
 pub fn create_agent(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
-    instruction_data: &[u8],
+    agent_param: AgentParam,
 ) -> Result<(), ProgramError> {
-    let account_info_iter = &mut accounts.iter();
-    let agent_account = next_account_info(account_info_iter)?;
-    let agent_mint_account = next_account_info(account_info_iter)?;
+    let CreateAgent {
+        agent_account,
+        agent_mint_account,
+    } = CreateAgent::try_accounts(program_id, accounts)?;
 
-    let agent_param = match AgentParam::try_from_slice(instruction_data) {
-        Ok(agent_param) => agent_param,
-        Err(_) => return Err(ProgramError::InvalidInstructionData),
-    };
     let mint_input = InitializeMintInput::new(agent_param.owner, agent_param.supply, agent_param.ticker.clone(), agent_param.decimals);
     let token_mint = initialize_mint(agent_mint_account, program_id, mint_input)?;
 
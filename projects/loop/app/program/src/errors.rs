@@ -1,8 +1,29 @@
-use borsh::{BorshDeserialize, BorshSerialize};
+use arch_program::program_error::CustomError;
 
-#[derive(Debug, Clone, BorshSerialize, BorshDeserialize)]
+/// Errors returned by the token program as `ProgramError::Custom`.
+///
+/// Every code is pinned, and 501 to 506 are the codes the program returned
+/// before this enum was used: 501 and 503 still cover both a transfer's
+/// sender and the balance account being minted to. 502 used to mean a wrong
+/// account count and a finished mint as well as an owner mismatch; those two
+/// now have codes of their own, 507 and 508. An overspent balance or supply
+/// is still `ProgramError::InsufficientFunds`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, CustomError)]
 pub enum FungibleTokenError {
-    InsufficientBalance,
+    #[custom_error(code = 501, msg = "Balance account is not owned by the program")]
+    BalanceNotOwnedByProgram,
+    #[custom_error(code = 502, msg = "Balance account belongs to another owner")]
+    OwnerMismatch,
+    #[custom_error(code = 503, msg = "Balance account holds a different mint")]
+    BalanceMintMismatch,
+    #[custom_error(code = 504, msg = "Mint account is not owned by the program")]
+    MintNotOwnedByProgram,
+    #[custom_error(code = 505, msg = "Receiver balance account is not owned by the program")]
+    ReceiverNotOwnedByProgram,
+    #[custom_error(code = 506, msg = "Receiver balance account holds a different mint")]
+    ReceiverMintMismatch,
+    #[custom_error(code = 507, msg = "Wrong number of accounts for the instruction")]
+    IncorrectAccountCount,
+    #[custom_error(code = 508, msg = "The mint has reached its supply")]
     MintOver,
-    NotEnoughRemainingMintableTokens,
 }
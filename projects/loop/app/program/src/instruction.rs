@@ -0,0 +1,16 @@
+use arch_program::instruction::ProgramInstruction;
+
+use crate::{agent::AgentParam, mint::MintInput, transfer::TransferInput};
+
+/// Instructions accepted by the loop program, tagged by their first byte.
+#[derive(Debug, ProgramInstruction)]
+pub enum LoopInstruction {
+    /// Accounts: `[writable] agent`, `[writable] agent mint`
+    CreateAgent(AgentParam),
+    /// Accounts: `[signer] owner`, `[writable] mint`, `[writable] balance`
+    InitializeBalance,
+    /// Accounts: `[writable] mint`, `[writable] balance`, `[signer] owner`
+    Mint(MintInput),
+    /// Accounts: `[signer] owner`, `[] mint`, `[writable] sender`, `[writable] receiver`
+    Transfer(TransferInput),
+}
@@ -1,9 +1,11 @@
 use std::collections::HashMap;
 
-use arch_program::{account::AccountInfo, msg, program_error::ProgramError, pubkey::Pubkey};
+use arch_program::{
+    account::AccountInfo, accounts::Accounts, msg, program_error::ProgramError, pubkey::Pubkey,
+};
 use borsh::{BorshDeserialize, BorshSerialize};
 
-use crate::token_account::TokenBalance;
+use crate::{errors::FungibleTokenError, token_account::TokenBalance};
 
 #[derive(Debug, Clone, BorshSerialize, BorshDeserialize)]
 pub struct TokenMintDetails {
@@ -98,13 +100,29 @@ impl MintInput {
         MintInput { amount }
     }
 }
+
+/// Accounts for [`mint_tokens`].
+#[derive(Accounts)]
+pub struct MintTokens<'a, 'b> {
+    #[account(mut)]
+    pub mint_account: &'a AccountInfo<'b>,
+    #[account(mut)]
+    pub balance_account: &'a AccountInfo<'b>,
+    #[account(signer)]
+    pub owner_account: &'a AccountInfo<'b>,
+}
+
 pub fn mint_tokens(
-    balance_account: &AccountInfo<'_>,
-    mint_account: &AccountInfo<'_>,
-    owner_account: &AccountInfo<'_>,
     program_id: &Pubkey,
+    accounts: &[AccountInfo],
     mint_input: MintInput,
 ) -> Result<(), ProgramError> {
+    let MintTokens {
+        mint_account,
+        balance_account,
+        owner_account,
+    } = MintTokens::try_accounts(program_id, accounts)?;
+
     /* ------------------------- Balance account checks ------------------------- */
     let mut token_balance_data = balance_account
         .data
@@ -115,15 +133,15 @@ pub fn mint_tokens(
         .map_err(|_| ProgramError::InvalidAccountData)?;
 
     if balance_account.owner != program_id {
-        return Err(ProgramError::Custom(501));
+        return Err(FungibleTokenError::BalanceNotOwnedByProgram.into());
     };
 
     if token_balance.mint_account != mint_account.key.serialize() {
-        return Err(ProgramError::Custom(503));
+        return Err(FungibleTokenError::BalanceMintMismatch.into());
     }
 
     if token_balance.owner != owner_account.key.serialize() {
-        return Err(ProgramError::Custom(502));
+        return Err(FungibleTokenError::OwnerMismatch.into());
     }
 
     /* --------------------------- MINT ACCOUNT CHECKS -------------------------- */
@@ -137,16 +155,11 @@ pub fn mint_tokens(
         .map_err(|_| ProgramError::InvalidAccountData)?;
 
     if mint_account.owner != program_id {
-        return Err(ProgramError::Custom(504));
+        return Err(FungibleTokenError::MintNotOwnedByProgram.into());
     }
 
     if mint_details.status == MintStatus::Finished {
-        return Err(ProgramError::Custom(502));
-    }
-
-    /* -------------------------- OWNER ACCOUNT CHECKS -------------------------- */
-    if !owner_account.is_signer {
-        return Err(ProgramError::MissingRequiredSignature);
+        return Err(FungibleTokenError::MintOver.into());
     }
 
     /* -------------------------------- EXECUTION ------------------------------- */
@@ -1,14 +1,19 @@
 use arch_program::{
     account::{AccountInfo, AccountMeta},
-    clock::Clock,
+    account_data::{Account, AccountData},
+    accounts::Accounts,
+    clock::{Clock, SECONDS_PER_DAY},
     entrypoint,
-    instruction::Instruction,
+    instruction::{Instruction, ProgramInstruction},
+    math::{simple_interest, Rounding, Wad},
     msg,
-    program::{invoke, next_account_info},
+    program::{invoke, set_return_data_borsh},
     program_error::ProgramError,
     pubkey::Pubkey,
 };
 use borsh::{BorshDeserialize, BorshSerialize};
+use instruction::PoolInstruction;
+pub mod instruction;
 
 #[derive(BorshSerialize, BorshDeserialize, Debug)]
 pub struct Pool {
@@ -52,13 +57,13 @@ impl TransferInput {
 
 #[derive(BorshSerialize, BorshDeserialize, Debug, PartialEq, Eq)]
 pub struct DepositParam {
-    pool_id: u64,
-    amount: u64,
+    pub pool_id: u64,
+    pub amount: u64,
 }
 
 #[derive(BorshSerialize, BorshDeserialize, Debug, PartialEq, Eq)]
 pub struct QueryParam {
-    pool_id: u64,
+    pub pool_id: u64,
 }
 
 #[derive(BorshSerialize, BorshDeserialize, Debug, PartialEq, Eq)]
@@ -121,70 +126,73 @@ pub struct PoolList {
     pub admin_list: Vec<Pubkey>,
 }
 
+impl AccountData for PoolList {
+    const DISCRIMINATOR: [u8; 8] = *b"poollist";
+}
+
 #[derive(BorshSerialize, BorshDeserialize)]
 pub struct UserDepositList {
     pub deposits: Vec<Deposits>,
 }
 
+impl AccountData for UserDepositList {
+    const DISCRIMINATOR: [u8; 8] = *b"deposits";
+}
+
 entrypoint!(process_instruction);
 fn process_instruction(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
     instruction_data: &[u8],
 ) -> Result<(), ProgramError> {
-    if instruction_data.is_empty() {
-        return Err(ProgramError::InvalidInstructionData);
+    match PoolInstruction::unpack(instruction_data)? {
+        PoolInstruction::CreatePool(pool_param) => create_pool(program_id, accounts, pool_param),
+        PoolInstruction::Deposit(deposit_param) => deposit(program_id, accounts, deposit_param),
+        PoolInstruction::Withdraw(query_param) => withdraw(program_id, accounts, query_param),
+        PoolInstruction::GetUserDeposit(query_param) => {
+            set_return_data_borsh(&get_user_deposit(program_id, accounts, query_param)?)
+        }
+        PoolInstruction::GetAllPools => {
+            set_return_data_borsh(&get_all_pools(program_id, accounts)?)
+        }
+        PoolInstruction::GetPoolById(pool_id) => {
+            set_return_data_borsh(&get_pool_by_id(program_id, accounts, pool_id)?)
+        }
+        PoolInstruction::GetPoolTvl(pool_id) => {
+            set_return_data_borsh(&get_pool_tvl(program_id, accounts, pool_id)?)
+        }
     }
+}
 
-    match instruction_data[0] {
-        0 => create_pool(program_id, accounts, &instruction_data[1..]),
-        1 => deposit(program_id, accounts, &instruction_data[1..]),
-        2 => withdraw(program_id, accounts, &instruction_data[1..]),
-        3 => get_user_deposit(accounts, &instruction_data[1..]),
-        4 => get_all_pools(program_id, accounts),
-        5 => get_pool_by_id(accounts, &instruction_data[1..]),
-        6 => get_pool_tvl(accounts, &instruction_data[1..])
-        _ => Err(ProgramError::InvalidInstructionData),
-    }
+/// Accounts for [`create_pool`]. The pool and the pool list are written, so
+/// both must belong to the program.
+#[derive(Accounts)]
+pub struct CreatePool<'a, 'b> {
+    #[account(mut, owner = program_id)]
+    pub pool_account: &'a AccountInfo<'b>,
+    #[account(signer)]
+    pub owner_account: &'a AccountInfo<'b>,
+    #[account(mut)]
+    pub pool_list_account: Account<'a, 'b, PoolList>,
 }
 
 pub fn create_pool(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
-    instruction_data: &[u8],
+    pool_param: PoolParam,
 ) -> Result<(), ProgramError> {
-    let account_iter = &mut accounts.iter();
-
-    let pool_account = next_account_info(account_iter)?;
-    let owner_account = next_account_info(account_iter)?;
-    let pool_list_account = next_account_info(account_iter)?;
-
-    let mut pool_list = match PoolList::try_from_slice(&pool_list_account.data.borrow()) {
-        Ok(list) => list,
-        Err(_) => PoolList {
-            pools: Vec::new(),
-            pool_id_to_pubkey: Vec::new(),
-            admin_list: vec![],
-        },
-    };
+    let CreatePool {
+        pool_account,
+        owner_account,
+        pool_list_account,
+    } = CreatePool::try_accounts(program_id, accounts)?;
 
-    let pool_param = match PoolParam::try_from_slice(instruction_data) {
-        Ok(list) => list,
-        Err(_) => return Err(ProgramError::InvalidInstructionData),
-    };
-
-    // if pool_account.owner != program_id {
-    //     return Err(ProgramError::IncorrectProgramId);
-    // }
+    let mut pool_list = pool_list_account.load_mut()?;
 
     if !pool_list.admin_list.contains(owner_account.key) {
         return Err(ProgramError::MissingRequiredSignature);
     }
 
-    if !owner_account.is_signer {
-        return Err(ProgramError::MissingRequiredSignature);
-    }
-
     let asset_type = AssetType::from_u8(pool_param.asset_type)?;
     let risk_type = RiskType::from_u8(pool_param.risk_type)?;
     let pool_id = pool_list.pools.len() as u64 + 1;
@@ -212,9 +220,7 @@ pub fn create_pool(
         .pool_id_to_pubkey
         .push((pool_id, *pool_account.key));
 
-    pool_list
-        .serialize(&mut &mut pool_list_account.data.borrow_mut()[..])
-        .map_err(|e| ProgramError::BorshIoError(e.to_string()))?;
+    pool_list_account.store(&pool_list)?;
     pool.serialize(&mut &mut pool_account.data.borrow_mut()[..])
         .map_err(|e| ProgramError::BorshIoError(e.to_string()))?;
 
@@ -227,51 +233,54 @@ pub fn create_pool(
     Ok(())
 }
 
+/// Accounts for [`deposit`]. The user's deposits are written, so the user
+/// account must belong to the program as well as sign.
+#[derive(Accounts)]
+pub struct Deposit<'a, 'b> {
+    #[account(mut, owner = program_id)]
+    pub pool_account: &'a AccountInfo<'b>,
+    pub pool_list_account: Account<'a, 'b, PoolList>,
+    #[account(mut, signer)]
+    pub user_account: Account<'a, 'b, UserDepositList>,
+    pub token_program: &'a AccountInfo<'b>,
+    #[account(mut)]
+    pub user_token_account: &'a AccountInfo<'b>,
+    #[account(mut)]
+    pub pool_token_account: &'a AccountInfo<'b>,
+    pub token_mint: &'a AccountInfo<'b>,
+    /// The pool listed under the deposit's pool id.
+    pub listed_pool_account: &'a AccountInfo<'b>,
+}
+
 pub fn deposit(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
-    instruction_data: &[u8],
+    deposit_param: DepositParam,
 ) -> Result<(), ProgramError> {
-    let account_iter = &mut accounts.iter();
-
-    let pool_account = next_account_info(account_iter)?;
-    let pool_list_account = next_account_info(account_iter)?;
-    let user_account = next_account_info(account_iter)?;
-    let token_program = next_account_info(account_iter)?;
-    let user_token_account = next_account_info(account_iter)?;
-    let pool_token_account = next_account_info(account_iter)?;
-    let token_mint = next_account_info(account_iter)?;
-
-    if pool_account.owner != program_id {
-        return Err(ProgramError::IncorrectProgramId);
-    }
-
-    let deposit_param = match DepositParam::try_from_slice(instruction_data) {
-        Ok(list) => list,
-        Err(_) => return Err(ProgramError::InvalidInstructionData),
-    };
-
-    let mut user_deposit_list = match UserDepositList::try_from_slice(&user_account.data.borrow()) {
-        Ok(list) => list,
-        Err(_) => UserDepositList {
+    let Deposit {
+        pool_account,
+        pool_list_account,
+        user_account,
+        token_program,
+        user_token_account,
+        pool_token_account,
+        token_mint,
+        listed_pool_account,
+    } = Deposit::try_accounts(program_id, accounts)?;
+
+    let mut user_deposit_list = if user_account.is_initialized()? {
+        user_account.load_mut()?
+    } else {
+        UserDepositList {
             deposits: Vec::new(),
-        },
+        }
     };
 
-    if !user_account.is_signer {
-        return Err(ProgramError::MissingRequiredSignature);
-    }
-
-    if instruction_data.len() < 8 {
-        return Err(ProgramError::InvalidInstructionData);
-    }
-
-    let pool_list = PoolList::try_from_slice(&pool_list_account.data.borrow())
-        .map_err(|_| ProgramError::InvalidAccountData)?;
+    let pool_list = pool_list_account.load()?;
     let deposit_amount = deposit_param.amount;
 
     let transfer_accounts = &[
-        user_account.clone(),
+        user_account.info().clone(),
         token_mint.clone(),
         user_token_account.clone(),
         pool_token_account.clone(),
@@ -284,14 +293,12 @@ pub fn deposit(
         .map(|(_, pubkey)| pubkey);
 
     let pool = if let Some(pool_pubkey) = pool_pubkey {
-        let pool_account = account_iter
-            .find(|account| account.key == pool_pubkey)
-            .ok_or(ProgramError::InvalidAccountData)?;
-
-        let pool = Pool::try_from_slice(&pool_account.data.borrow())
-            .map_err(|_| ProgramError::InvalidAccountData)?;
+        if listed_pool_account.key != pool_pubkey {
+            return Err(ProgramError::InvalidAccountData);
+        }
 
-        pool
+        Pool::try_from_slice(&listed_pool_account.data.borrow())
+            .map_err(|_| ProgramError::InvalidAccountData)?
     } else {
         return Err(ProgramError::InvalidArgument);
     };
@@ -308,7 +315,7 @@ pub fn deposit(
         program_id: *token_program.key,
         accounts: vec![
             AccountMeta {
-                pubkey: *user_account.key,
+                pubkey: *user_account.key(),
                 is_signer: true,   // This account needs to sign the transaction
                 is_writable: true, // This account's data will be modified
             },
@@ -336,9 +343,8 @@ pub fn deposit(
     let mut pool: Pool = Pool::try_from_slice(&pool_account.data.borrow())
         .map_err(|e| ProgramError::BorshIoError(e.to_string()))?;
 
-    let apy = pool.apy;
-    let days_in_year = 365;
-    let daily_payout = (deposit_amount * apy / 100) / days_in_year;
+    let apy = Wad::from_percent(pool.apy);
+    let daily_payout = simple_interest(deposit_amount, apy, SECONDS_PER_DAY, Rounding::Down)?;
     if let Some(deposit) = user_deposit_list
         .deposits
         .iter_mut()
@@ -348,17 +354,22 @@ pub fn deposit(
             .deposited_amount
             .checked_add(deposit_amount)
             .ok_or(ProgramError::InvalidAccountData)?;
-        deposit.daily_payout = (deposit.deposited_amount * apy / 100) / days_in_year;
-        deposit.start_date = Clock::default().unix_timestamp as u64;
+        deposit.daily_payout = simple_interest(
+            deposit.deposited_amount,
+            apy,
+            SECONDS_PER_DAY,
+            Rounding::Down,
+        )?;
+        deposit.start_date = Clock::get()?.unix_timestamp as u64;
     } else {
         user_deposit_list.deposits.push(Deposits {
             pool_id: deposit_param.pool_id,
-            user_pubkey: *user_account.key,
+            user_pubkey: *user_account.key(),
             pool_pubkey: *pool_account.key,
             deposited_amount: deposit_amount,
             status: DepositStatus::Active,
             daily_payout,
-            start_date: Clock::default().unix_timestamp as u64,
+            start_date: Clock::get()?.unix_timestamp as u64,
         });
     }
 
@@ -374,6 +385,7 @@ pub fn deposit(
 
     pool.serialize(&mut &mut pool_account.data.borrow_mut()[..])
         .map_err(|e| ProgramError::BorshIoError(e.to_string()))?;
+    user_account.store(&user_deposit_list)?;
 
     msg!(
         "Deposit successful. Amount: {}, New TVL: {}",
@@ -383,41 +395,39 @@ pub fn deposit(
     Ok(())
 }
 
+/// Accounts for [`withdraw`].
+#[derive(Accounts)]
+pub struct Withdraw<'a, 'b> {
+    #[account(mut, owner = program_id)]
+    pub pool_account: &'a AccountInfo<'b>,
+    #[account(mut, signer)]
+    pub user_account: Account<'a, 'b, UserDepositList>,
+    pub token_program: &'a AccountInfo<'b>,
+    #[account(mut)]
+    pub pool_token_account: &'a AccountInfo<'b>,
+    #[account(mut)]
+    pub user_token_account: &'a AccountInfo<'b>,
+    pub token_mint: &'a AccountInfo<'b>,
+}
+
 pub fn withdraw(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
-    instruction_data: &[u8],
+    withdraw_param: QueryParam,
 ) -> Result<(), ProgramError> {
-    let account_iter = &mut accounts.iter();
-
-    let pool_account = next_account_info(account_iter)?;
-    let user_account = next_account_info(account_iter)?;
-    let token_program = next_account_info(account_iter)?;
-    let pool_token_account = next_account_info(account_iter)?;
-    let user_token_account = next_account_info(account_iter)?;
-    let token_mint = next_account_info(account_iter)?;
+    let Withdraw {
+        pool_account,
+        user_account,
+        token_program,
+        pool_token_account,
+        user_token_account,
+        token_mint,
+    } = Withdraw::try_accounts(program_id, accounts)?;
 
-    if pool_account.owner != program_id {
-        return Err(ProgramError::IncorrectProgramId);
-    }
-
-    if !user_account.is_signer {
-        return Err(ProgramError::MissingRequiredSignature);
-    }
-
-    let withdraw_param = match QueryParam::try_from_slice(instruction_data) {
-        Ok(list) => list,
-        Err(_) => return Err(ProgramError::InvalidInstructionData),
-    };
     let mut pool: Pool = Pool::try_from_slice(&pool_account.data.borrow())
         .map_err(|e| ProgramError::BorshIoError(e.to_string()))?;
 
-    let mut user_deposit_list = match UserDepositList::try_from_slice(&user_account.data.borrow()) {
-        Ok(list) => list,
-        Err(_) => UserDepositList {
-            deposits: Vec::new(),
-        },
-    };
+    let mut user_deposit_list = user_account.load_mut()?;
 
     let user_deposit = if let Some(deposit) = user_deposit_list
         .deposits
@@ -429,16 +439,11 @@ pub fn withdraw(
         return Err(ProgramError::InvalidAccountData);
     };
 
-    let clock = Clock::default();
-    let current_time = clock.unix_timestamp as u64;
-    if current_time < user_deposit.start_date + pool.min_period {
+    let clock = Clock::get()?;
+    if clock.elapsed_seconds(user_deposit.start_date)? < pool.min_period {
         return Err(ProgramError::Custom(1));
     }
 
-    if instruction_data.len() < 8 {
-        return Err(ProgramError::InvalidInstructionData);
-    }
-
     let withdraw_amount = user_deposit.deposited_amount;
 
     if withdraw_amount > pool.tvl {
@@ -506,6 +511,7 @@ pub fn withdraw(
 
     pool.serialize(&mut &mut pool_account.data.borrow_mut()[..])
         .map_err(|e| ProgramError::BorshIoError(e.to_string()))?;
+    user_account.store(&user_deposit_list)?;
 
     msg!(
         "Withdraw successful. Amount: {}, Remaining TVL: {}",
@@ -515,51 +521,40 @@ pub fn withdraw(
     Ok(())
 }
 
+/// Accounts for [`get_user_deposit`].
+#[derive(Accounts)]
+pub struct GetUserDeposit<'a, 'b> {
+    pub user_account: Account<'a, 'b, UserDepositList>,
+}
+
 pub fn get_user_deposit(
+    program_id: &Pubkey,
     accounts: &[AccountInfo],
-    instruction_data: &[u8],
-) -> Result<(), ProgramError> {
-    let account_iter = &mut accounts.iter();
-    let user_account = next_account_info(account_iter)?;
+    query_param: QueryParam,
+) -> Result<Deposits, ProgramError> {
+    let GetUserDeposit { user_account } = GetUserDeposit::try_accounts(program_id, accounts)?;
 
-    let query_param = match QueryParam::try_from_slice(instruction_data) {
-        Ok(list) => list,
-        Err(_) => return Err(ProgramError::InvalidInstructionData),
-    };
+    let user_deposit_list = user_account.load()?;
 
-    let user_deposit_list = match UserDepositList::try_from_slice(&user_account.data.borrow()) {
-        Ok(list) => list,
-        Err(_) => UserDepositList {
-            deposits: Vec::new(),
-        },
-    };
-
-    let user_deposit = if let Some(deposit) = user_deposit_list
+    user_deposit_list
         .deposits
-        .iter()
+        .into_iter()
         .find(|d| d.pool_id == query_param.pool_id)
-    {
-        deposit
-    } else {
-        return Err(ProgramError::InvalidAccountData);
-    };
-
-    msg!("User Deposit: {:?}", user_deposit);
-
-    Ok(())
+        .ok_or(ProgramError::InvalidAccountData)
 }
 
-pub fn get_all_pools(program_id: &Pubkey, accounts: &[AccountInfo]) -> Result<(), ProgramError> {
-    let account_iter = &mut accounts.iter();
-
-    let pool_list_account = next_account_info(account_iter)?;
-
-    if pool_list_account.owner != program_id {
-        return Err(ProgramError::IncorrectProgramId);
-    }
+/// Accounts for [`get_all_pools`]. The listed pools follow the pool list.
+#[derive(Accounts)]
+pub struct GetAllPools<'a, 'b> {
+    pub pool_list_account: Account<'a, 'b, PoolList>,
+}
 
-    let pool_list: PoolList = PoolList::try_from_slice(&pool_list_account.data.borrow())
-        .map_err(|e| ProgramError::BorshIoError(e.to_string()))?;
+pub fn get_all_pools(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> Result<Vec<Pool>, ProgramError> {
+    let GetAllPools { pool_list_account } = GetAllPools::try_accounts(program_id, accounts)?;
+    let pool_list = pool_list_account.load()?;
 
     let mut pools: Vec<Pool> = Vec::new();
 
@@ -584,25 +579,26 @@ pub fn get_all_pools(program_id: &Pubkey, accounts: &[AccountInfo]) -> Result<()
         }
     }
 
-    msg!("Pools: {:?}", pools);
+    Ok(pools)
+}
 
-    Ok(())
+/// Accounts for [`get_pool_by_id`] and [`get_pool_tvl`].
+#[derive(Accounts)]
+pub struct GetPool<'a, 'b> {
+    pub pool_list_account: Account<'a, 'b, PoolList>,
+    pub pool_account: &'a AccountInfo<'b>,
 }
 
 pub fn get_pool_by_id(
+    program_id: &Pubkey,
     accounts: &[AccountInfo],
-    instruction_data: &[u8],
+    pool_id: u64,
 ) -> Result<Pool, ProgramError> {
-    let account_iter = &mut accounts.iter();
-
-    let pool_id = match u64::try_from_slice(instruction_data) {
-        Ok(list) => list,
-        Err(_) => return Err(ProgramError::InvalidInstructionData),
-    };
-
-    let pool_list_account = next_account_info(account_iter)?;
-    let pool_list = PoolList::try_from_slice(&pool_list_account.data.borrow())
-        .map_err(|_| ProgramError::InvalidAccountData)?;
+    let GetPool {
+        pool_list_account,
+        pool_account,
+    } = GetPool::try_accounts(program_id, accounts)?;
+    let pool_list = pool_list_account.load()?;
 
     let pool_pubkey = pool_list
         .pool_id_to_pubkey
@@ -611,33 +607,29 @@ pub fn get_pool_by_id(
         .map(|(_, pubkey)| pubkey);
 
     if let Some(pool_pubkey) = pool_pubkey {
-        let pool_account = account_iter
-            .find(|account| account.key == pool_pubkey)
-            .ok_or(ProgramError::InvalidAccountData)?;
+        if pool_account.key != pool_pubkey {
+            return Err(ProgramError::InvalidAccountData);
+        }
 
         let pool = Pool::try_from_slice(&pool_account.data.borrow())
             .map_err(|_| ProgramError::InvalidAccountData)?;
 
-        msg!("Pool: {:?}", pool);
-        Ok(())
+        Ok(pool)
     } else {
         Err(ProgramError::InvalidArgument)
     }
 }
 
 pub fn get_pool_tvl(
+    program_id: &Pubkey,
     accounts: &[AccountInfo],
-    instruction_data: &[u8],
+    pool_id: u64,
 ) -> Result<u64, ProgramError> {
-    let account_iter = &mut accounts.iter();
-    let pool_list_account = next_account_info(account_iter)?;
-    let pool_list = PoolList::try_from_slice(&pool_list_account.data.borrow())
-        .map_err(|_| ProgramError::InvalidAccountData)?;
-
-    let pool_id = match u64::try_from_slice(instruction_data) {
-        Ok(list) => list,
-        Err(_) => return Err(ProgramError::InvalidInstructionData),
-    };
+    let GetPool {
+        pool_list_account,
+        pool_account,
+    } = GetPool::try_accounts(program_id, accounts)?;
+    let pool_list = pool_list_account.load()?;
 
     let pool_pubkey = pool_list
         .pool_id_to_pubkey
@@ -646,16 +638,62 @@ pub fn get_pool_tvl(
         .map(|(_, pubkey)| pubkey);
 
     if let Some(pool_pubkey) = pool_pubkey {
-        let pool_account = account_iter
-            .find(|account| account.key == pool_pubkey)
-            .ok_or(ProgramError::InvalidAccountData)?;
+        if pool_account.key != pool_pubkey {
+            return Err(ProgramError::InvalidAccountData);
+        }
 
         let pool = Pool::try_from_slice(&pool_account.data.borrow())
             .map_err(|_| ProgramError::InvalidAccountData)?;
 
-        msg!("Pool TVL: {}", pool.tvl);
-        Ok(())
+        Ok(pool.tvl)
     } else {
         Err(ProgramError::InvalidArgument)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arch_program::utxo::UtxoMeta;
+
+    fn program_id() -> Pubkey {
+        Pubkey::from([4; 32])
+    }
+
+    /// Parse `create_pool`'s accounts with the pool account owned by
+    /// `pool_owner`.
+    fn try_create_pool_accounts(pool_owner: Pubkey) -> Result<(), ProgramError> {
+        let keys = [Pubkey::from([1; 32]), Pubkey::from([2; 32]), Pubkey::from([3; 32])];
+        let owners = [pool_owner, Pubkey::system_program(), program_id()];
+        let utxo = UtxoMeta::from([0; 32], 0);
+        let mut data = [vec![], vec![], vec![]];
+        let infos: Vec<AccountInfo> = data
+            .iter_mut()
+            .zip(keys.iter().zip(owners.iter()))
+            .enumerate()
+            .map(|(index, (data, (key, owner)))| {
+                let is_owner_account = index == 1;
+                AccountInfo::new(
+                    key,
+                    data,
+                    owner,
+                    &utxo,
+                    is_owner_account,
+                    !is_owner_account,
+                    false,
+                )
+            })
+            .collect();
+        let program_id = program_id();
+        CreatePool::try_accounts(&program_id, &infos).map(|_| ())
+    }
+
+    #[test]
+    fn test_create_pool_checks_pool_owner() {
+        assert_eq!(try_create_pool_accounts(program_id()), Ok(()));
+        assert_eq!(
+            try_create_pool_accounts(Pubkey::system_program()),
+            Err(ProgramError::InvalidAccountOwner)
+        );
+    }
+}
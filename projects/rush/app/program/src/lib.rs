@@ -80,16 +80,15 @@ pub fn start(
     //     user.spins = 10;
     // }
 
-    let clock = Clock::default();
-    let current_time = clock.unix_timestamp as u64;
+    let clock = Clock::get()?;
 
     if user.last_spin_time > 0 {
-        let diff = current_time - user.last_spin_time;
+        let diff = clock.elapsed_seconds(user.last_spin_time)?;
 
         if diff > TEN_HOURS_IN_SECONDS {
             user.spins = 10;
         } else {
-            user.spins += time_diff / ONE_HOUR;
+            user.spins += diff / SECONDS_IN_ONE_HOUR;
             if user.spins > 10 {
                 user.spins = 10;
             }
@@ -114,7 +113,7 @@ pub fn end_game(
         Err(_) => return Err(ProgramError::InvalidAccountData),
     };
 
-    let clock = Clock::default();
+    let clock = Clock::get()?;
     user.last_spin_time = clock.unix_timestamp as u64;
 
     user.serialize(&mut &mut user_account.data.borrow_mut()[..])